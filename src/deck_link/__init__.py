@@ -3,5 +3,6 @@
 __version__ = "0.1.0"
 
 PORT = 52525
+PROTOCOL_VERSION = "1.0"
 SERVICE_TYPE = "_decklink._tcp.local."
 SERVICE_NAME = "DeckLink"
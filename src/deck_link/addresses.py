@@ -0,0 +1,142 @@
+"""IP addresses and hostnames, IPv4 and IPv6 alike.
+
+Hosts are normalized where they come in (requests, discovery, sockets), so
+the rest of the sidecar can compare them as strings: IPv6 compressed and
+without brackets, IPv4-mapped IPv6 as plain IPv4, and link-local IPv6 with
+the zone (``%eth0``) it is only reachable through.
+"""
+
+import ipaddress
+import logging
+import re
+import socket
+from typing import Any, Optional, Sequence, Union
+
+logger = logging.getLogger(__name__)
+
+IPAddress = Union[ipaddress.IPv4Address, ipaddress.IPv6Address]
+
+_HOST_LABEL = re.compile(r"^(?!-)[A-Za-z0-9-]{1,63}(?<!-)$")
+
+# Destinations used only to ask the OS which source address it would route
+# from; nothing is sent to them
+_ROUTE_PROBES = {
+    socket.AF_INET: ("8.8.8.8", 80),
+    socket.AF_INET6: ("2001:4860:4860::8888", 80),
+}
+
+
+def parse_ip(host: str) -> Optional[IPAddress]:
+    """host as an IP address, brackets and zone allowed, or None if it isn't one."""
+    host = host.strip()
+    if host.startswith("[") and host.endswith("]"):
+        host = host[1:-1]
+    try:
+        ip = ipaddress.ip_address(host)
+    except ValueError:
+        return None
+    if isinstance(ip, ipaddress.IPv6Address):
+        if ip.ipv4_mapped:
+            return ip.ipv4_mapped
+        if ip.scope_id and not ip.is_link_local:
+            # A zone only means something on a link-local address
+            return ipaddress.IPv6Address(host.split("%", 1)[0])
+    return ip
+
+
+def normalize_host(host: str) -> str:
+    """host in canonical form; raises ValueError if it's neither an IP address nor a hostname."""
+    ip = parse_ip(host)
+    if ip is not None:
+        return str(ip)
+    name = host.strip().rstrip(".")
+    if not name or len(name) > 253 or not all(_HOST_LABEL.match(label) for label in name.split(".")):
+        raise ValueError(f"invalid host {host!r}")
+    return name.lower()
+
+
+def ws_uri(host: str, port: int) -> str:
+    """WebSocket URI for host, bracketing IPv6; the zone is left out, see connect_kwargs."""
+    ip = parse_ip(host)
+    if isinstance(ip, ipaddress.IPv6Address):
+        return f"ws://[{str(ip).split('%', 1)[0]}]:{port}"
+    return f"ws://{host}:{port}"
+
+
+def connect_kwargs(host: str, bind: Sequence[str] = ()) -> dict[str, Any]:
+    """Extra arguments for websockets' connect.
+
+    A zoned address is dialled with its zone, and when bound to addresses
+    the connection leaves from one of the same family. A hostname's family
+    isn't known until it resolves, so it is dialled unbound.
+    """
+    kwargs: dict[str, Any] = {}
+    ip = parse_ip(host)
+    if isinstance(ip, ipaddress.IPv6Address) and ip.scope_id:
+        kwargs["host"] = str(ip)
+    for address in bind:
+        source = parse_ip(address)
+        if ip is not None and source is not None and source.version == ip.version:
+            kwargs["local_addr"] = (address, 0)
+            break
+    return kwargs
+
+
+def sort_key(address: str) -> tuple[int, int, int, str]:
+    """IPv4 before IPv6, routable before link-local, then by address."""
+    ip = parse_ip(address)
+    if ip is None:
+        return (2, 0, 0, address)
+    return (0 if ip.version == 4 else 1, int(ip.is_link_local), int(ip), address)
+
+
+def _route_source(family: socket.AddressFamily) -> Optional[str]:
+    """The address the OS would send from to reach the internet, if any."""
+    try:
+        with socket.socket(family, socket.SOCK_DGRAM) as s:
+            s.connect(_ROUTE_PROBES[family])
+            return str(s.getsockname()[0])
+    except OSError:
+        return None
+
+
+def primary_ip() -> str:
+    """The address peers should try first; loopback when offline."""
+    return _route_source(socket.AF_INET) or _route_source(socket.AF_INET6) or "127.0.0.1"
+
+
+def local_addresses() -> list[dict[str, Any]]:
+    """Every non-loopback address of this device, the primary first.
+
+    The rest follow sort_key, so the list is the same from one call to the
+    next. Fine-grained interface listing needs a platform dependency; this
+    takes what the hostname resolves to plus the routed source addresses.
+    """
+    found: set[str] = set()
+    for family in (socket.AF_INET, socket.AF_INET6):
+        source = _route_source(family)
+        if source:
+            found.add(source)
+    try:
+        for info in socket.getaddrinfo(socket.gethostname(), None, proto=socket.IPPROTO_TCP):
+            found.add(str(info[4][0]))
+    except OSError as e:
+        logger.debug(f"Cannot resolve own hostname: {e}")
+
+    addresses: dict[str, IPAddress] = {}
+    for address in found:
+        ip = parse_ip(address)
+        if ip is not None and not ip.is_loopback and not ip.is_unspecified:
+            addresses[str(ip)] = ip
+
+    primary = primary_ip()
+    ordered = sorted(addresses, key=lambda address: (address != primary, sort_key(address)))
+    return [
+        {
+            "ip": address,
+            "family": addresses[address].version,
+            "primary": index == 0,
+            "link_local": addresses[address].is_link_local,
+        }
+        for index, address in enumerate(ordered)
+    ]
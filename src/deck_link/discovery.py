@@ -3,14 +3,23 @@
 import asyncio
 import socket
 import logging
+import time
 from dataclasses import dataclass, field
-from typing import Callable, Optional
+from typing import Any, Callable, Optional
 from zeroconf import Zeroconf, ServiceInfo, ServiceBrowser, ServiceListener  # type: ignore
 
 from . import PORT, SERVICE_TYPE, SERVICE_NAME
+from .addresses import local_addresses, parse_ip, primary_ip, sort_key
 
 logger = logging.getLogger(__name__)
 
+# How often peers we know of are confirmed and reported again, so the UI
+# can expire ones that vanished without saying goodbye
+HEARTBEAT_INTERVAL = 20.0
+
+# How long a confirmation may take, in milliseconds
+HEARTBEAT_TIMEOUT_MS = 2000
+
 
 @dataclass
 class DiscoveredPeer:
@@ -65,14 +74,21 @@ class PeerDiscoveryListener(ServiceListener):
             if self.on_peer_lost:
                 self.on_peer_lost(name)
 
+    def clear(self) -> None:
+        """Forget every peer, reporting each as lost."""
+        for name in list(self.peers):
+            del self.peers[name]
+            if self.on_peer_lost:
+                self.on_peer_lost(name)
+
     def _handle_service_info(self, name: str, info: ServiceInfo) -> None:
         """Process discovered service info."""
         addresses = []
-        for addr in info.addresses:
-            try:
-                addresses.append(socket.inet_ntoa(addr))
-            except Exception:
-                pass
+        for addr in info.parsed_scoped_addresses():
+            ip = parse_ip(addr)
+            if ip is not None and str(ip) not in addresses:
+                addresses.append(str(ip))
+        addresses.sort(key=sort_key)
 
         properties = {}
         for k, v in info.properties.items():
@@ -88,8 +104,10 @@ class PeerDiscoveryListener(ServiceListener):
             properties=properties,
         )
 
+        # Heartbeats re-report known peers; only log the first sighting
+        if name not in self.peers:
+            logger.info(f"Peer found: {peer.display_name} at {peer.host}:{peer.port}")
         self.peers[name] = peer
-        logger.info(f"Peer found: {peer.display_name} at {peer.host}:{peer.port}")
 
         if self.on_peer_found:
             self.on_peer_found(peer)
@@ -109,29 +127,39 @@ class Discovery:
         port: int = PORT,
         on_peer_found: Optional[Callable[[DiscoveredPeer], None]] = None,
         on_peer_lost: Optional[Callable[[str], None]] = None,
+        on_status: Optional[Callable[[dict[str, Any]], None]] = None,
+        interface: Optional[str] = None,
+        bind_addresses: Optional[list[str]] = None,
     ):
         self.device_name = device_name
         self.device_type = device_type
         self.port = port
+        # Interface we're limited to and its addresses; None and empty for all
+        self.interface = interface
+        self.bind_addresses = bind_addresses or []
         self.on_peer_found = on_peer_found
         self.on_peer_lost = on_peer_lost
+        # Called with get_status() whenever it changes, from whichever
+        # thread changed it
+        self.on_status = on_status
 
         self._zeroconf: Optional[Zeroconf] = None
         self._browser: Optional[ServiceBrowser] = None
         self._service_info: Optional[ServiceInfo] = None
         self._listener: Optional[PeerDiscoveryListener] = None
         self._running = False
-
-    def _get_local_ip(self) -> str:
-        """Get the local IP address."""
-        try:
-            s = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
-            s.connect(("8.8.8.8", 80))
-            ip = s.getsockname()[0]
-            s.close()
-            return ip
-        except Exception:
-            return "127.0.0.1"
+        # Unix time (ms) the current browse started
+        self._last_scan_at: Optional[int] = None
+        # Why discovery isn't working, e.g. multicast blocked
+        self._error: Optional[str] = None
+        self._heartbeat: Optional[asyncio.Task[None]] = None
+
+    def _get_local_ips(self) -> list[str]:
+        """Every local address to advertise, the primary first."""
+        if self.bind_addresses:
+            return sorted(self.bind_addresses, key=sort_key)
+        addresses = [address["ip"] for address in local_addresses()]
+        return addresses or [primary_ip()]
 
     async def start(self) -> None:
         """Start advertising this service and browsing for peers."""
@@ -141,30 +169,28 @@ class Discovery:
         # Run zeroconf setup in thread pool to avoid blocking
         loop = asyncio.get_event_loop()
         await loop.run_in_executor(None, self._start_sync)
+        if self._running:
+            self._heartbeat = asyncio.create_task(self._run_heartbeat())
 
     def _start_sync(self) -> None:
         """Synchronous startup of zeroconf."""
-        self._zeroconf = Zeroconf()
-
-        # Create service info for advertising
-        local_ip = self._get_local_ip()
-        service_name = f"{self.device_name}.{SERVICE_TYPE}"
-
-        self._service_info = ServiceInfo(
-            SERVICE_TYPE,
-            service_name,
-            addresses=[socket.inet_aton(local_ip)],
-            port=self.port,
-            properties={
-                "device_name": self.device_name,
-                "device_type": self.device_type,
-                "version": "0.1.0",
-            },
-        )
+        try:
+            if self.bind_addresses:
+                # mDNS sockets are opened per address; zones don't apply
+                interfaces = [ip.split("%", 1)[0] for ip in self.bind_addresses]
+                self._zeroconf = Zeroconf(interfaces=interfaces)
+            else:
+                self._zeroconf = Zeroconf()
+        except OSError as e:
+            # No multicast route, or a firewall refusing the mDNS socket;
+            # the rest of the sidecar works without discovery
+            logger.error(f"Discovery unavailable: {e}")
+            self._error = f"Cannot open mDNS socket: {e}"
+            self._report_status()
+            return
 
         # Register our service
-        self._zeroconf.register_service(self._service_info)
-        logger.info(f"Advertising as {service_name} on {local_ip}:{self.port}")
+        self._advertise(self._get_local_ips())
 
         # Set up listener for peer discovery
         self._listener = PeerDiscoveryListener(
@@ -180,7 +206,54 @@ class Discovery:
         )
 
         self._running = True
+        self._last_scan_at = int(time.time() * 1000)
         logger.info("Discovery started")
+        self._report_status()
+
+    def _advertise(self, local_ips: list[str]) -> None:
+        """Register our service at local_ips, replacing any previous registration."""
+        if not self._zeroconf:
+            return
+
+        if self._service_info:
+            self._zeroconf.unregister_service(self._service_info)
+
+        service_name = self.service_name
+        self._service_info = ServiceInfo(
+            SERVICE_TYPE,
+            service_name,
+            # mDNS records carry no zone; peers on the link supply their own
+            parsed_addresses=[ip.split("%", 1)[0] for ip in local_ips],
+            port=self.port,
+            properties={
+                "device_name": self.device_name,
+                "device_type": self.device_type,
+                "version": "0.1.0",
+            },
+        )
+        try:
+            self._zeroconf.register_service(self._service_info)
+        except OSError as e:
+            logger.error(f"Could not advertise on {', '.join(local_ips)}: {e}")
+            self._error = f"Cannot send mDNS announcements: {e}"
+            self._report_status()
+            return
+        self._error = None
+        logger.info(f"Advertising as {service_name} on {', '.join(local_ips)} port {self.port}")
+
+    async def update_identity(
+        self, device_name: Optional[str] = None, device_type: Optional[str] = None
+    ) -> None:
+        """Change how we advertise ourselves; peers see the change on their next browse."""
+        if device_name is not None:
+            self.device_name = device_name
+        if device_type is not None:
+            self.device_type = device_type
+
+        if not self._running:
+            return
+        loop = asyncio.get_event_loop()
+        await loop.run_in_executor(None, self._advertise, self._get_local_ips())
 
     def _on_peer_found(self, peer: DiscoveredPeer) -> None:
         """Filter out self from discovered peers."""
@@ -189,10 +262,74 @@ class Discovery:
         if self.on_peer_found:
             self.on_peer_found(peer)
 
+    async def rescan(self) -> None:
+        """Drop cached peers and browse again from scratch."""
+        if not self._running:
+            return
+
+        loop = asyncio.get_event_loop()
+        await loop.run_in_executor(None, self._rescan_sync)
+
+    def _rescan_sync(self) -> None:
+        """Synchronous restart of the service browser."""
+        if self._browser:
+            self._browser.cancel()
+        if self._listener:
+            self._listener.clear()
+
+        # After a network change our advertised addresses may be stale
+        local_ips = self._get_local_ips()
+        advertised = self._service_info.parsed_addresses() if self._service_info else []
+        if {ip.split("%", 1)[0] for ip in local_ips} != set(advertised):
+            self._advertise(local_ips)
+
+        self._browser = ServiceBrowser(
+            self._zeroconf,
+            SERVICE_TYPE,
+            self._listener,
+        )
+        self._last_scan_at = int(time.time() * 1000)
+        logger.info("Discovery restarted")
+        self._report_status()
+
+    async def rebind(self, interface: Optional[str], addresses: list[str]) -> None:
+        """Limit discovery to interface, or lift the limit with None, and start it afresh."""
+        if self._listener:
+            self._listener.clear()
+        await self.stop()
+        self.interface = interface
+        self.bind_addresses = addresses
+        self._error = None
+        await self.start()
+
+    async def _run_heartbeat(self) -> None:
+        """Re-report every peer that still answers, every HEARTBEAT_INTERVAL."""
+        loop = asyncio.get_event_loop()
+        while self._running:
+            await asyncio.sleep(HEARTBEAT_INTERVAL)
+            try:
+                await loop.run_in_executor(None, self._heartbeat_sync)
+            except Exception as e:
+                logger.warning(f"Peer heartbeat failed: {e}")
+
+    def _heartbeat_sync(self) -> None:
+        """Confirm each known peer; answers come from the mDNS cache while fresh."""
+        if not self._zeroconf or not self._listener:
+            return
+        for name in list(self._listener.peers):
+            info = self._zeroconf.get_service_info(
+                SERVICE_TYPE, name, timeout=HEARTBEAT_TIMEOUT_MS
+            )
+            if info:
+                self._listener._handle_service_info(name, info)
+
     async def stop(self) -> None:
         """Stop advertising and browsing."""
         if not self._running:
             return
+        if self._heartbeat:
+            self._heartbeat.cancel()
+            self._heartbeat = None
 
         loop = asyncio.get_event_loop()
         await loop.run_in_executor(None, self._stop_sync)
@@ -213,6 +350,21 @@ class Discovery:
 
         self._running = False
         logger.info("Discovery stopped")
+        self._report_status()
+
+    def get_status(self) -> dict[str, Any]:
+        """Whether discovery works, for diagnosing peers that never show up."""
+        addresses = self._service_info.parsed_addresses() if self._service_info else []
+        return {
+            "active": self._running and self._error is None,
+            "last_scan_at": self._last_scan_at,
+            "interfaces": addresses,
+            "error": self._error,
+        }
+
+    def _report_status(self) -> None:
+        if self.on_status:
+            self.on_status(self.get_status())
 
     def get_peers(self) -> list[DiscoveredPeer]:
         """Get list of currently discovered peers."""
@@ -225,12 +377,19 @@ class Discovery:
             if not peer.name.startswith(self.device_name)
         ]
 
+    @property
+    def service_name(self) -> str:
+        """Our mDNS service name, which peers see as our id."""
+        return f"{self.device_name}.{SERVICE_TYPE}"
+
     def get_local_info(self) -> dict:
         """Get local service info for display."""
+        addresses = local_addresses()
         return {
             "name": self.device_name,
             "type": self.device_type,
-            "ip": self._get_local_ip(),
+            "ip": addresses[0]["ip"] if addresses else primary_ip(),
+            "addresses": addresses,
             "port": self.port,
         }
 
@@ -0,0 +1,122 @@
+"""Long-lived device identity, so peers can recognise us across connections.
+
+Each device keeps an Ed25519 key. During the handshake both sides send
+their public key and a signature over the session id; the peer shows the
+key's fingerprint so the user can compare it on both screens, and the app
+warns if a known peer's fingerprint ever changes.
+
+Without the `cryptography` package identities are simply not exchanged.
+"""
+
+import base64
+import hashlib
+import logging
+import os
+from pathlib import Path
+from typing import Any, Optional
+
+try:
+    from cryptography.exceptions import InvalidSignature
+    from cryptography.hazmat.primitives import serialization
+    from cryptography.hazmat.primitives.asymmetric.ed25519 import (
+        Ed25519PrivateKey,
+        Ed25519PublicKey,
+    )
+
+    HAVE_CRYPTO = True
+except ImportError:  # pragma: no cover - depends on the environment
+    HAVE_CRYPTO = False
+
+logger = logging.getLogger(__name__)
+
+
+def default_path() -> Path:
+    """Where the key lives unless DECK_LINK_IDENTITY says otherwise."""
+    override = os.environ.get("DECK_LINK_IDENTITY")
+    if override:
+        return Path(override)
+    config = os.environ.get("XDG_CONFIG_HOME") or os.path.expanduser("~/.config")
+    return Path(config) / "deck-link" / "identity.pem"
+
+
+def fingerprint(public_key: str) -> str:
+    """SHA-256 of the raw public key, as lowercase hex."""
+    return hashlib.sha256(base64.b64decode(public_key)).hexdigest()
+
+
+def verify(public_key: str, signature: str, data: str) -> bool:
+    """Check a peer's signature over data."""
+    if not HAVE_CRYPTO:
+        return False
+    try:
+        key = Ed25519PublicKey.from_public_bytes(base64.b64decode(public_key))
+        key.verify(base64.b64decode(signature), data.encode())
+        return True
+    except (InvalidSignature, ValueError):
+        return False
+
+
+class Identity:
+    """Our own key pair."""
+
+    def __init__(self, key: "Ed25519PrivateKey"):
+        self._key = key
+        raw = key.public_key().public_bytes(
+            serialization.Encoding.Raw, serialization.PublicFormat.Raw
+        )
+        self.public_key = base64.b64encode(raw).decode()
+        self.fingerprint = fingerprint(self.public_key)
+
+    @classmethod
+    def load_or_create(cls, path: Optional[Path] = None) -> Optional["Identity"]:
+        """Load the key at path, generating it on first run."""
+        if not HAVE_CRYPTO:
+            logger.warning("cryptography not installed; device identity disabled")
+            return None
+
+        path = path or default_path()
+        try:
+            if path.exists():
+                key = serialization.load_pem_private_key(path.read_bytes(), password=None)
+                if not isinstance(key, Ed25519PrivateKey):
+                    raise ValueError("not an Ed25519 key")
+                return cls(key)
+
+            key = Ed25519PrivateKey.generate()
+            path.parent.mkdir(parents=True, exist_ok=True)
+            pem = key.private_bytes(
+                serialization.Encoding.PEM,
+                serialization.PrivateFormat.PKCS8,
+                serialization.NoEncryption(),
+            )
+            fd = os.open(path, os.O_WRONLY | os.O_CREAT | os.O_TRUNC, 0o600)
+            with os.fdopen(fd, "wb") as f:
+                f.write(pem)
+            logger.info(f"Generated device identity at {path}")
+            return cls(key)
+        except (OSError, ValueError) as e:
+            logger.error(f"Device identity unavailable: {e}")
+            return None
+
+    def proof(self, session_id: str, role: str) -> dict[str, Any]:
+        """Public key plus a signature binding it to this session and role."""
+        signature = self._key.sign(f"{session_id}:{role}".encode())
+        return {
+            "public_key": self.public_key,
+            "signature": base64.b64encode(signature).decode(),
+        }
+
+
+def check_proof(payload: dict[str, Any], session_id: str, role: str) -> Optional[str]:
+    """Verify an identity proof from a peer.
+
+    Returns the peer's fingerprint, or None if it sent no identity. Raises
+    ValueError if it sent one that doesn't verify.
+    """
+    public_key = payload.get("public_key")
+    if not public_key or not HAVE_CRYPTO:
+        return None
+    signature = payload.get("signature", "")
+    if not verify(public_key, signature, f"{session_id}:{role}"):
+        raise ValueError("Peer identity could not be verified")
+    return fingerprint(public_key)
@@ -1,6 +1,7 @@
 """Main entry point for Deck-Link CLI and IPC server."""
 
 import asyncio
+import contextvars
 import json
 import logging
 import socket
@@ -9,15 +10,51 @@ from typing import Any, Optional
 
 import click
 
-from . import PORT
+from . import PORT, PROTOCOL_VERSION, __version__
+from .addresses import normalize_host
 from .server import DeckLinkServer
 from .discovery import Discovery, DiscoveredPeer
+from .identity import Identity
+from .pairing_secrets import PairingSecrets
 
 logging.basicConfig(
     level=logging.INFO, format="%(asctime)s - %(name)s - %(levelname)s - %(message)s"
 )
 logger = logging.getLogger(__name__)
 
+# Kinds of device we can advertise as; peers pick an icon from it
+DEVICE_TYPES = ["laptop", "deck", "desktop", "phone", "tablet", "server"]
+
+# Longest request line read from stdin. asyncio's default of 64 KiB would
+# cut off clipboard contents and images; the app keeps its lines under 1 MiB.
+MAX_REQUEST_BYTES = 4 * 1024 * 1024
+
+# Commands that wait on a peer or the network. They run as tasks of their
+# own, so a slow peer can't hold up pings and the health check doesn't
+# take the sidecar for wedged; every other command is answered in order.
+BACKGROUND_COMMANDS = {
+    "connect",
+    "probe",
+    "submit_passphrase",
+    "disconnect",
+    "verify_connections",
+    "send_notification",
+    "set_clipboard",
+    "set_clipboard_image",
+    "transfer_start",
+    "transfer_chunk",
+    "transfer_finish",
+    "transfer_cancel",
+    "accept_transfer",
+    "reject_transfer",
+}
+
+# Trace id of the request being handled. Tasks started while handling it
+# inherit the value, so events they send later are tagged too.
+current_trace_id: contextvars.ContextVar[Optional[str]] = contextvars.ContextVar(
+    "current_trace_id", default=None
+)
+
 
 class JsonRpcServer:
     """
@@ -31,6 +68,8 @@ class JsonRpcServer:
         self.deck_link = deck_link
         self.discovery = discovery
         self._running = False
+        # Background commands still running, by request id
+        self._tasks: dict[Any, asyncio.Task[None]] = {}
 
     async def start(self) -> None:
         """Start listening for commands on stdin."""
@@ -38,25 +77,47 @@ class JsonRpcServer:
 
         # Make stdin non-blocking
         loop = asyncio.get_event_loop()
-        reader = asyncio.StreamReader()
+        reader = asyncio.StreamReader(limit=MAX_REQUEST_BYTES)
         protocol = asyncio.StreamReaderProtocol(reader)
         await loop.connect_read_pipe(lambda: protocol, sys.stdin)
 
         while self._running:
-            try:
-                line = await reader.readline()
-                if not line:
-                    break
+            line = await reader.readline()
+            if not line:
+                break
 
+            try:
                 command = json.loads(line.decode().strip())
-                response = await self._handle_command(command)
-                self._send_response(response)
-
             except json.JSONDecodeError as e:
                 self._send_response({"error": f"Invalid JSON: {e}"})
-            except Exception as e:
-                logger.error(f"Command error: {e}")
-                self._send_response({"error": str(e)})
+                continue
+
+            if command.get("method") in BACKGROUND_COMMANDS:
+                request_id = command.get("id")
+                task = asyncio.create_task(self._run_command(command))
+                self._tasks[request_id] = task
+                task.add_done_callback(lambda _, request_id=request_id: self._tasks.pop(request_id, None))
+            else:
+                await self._run_command(command)
+
+        for task in list(self._tasks.values()):
+            task.cancel()
+
+    async def _run_command(self, command: dict[str, Any]) -> None:
+        """Handle one command and send its response."""
+        trace_id = command.get("trace_id")
+        token = current_trace_id.set(trace_id if isinstance(trace_id, str) else None)
+        try:
+            self._send_response(await self._handle_command(command))
+        except asyncio.CancelledError:
+            if self._running:
+                self._send_response({"error": "Cancelled", "id": command.get("id")})
+        except Exception as e:
+            logger.error(f"Command error: {e}")
+            # Keep the id so the caller's request fails instead of timing out
+            self._send_response({"error": str(e), "id": command.get("id")})
+        finally:
+            current_trace_id.reset(token)
 
     async def _handle_command(self, command: dict[str, Any]) -> dict[str, Any]:
         """Handle a JSON-RPC command."""
@@ -71,40 +132,173 @@ class JsonRpcServer:
             result["local_info"] = self.discovery.get_local_info()
 
         elif method == "get_peers":
-            peers = self.discovery.get_peers()
-            result = [
-                {
-                    "name": p.display_name,
-                    "host": p.host,
-                    "port": p.port,
-                    "device_type": p.device_type,
-                }
-                for p in peers
-            ]
+            result = [peer_to_dict(p) for p in self.discovery.get_peers()]
+
+        elif method == "create_pairing_token":
+            result = self.deck_link.create_pairing_token()
+            result["peer_id"] = self.discovery.service_name
+            result["local_info"] = self.discovery.get_local_info()
+
+        elif method == "get_discovery_status":
+            result = self.discovery.get_status()
+
+        elif method == "rescan":
+            await self.discovery.rescan()
+            result = {"status": "scanning"}
 
         elif method == "connect":
             host = params.get("host", "")
             port = params.get("port", PORT)
-            await self.deck_link.connect_to(host, port)
-            result = {"status": "connecting"}
+            pairing_token = params.get("pairing_token")
+            pairing_secret = params.get("pairing_secret")
+            session_id = await self.deck_link.connect_to(host, port, pairing_token, pairing_secret)
+            result = {"status": "connecting", "session_id": session_id}
+
+        elif method == "probe":
+            result = await self.deck_link.probe_peer(params.get("host", ""), params.get("port", PORT))
+
+        elif method == "accept_pairing":
+            self.deck_link.resolve_pairing_request(params.get("id", ""), None)
+            result = {"status": "accepted"}
+
+        elif method == "reject_pairing":
+            reason = params.get("reason") or "declined"
+            self.deck_link.resolve_pairing_request(params.get("id", ""), reason)
+            result = {"status": "rejected"}
+
+        elif method == "revoke_pairing_secrets":
+            fingerprints = params.get("fingerprints")
+            if params.get("all"):
+                fingerprints = None
+            elif not isinstance(fingerprints, list) or not all(isinstance(fp, str) for fp in fingerprints):
+                raise ValueError("Revoking pairing secrets needs a list of fingerprints or all")
+            secrets = self.deck_link.pairing_secrets
+            result = {"revoked": secrets.revoke(fingerprints) if secrets else 0}
 
         elif method == "submit_passphrase":
             passphrase = params.get("passphrase", "")
             await self.deck_link.submit_passphrase(passphrase)
             result = {"status": "submitted"}
 
+        elif method == "set_device_name":
+            name = params.get("name", "").strip()
+            if not name:
+                raise ValueError("Device name must not be empty")
+            self.deck_link.device_name = name
+            await self.discovery.update_identity(device_name=name)
+            result = {"status": "updated"}
+
+        elif method == "set_device_type":
+            device_type = params.get("device_type", "")
+            if device_type not in DEVICE_TYPES:
+                raise ValueError(f"Unknown device type: {device_type}")
+            self.deck_link.device_type = device_type
+            await self.discovery.update_identity(device_type=device_type)
+            result = {"status": "updated"}
+
+        elif method == "set_bind_interface":
+            interface = params.get("interface")
+            addresses = [normalize_host(address) for address in params.get("addresses", [])]
+            if interface and not addresses:
+                raise ValueError(f"Interface {interface} has no addresses")
+            await self.deck_link.rebind(addresses)
+            await self.discovery.rebind(interface, addresses)
+            result = {"status": "updated"}
+
+        elif method == "set_transfer_rate_limit":
+            kbps = params.get("kbps")
+            if kbps is not None and (not isinstance(kbps, int) or kbps < 1):
+                raise ValueError("Rate limit must be a positive number of kbps")
+            self.deck_link.transfer_rate_limit_kbps = kbps
+            result = {"status": "updated"}
+
         elif method == "disconnect":
-            await self.deck_link.disconnect_peer()
+            await self.deck_link.disconnect_peer(params.get("session_id"))
             result = {"status": "disconnected"}
 
+        elif method == "verify_connections":
+            result = {"alive": await self.deck_link.verify_connections()}
+
         elif method == "send_notification":
             title = params.get("title", "")
             body = params.get("body", "")
-            await self.deck_link.send_notification(title, body)
+            priority = params.get("priority")
+            session_id = params.get("session_id")
+            await self.deck_link.send_notification(title, body, priority, session_id)
+            result = {"status": "sent"}
+
+        elif method == "set_clipboard":
+            text = params.get("text")
+            origin = params.get("origin")
+            if not isinstance(text, str) or not isinstance(origin, str):
+                raise ValueError("Clipboard updates need text and an origin")
+            await self.deck_link.send_clipboard(text, origin)
+            result = {"status": "sent"}
+
+        elif method == "set_clipboard_image":
+            png = params.get("png")
+            origin = params.get("origin")
+            if not isinstance(png, str) or not isinstance(origin, str):
+                raise ValueError("Clipboard images need a PNG and an origin")
+            await self.deck_link.send_clipboard_image(png, origin)
             result = {"status": "sent"}
 
+        elif method == "transfer_start":
+            size = params.get("size")
+            if not isinstance(size, int) or size < 0:
+                raise ValueError("Transfer size must be a number of bytes")
+            await self.deck_link.start_transfer(
+                params.get("id", ""), params.get("filename", ""), size, params.get("session_id")
+            )
+            result = {"status": "offered"}
+
+        elif method == "transfer_chunk":
+            await self.deck_link.send_transfer_chunk(
+                params.get("id", ""),
+                params.get("offset", 0),
+                params.get("data", ""),
+                params.get("session_id"),
+            )
+            result = {"status": "sent"}
+
+        elif method == "transfer_finish":
+            await self.deck_link.finish_transfer(params.get("id", ""), params.get("session_id"))
+            result = {"status": "finished"}
+
+        elif method == "transfer_cancel":
+            await self.deck_link.cancel_transfer(params.get("id", ""))
+            result = {"status": "cancelled"}
+
+        elif method == "accept_transfer":
+            await self.deck_link.answer_transfer(params.get("id", ""), True, params.get("session_id"))
+            result = {"status": "accepted"}
+
+        elif method == "reject_transfer":
+            await self.deck_link.answer_transfer(params.get("id", ""), False, params.get("session_id"))
+            result = {"status": "rejected"}
+
+        elif method == "cancel":
+            # Only background commands can still be running by now; any
+            # other has been answered already, and the caller drops that
+            task = self._tasks.get(params.get("id"))
+            if task:
+                task.cancel()
+            result = {"status": "cancelled" if task else "finished", "id": params.get("id")}
+
+        elif method == "shutdown":
+            # Say goodbye to the peer now; leaving the command loop runs the
+            # regular cleanup (unadvertise, close the server) and exits
+            await self.deck_link.disconnect_peer()
+            self._running = False
+            result = {"status": "shutting_down"}
+
         elif method == "ping":
-            result = {"pong": True}
+            result = {
+                "pong": True,
+                "protocol_version": PROTOCOL_VERSION,
+                "version": __version__,
+                "peer_rtt_ms": self.deck_link.peer_rtt_ms,
+            }
 
         else:
             return {"error": f"Unknown method: {method}", "id": request_id}
@@ -112,7 +306,10 @@ class JsonRpcServer:
         return {"result": result, "id": request_id}
 
     def _send_response(self, response: dict[str, Any]) -> None:
-        """Send a JSON response to stdout."""
+        """Send a JSON response to stdout, tagged with the current trace id."""
+        trace_id = current_trace_id.get()
+        if trace_id is not None:
+            response.setdefault("trace_id", trace_id)
         print(json.dumps(response), flush=True)
 
     def send_event(self, event: str, data: dict[str, Any]) -> None:
@@ -125,6 +322,19 @@ class JsonRpcServer:
         )
 
 
+def peer_to_dict(peer: DiscoveredPeer) -> dict[str, Any]:
+    """Serialize a discovered peer for the UI."""
+    return {
+        "id": peer.name,
+        "name": peer.display_name,
+        "host": peer.host,
+        "ip": peer.addresses[0] if peer.addresses else peer.host,
+        "addresses": peer.addresses,
+        "port": peer.port,
+        "device_type": peer.device_type,
+    }
+
+
 def get_device_name() -> str:
     """Get a reasonable device name."""
     hostname = socket.gethostname()
@@ -150,16 +360,53 @@ def cli() -> None:
 
 
 @cli.command()
-@click.option("--mode", type=click.Choice(["laptop", "deck"]), default="laptop")
+@click.option("--mode", type=click.Choice(DEVICE_TYPES), default="laptop")
 @click.option("--name", default=None, help="Device name")
 @click.option("--port", default=PORT, help="Port to listen on")
 @click.option("--ipc", is_flag=True, help="Run in IPC mode for Tauri")
-def run(mode: str, name: Optional[str], port: int, ipc: bool) -> None:
+@click.option("--verbose", is_flag=True, help="Log at debug level")
+@click.option("--interface", default=None, help="Network interface to limit discovery to")
+@click.option("--bind", multiple=True, help="Address to listen and connect from (repeatable)")
+def run(
+    mode: str,
+    name: Optional[str],
+    port: int,
+    ipc: bool,
+    verbose: bool,
+    interface: Optional[str],
+    bind: tuple[str, ...],
+) -> None:
     """Run the Deck-Link server."""
+    if verbose:
+        logging.getLogger().setLevel(logging.DEBUG)
     device_name = name or get_device_name()
+    bind_addresses = [normalize_host(address) for address in bind]
 
     async def main() -> None:
         rpc_server: Optional[JsonRpcServer] = None
+        loop = asyncio.get_running_loop()
+
+        # Zeroconf calls these from its own thread; hop onto the loop so
+        # events never interleave with responses on stdout
+        def on_peer_found(peer: DiscoveredPeer) -> None:
+            logger.debug(f"Peer found: {peer.display_name}")
+            if rpc_server:
+                loop.call_soon_threadsafe(
+                    rpc_server.send_event, "peer_discovered", peer_to_dict(peer)
+                )
+
+        def on_peer_lost(name: str) -> None:
+            logger.info(f"Peer lost: {name}")
+            if rpc_server:
+                loop.call_soon_threadsafe(
+                    rpc_server.send_event, "peer_lost", {"id": name}
+                )
+
+        def on_discovery_status(status: dict[str, Any]) -> None:
+            if rpc_server:
+                loop.call_soon_threadsafe(
+                    rpc_server.send_event, "discovery_status", status
+                )
 
         # Create server
         server = DeckLinkServer(
@@ -167,6 +414,9 @@ def run(mode: str, name: Optional[str], port: int, ipc: bool) -> None:
             device_type=mode,
             port=port,
             on_event=create_event_handler(rpc_server),
+            identity=Identity.load_or_create(),
+            bind_addresses=bind_addresses,
+            pairing_secrets=PairingSecrets(),
         )
 
         # Create discovery
@@ -174,8 +424,11 @@ def run(mode: str, name: Optional[str], port: int, ipc: bool) -> None:
             device_name=device_name,
             device_type=mode,
             port=port,
-            on_peer_found=lambda p: logger.info(f"Peer found: {p.display_name}"),
-            on_peer_lost=lambda n: logger.info(f"Peer lost: {n}"),
+            on_peer_found=on_peer_found,
+            on_peer_lost=on_peer_lost,
+            on_status=on_discovery_status,
+            interface=interface,
+            bind_addresses=bind_addresses,
         )
 
         # Start services
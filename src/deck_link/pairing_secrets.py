@@ -0,0 +1,87 @@
+"""Secrets that let a paired peer reconnect without a passphrase.
+
+When a peer that proved an identity key pairs with us, we issue it a
+random secret along with the auth result. It keeps the secret (the app
+files it in the OS keychain) and presents it with later connection
+requests, which are then accepted straight away. Only a hash of each
+secret is stored here, under the fingerprint of the key it was issued to,
+so a secret is worthless to anyone who doesn't also hold that key.
+"""
+
+import hashlib
+import hmac
+import json
+import logging
+import os
+import secrets
+from pathlib import Path
+from typing import Optional
+
+from .identity import default_path as identity_path
+
+logger = logging.getLogger(__name__)
+
+
+def default_path() -> Path:
+    """Next to the identity key."""
+    return identity_path().with_name("pairing_secrets.json")
+
+
+def _digest(secret: str) -> str:
+    return hashlib.sha256(secret.encode()).hexdigest()
+
+
+class PairingSecrets:
+    """Hashes of the secrets we issued, by peer fingerprint."""
+
+    def __init__(self, path: Optional[Path] = None):
+        self.path = path or default_path()
+        self._hashes: dict[str, str] = {}
+        try:
+            if self.path.exists():
+                loaded = json.loads(self.path.read_text())
+                if isinstance(loaded, dict):
+                    self._hashes = {k: v for k, v in loaded.items() if isinstance(v, str)}
+        except (OSError, ValueError) as e:
+            logger.error(f"Pairing secrets unreadable, peers will pair again: {e}")
+
+    def issue(self, fingerprint: str) -> str:
+        """A new secret for the peer with fingerprint, replacing any before it."""
+        secret = secrets.token_urlsafe(32)
+        self._hashes[fingerprint] = _digest(secret)
+        self._save()
+        return secret
+
+    def check(self, fingerprint: str, secret: str) -> bool:
+        """Whether secret is the one issued to the peer with fingerprint."""
+        expected = self._hashes.get(fingerprint)
+        return expected is not None and hmac.compare_digest(_digest(secret), expected)
+
+    def revoke(self, fingerprints: Optional[list[str]] = None) -> int:
+        """Stop honouring the secrets of these peers, or of every peer with
+        None, so they have to pair again. Returns how many were dropped.
+
+        Raises OSError if that can't be persisted; the secrets stay revoked
+        until we restart.
+        """
+        if fingerprints is None:
+            revoked = len(self._hashes)
+            self._hashes.clear()
+        else:
+            revoked = sum(self._hashes.pop(fp, None) is not None for fp in fingerprints)
+        if revoked:
+            self._write()
+        return revoked
+
+    def _save(self) -> None:
+        try:
+            self._write()
+        except OSError as e:
+            # The secret still works until we restart
+            logger.error(f"Not persisting pairing secrets: {e}")
+
+    def _write(self) -> None:
+        self.path.parent.mkdir(parents=True, exist_ok=True)
+        fd = os.open(self.path, os.O_WRONLY | os.O_CREAT | os.O_TRUNC, 0o600)
+        with os.fdopen(fd, "w") as f:
+            json.dump(self._hashes, f)
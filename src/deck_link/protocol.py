@@ -28,6 +28,16 @@ class MessageType(str, Enum):
     CONTROLLER_INPUT = "controller_input"
     AUDIO_STREAM = "audio_stream"
     NOTIFICATION = "notification"
+    CLIPBOARD = "clipboard"
+    CLIPBOARD_IMAGE = "clipboard_image"
+
+    # File transfers, offered by the sender and accepted by the receiver
+    TRANSFER_START = "transfer_start"
+    TRANSFER_ACCEPT = "transfer_accept"
+    TRANSFER_REJECT = "transfer_reject"
+    TRANSFER_CHUNK = "transfer_chunk"
+    TRANSFER_FINISH = "transfer_finish"
+    TRANSFER_CANCEL = "transfer_cancel"
 
     # Errors
     ERROR = "error"
@@ -75,16 +85,24 @@ class Message(BaseModel):
 # Convenience factory functions for common messages
 
 
-def connection_request(sender_name: str, sender_ip: str, sender_port: int) -> Message:
+def connection_request(
+    sender_name: str,
+    sender_ip: str,
+    sender_port: int,
+    pairing_token: Optional[str] = None,
+    pairing_secret: Optional[str] = None,
+) -> Message:
     """Create a connection request message."""
-    return Message(
-        type=MessageType.CONNECTION_REQUEST,
-        payload={
-            "sender_name": sender_name,
-            "sender_ip": sender_ip,
-            "sender_port": sender_port,
-        },
-    )
+    payload: dict[str, Any] = {
+        "sender_name": sender_name,
+        "sender_ip": sender_ip,
+        "sender_port": sender_port,
+    }
+    if pairing_token:
+        payload["pairing_token"] = pairing_token
+    if pairing_secret:
+        payload["pairing_secret"] = pairing_secret
+    return Message(type=MessageType.CONNECTION_REQUEST, payload=payload)
 
 
 def challenge_response(session_id: str, receiver_name: str) -> Message:
@@ -110,7 +128,9 @@ def auth_attempt(session_id: str, passphrase: str) -> Message:
     )
 
 
-def auth_result(session_id: str, success: bool, message: str = "") -> Message:
+def auth_result(
+    session_id: str, success: bool, message: str = "", rate_limited: bool = False
+) -> Message:
     """Create an authentication result message."""
     return Message(
         type=MessageType.AUTH_RESULT,
@@ -118,6 +138,7 @@ def auth_result(session_id: str, success: bool, message: str = "") -> Message:
         payload={
             "success": success,
             "message": message,
+            "rate_limited": rate_limited,
         },
     )
 
@@ -151,7 +172,12 @@ def error(message: str, code: str = "UNKNOWN") -> Message:
     )
 
 
-def notification(title: str, body: str, icon: Optional[str] = None) -> Message:
+def notification(
+    title: str,
+    body: str,
+    icon: Optional[str] = None,
+    priority: Optional[str] = None,
+) -> Message:
     """Create a notification message."""
     return Message(
         type=MessageType.NOTIFICATION,
@@ -159,5 +185,57 @@ def notification(title: str, body: str, icon: Optional[str] = None) -> Message:
             "title": title,
             "body": body,
             "icon": icon,
+            "priority": priority,
+        },
+    )
+
+
+def clipboard(text: str, origin: str) -> Message:
+    """Create a clipboard update; origin names the device it was copied on."""
+    return Message(
+        type=MessageType.CLIPBOARD,
+        payload={
+            "text": text,
+            "origin": origin,
         },
     )
+
+
+def clipboard_image(png: str, origin: str) -> Message:
+    """Create a clipboard image update, carrying the picture as base64 PNG."""
+    return Message(
+        type=MessageType.CLIPBOARD_IMAGE,
+        payload={
+            "png": png,
+            "origin": origin,
+        },
+    )
+
+
+def transfer_start(transfer_id: str, filename: str, size: int) -> Message:
+    """Create a file transfer offer."""
+    return Message(
+        type=MessageType.TRANSFER_START,
+        payload={
+            "id": transfer_id,
+            "filename": filename,
+            "size": size,
+        },
+    )
+
+
+def transfer_chunk(transfer_id: str, offset: int, data: str) -> Message:
+    """Create a chunk of an accepted transfer, carrying base64 data."""
+    return Message(
+        type=MessageType.TRANSFER_CHUNK,
+        payload={
+            "id": transfer_id,
+            "offset": offset,
+            "data": data,
+        },
+    )
+
+
+def transfer_control(kind: MessageType, transfer_id: str) -> Message:
+    """Create an accept, reject, finish or cancel message for a transfer."""
+    return Message(type=kind, payload={"id": transfer_id})
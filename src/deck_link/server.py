@@ -1,15 +1,21 @@
 """WebSocket server for Deck-Link communication."""
 
 import asyncio
+import hmac
 import json
 import logging
+import secrets
+import socket
+import ssl
+import time
 from dataclasses import dataclass, field
 from typing import Any, Callable, Optional
 from websockets.server import serve, WebSocketServerProtocol  # type: ignore
 from websockets.client import connect, WebSocketClientProtocol  # type: ignore
-from websockets.exceptions import ConnectionClosed  # type: ignore
+from websockets.exceptions import ConnectionClosed, InvalidHandshake  # type: ignore
 
 from . import PORT
+from .addresses import connect_kwargs, normalize_host, parse_ip, ws_uri
 from .protocol import (
     Message,
     MessageType,
@@ -22,11 +28,54 @@ from .protocol import (
     pong,
     disconnect,
     error,
+    clipboard,
+    clipboard_image,
+    transfer_start,
+    transfer_chunk,
+    transfer_control,
 )
+from .identity import Identity, check_proof
+from .pairing_secrets import PairingSecrets
 from .passphrase import generate_passphrase, validate_passphrase
 
 logger = logging.getLogger(__name__)
 
+# Failed passphrase attempts allowed from one address per lockout window
+MAX_AUTH_FAILURES = 5
+AUTH_LOCKOUT_SECONDS = 300.0
+
+# How long a pairing token from create_pairing_token stays valid
+PAIRING_TOKEN_TTL_SECONDS = 300.0
+
+# How long an incoming pairing request waits for accept_pairing or
+# reject_pairing before it is turned down; the UI normally answers first
+PAIRING_APPROVAL_TIMEOUT_SECONDS = 90.0
+
+# How often the session's byte counters are reported while connected
+STATS_INTERVAL_SECONDS = 1.0
+
+# How long a peer gets to answer when connections are verified, e.g. after
+# the machine wakes from sleep
+VERIFY_TIMEOUT_SECONDS = 3.0
+
+# How long probe_peer waits for the WebSocket handshake
+PROBE_TIMEOUT_SECONDS = 2.5
+
+# Largest WebSocket message accepted from a peer; clipboard images are
+# sent whole, so this is above websockets' 1 MiB default
+MAX_MESSAGE_BYTES = 4 * 1024 * 1024
+
+# Largest clipboard image, as base64 PNG, passed on either way; the Tauri
+# side reads our output a line at a time and drops lines over 1 MiB
+MAX_CLIPBOARD_IMAGE_CHARS = 960 * 1024
+
+# How long the first chunk of a transfer waits for the peer to accept it
+TRANSFER_ACCEPT_TIMEOUT_SECONDS = 120.0
+
+# How long connect_to waits for the WebSocket handshake, so an unreachable
+# peer fails (and can be retried) instead of holding the handshake slot
+CONNECT_TIMEOUT_SECONDS = 10.0
+
 
 @dataclass
 class PeerInfo:
@@ -36,6 +85,8 @@ class PeerInfo:
     ip: str
     port: int
     websocket: Optional[WebSocketServerProtocol | WebSocketClientProtocol] = None
+    # Fingerprint of the identity key the peer proved it holds, if it sent one
+    fingerprint: Optional[str] = None
 
 
 @dataclass
@@ -48,8 +99,96 @@ class ConnectionSession:
     state: ConnectionState = ConnectionState.DISCONNECTED
 
 
+@dataclass
+class PeerConnection:
+    """An established session with a peer, one of possibly several."""
+
+    session_id: str
+    peer: PeerInfo
+    websocket: WebSocketServerProtocol | WebSocketClientProtocol
+
+    # Heartbeat: when the last ping went out, and the round trip of the last
+    # one answered
+    ping_task: Optional[asyncio.Task[None]] = None
+    last_pong: float = 0
+    ping_sent_at: Optional[float] = None
+    rtt_ms: Optional[float] = None
+
+    # Bytes exchanged this session, reported by the stats loop
+    stats_task: Optional[asyncio.Task[None]] = None
+    bytes_sent: int = 0
+    bytes_received: int = 0
+
+    def to_dict(self) -> dict[str, Any]:
+        return {
+            "session_id": self.session_id,
+            "name": self.peer.name,
+            "ip": self.peer.ip,
+            "port": self.peer.port,
+            "fingerprint": self.peer.fingerprint,
+        }
+
+
+@dataclass
+class OutgoingTransfer:
+    """A file offered to a peer; the Tauri side reads it and sends the chunks."""
+
+    transfer_id: str
+    conn: PeerConnection
+    # Resolves to None once the peer accepts, otherwise to why it won't
+    answer: asyncio.Future[Optional[str]]
+    # Set when the peer gives up on a transfer it had accepted
+    cancelled: bool = False
+
+
+def _remote_ip(websocket: Any) -> str:
+    """The normalized address a socket's peer is connecting from."""
+    remote = websocket.remote_address
+    if not remote:
+        return ""
+    ip = parse_ip(str(remote[0]))
+    if ip is None:
+        return str(remote[0])
+    # IPv6 socket addresses carry the zone as a scope id
+    if ip.version == 6 and ip.is_link_local and len(remote) >= 4 and remote[3] and "%" not in str(ip):
+        try:
+            return f"{ip}%{socket.if_indextoname(remote[3])}"
+        except OSError:
+            pass
+    return str(ip)
+
+
+def classify_connect_error(e: BaseException) -> str:
+    """Why connecting failed, as the code sent with connection_error.
+
+    One of peer_offline, refused, timeout, incompatible_version or
+    tls_error; wrong_passphrase comes with auth_failed instead.
+    """
+    if isinstance(e, (asyncio.TimeoutError, TimeoutError)):
+        return "timeout"
+    if isinstance(e, ssl.SSLError):
+        return "tls_error"
+    if isinstance(e, InvalidHandshake):
+        # Something answered, but not as a Deck-Link WebSocket server
+        return "incompatible_version"
+    if isinstance(e, ConnectionRefusedError):
+        return "refused"
+    # Unreachable hosts, unresolvable names, no route
+    return "peer_offline"
+
+
 EventCallback = Callable[[str, dict[str, Any]], None]
 
+# Peer messages handled by _handle_transfer_message
+TRANSFER_MESSAGES = (
+    MessageType.TRANSFER_START,
+    MessageType.TRANSFER_ACCEPT,
+    MessageType.TRANSFER_REJECT,
+    MessageType.TRANSFER_CHUNK,
+    MessageType.TRANSFER_FINISH,
+    MessageType.TRANSFER_CANCEL,
+)
+
 
 class DeckLinkServer:
     """
@@ -65,46 +204,115 @@ class DeckLinkServer:
         device_type: str = "laptop",
         port: int = PORT,
         on_event: Optional[EventCallback] = None,
+        identity: Optional[Identity] = None,
+        bind_addresses: Optional[list[str]] = None,
+        pairing_secrets: Optional[PairingSecrets] = None,
     ):
         self.device_name = device_name
         self.device_type = device_type
         self.port = port
+        # Addresses to listen and dial from; empty for every interface
+        self.bind_addresses = bind_addresses or []
         self.on_event = on_event
+        self.identity = identity
+        # Secrets issued to paired peers; without them every peer pairs anew
+        self.pairing_secrets = pairing_secrets
 
-        # Connection state
+        # State of the handshake in progress, if any; only one peer can be
+        # pairing at a time, however many are already connected
         self._state = ConnectionState.DISCONNECTED
         self._current_session: Optional[ConnectionSession] = None
-        self._peer: Optional[PeerInfo] = None
-        self._websocket: Optional[WebSocketServerProtocol | WebSocketClientProtocol] = (
-            None
-        )
+
+        # Established sessions, by session id
+        self._connections: dict[str, PeerConnection] = {}
 
         # Server
         self._server: Optional[asyncio.Server] = None
         self._running = False
 
-        # Ping/pong
-        self._ping_task: Optional[asyncio.Task[None]] = None
-        self._last_pong: float = 0
+        # Recent failed auth attempts (timestamps), by remote address
+        self._auth_failures: dict[str, list[float]] = {}
+
+        # Outstanding pairing token and its expiry time; only one at a time
+        self._pairing_token: Optional[tuple[str, float]] = None
+
+        # Incoming pairing requests waiting on the user, by session id;
+        # each resolves to None to accept, or the reason it was refused
+        self._pairing_requests: dict[str, asyncio.Future[Optional[str]]] = {}
+
+        # Cap on what we send the peer during file transfers, in kbit/s
+        self.transfer_rate_limit_kbps: Optional[int] = None
+
+        # Transfers we offered, by id, and the session of each one offered
+        # to us; the files themselves are only ever touched on the Tauri side
+        self._outgoing_transfers: dict[str, OutgoingTransfer] = {}
+        self._incoming_transfers: dict[str, str] = {}
 
     @property
     def state(self) -> ConnectionState:
+        """The handshake state while pairing, otherwise whether anyone is connected."""
+        if not self._handshake_in_progress and self._connections:
+            return ConnectionState.CONNECTED
         return self._state
 
     @property
     def is_connected(self) -> bool:
-        return self._state == ConnectionState.CONNECTED
+        return bool(self._connections)
 
     @property
-    def peer(self) -> Optional[PeerInfo]:
-        return self._peer
+    def connections(self) -> list[PeerConnection]:
+        return list(self._connections.values())
+
+    @property
+    def peer_rtt_ms(self) -> Optional[float]:
+        """The slowest round trip among connected peers, if any were measured."""
+        rtts = [conn.rtt_ms for conn in self._connections.values() if conn.rtt_ms is not None]
+        return max(rtts) if rtts else None
+
+    @property
+    def _handshake_in_progress(self) -> bool:
+        return self._state not in (
+            ConnectionState.DISCONNECTED,
+            ConnectionState.CONNECTED,
+            ConnectionState.ERROR,
+        )
+
+    def _connection_for(
+        self, websocket: WebSocketServerProtocol | WebSocketClientProtocol
+    ) -> Optional[PeerConnection]:
+        for conn in self._connections.values():
+            if conn.websocket is websocket:
+                return conn
+        return None
+
+    def _is_handshake_socket(
+        self, websocket: WebSocketServerProtocol | WebSocketClientProtocol
+    ) -> bool:
+        session = self._current_session
+        return bool(session and session.peer_info and session.peer_info.websocket is websocket)
 
     def _emit(self, event: str, data: dict[str, Any]) -> None:
         """Emit an event to the UI."""
-        logger.debug(f"Event: {event} - {data}")
+        redacted = {
+            k: "<redacted>" if k in ("passphrase", "pairing_secret") else v
+            for k, v in data.items()
+        }
+        logger.debug(f"Event: {event} - {redacted}")
         if self.on_event:
             self.on_event(event, data)
 
+    async def _send(
+        self,
+        websocket: WebSocketServerProtocol | WebSocketClientProtocol,
+        message: Message,
+    ) -> None:
+        """Send a message, counting it towards the session's traffic."""
+        data = message.to_json()
+        await websocket.send(data)
+        conn = self._connection_for(websocket)
+        if conn:
+            conn.bytes_sent += len(data.encode())
+
     def _set_state(self, state: ConnectionState) -> None:
         """Update connection state and notify UI."""
         old_state = self._state
@@ -122,24 +330,25 @@ class DeckLinkServer:
         if self._running:
             return
 
+        # No host: listen on every interface, IPv4 and IPv6
         self._server = await serve(
             self._handle_connection,
-            "0.0.0.0",
+            self.bind_addresses or None,
             self.port,
+            max_size=MAX_MESSAGE_BYTES,
         )
         self._running = True
-        logger.info(f"Server started on port {self.port}")
+        if self.bind_addresses:
+            logger.info(f"Server started on {', '.join(self.bind_addresses)} port {self.port}")
+        else:
+            logger.info(f"Server started on port {self.port}")
         self._emit("server_started", {"port": self.port})
 
     async def stop(self) -> None:
         """Stop the server and disconnect."""
-        if self._ping_task:
-            self._ping_task.cancel()
-            self._ping_task = None
-
-        if self._websocket:
-            await self._websocket.close()
-            self._websocket = None
+        for conn in list(self._connections.values()):
+            self._close_connection(conn)
+            await conn.websocket.close()
 
         if self._server:
             self._server.close()
@@ -148,11 +357,21 @@ class DeckLinkServer:
 
         self._running = False
         self._set_state(ConnectionState.DISCONNECTED)
-        self._peer = None
         self._current_session = None
         logger.info("Server stopped")
         self._emit("server_stopped", {})
 
+    async def rebind(self, addresses: list[str]) -> None:
+        """Listen and dial from addresses instead, or everywhere when empty.
+
+        Peers are told we're leaving first, since their connections can't
+        survive the server being replaced.
+        """
+        await self.disconnect_peer()
+        await self.stop()
+        self.bind_addresses = addresses
+        await self.start()
+
     async def _handle_connection(self, websocket: WebSocketServerProtocol) -> None:
         """Handle an incoming WebSocket connection."""
         remote_addr = websocket.remote_address
@@ -160,19 +379,23 @@ class DeckLinkServer:
 
         try:
             async for raw_message in websocket:
+                conn = self._connection_for(websocket)
                 if isinstance(raw_message, bytes):
+                    size = len(raw_message)
                     message = Message.from_bytes(raw_message)
                 else:
+                    size = len(raw_message.encode())
                     message = Message.from_json(raw_message)
+                if conn:
+                    conn.bytes_received += size
 
                 await self._handle_message(message, websocket, is_server=True)
         except ConnectionClosed:
             logger.info(f"Connection closed from {remote_addr}")
-            if self._websocket == websocket:
-                self._handle_disconnect()
+            self._handle_disconnect(websocket)
         except Exception as e:
             logger.error(f"Error handling connection: {e}")
-            self._handle_disconnect()
+            self._handle_disconnect(websocket)
 
     async def _handle_message(
         self,
@@ -199,13 +422,63 @@ class DeckLinkServer:
             await self._handle_ping(message, websocket)
 
         elif message.type == MessageType.PONG:
-            self._handle_pong(message)
+            self._handle_pong(websocket)
 
         elif message.type == MessageType.DISCONNECT:
-            self._handle_disconnect()
+            self._handle_disconnect(websocket)
+
+        elif message.type == MessageType.ERROR and not is_server:
+            # The receiver refused our connection request
+            reason = message.payload.get("message", "Connection refused")
+            self._set_state(ConnectionState.DISCONNECTED)
+            self._current_session = None
+            self._emit("connection_error", {"error": reason, "code": "refused"})
+            await websocket.close()
 
         elif message.type == MessageType.NOTIFICATION:
-            self._emit("notification", message.payload)
+            conn = self._connection_for(websocket)
+            self._emit(
+                "notification",
+                {**message.payload, "session_id": conn.session_id if conn else None},
+            )
+
+        elif message.type == MessageType.CLIPBOARD:
+            conn = self._connection_for(websocket)
+            if conn is None:
+                logger.warning("Clipboard update from outside a session")
+                return
+            self._emit(
+                "clipboard_updated",
+                {
+                    "text": message.payload.get("text", ""),
+                    "origin": message.payload.get("origin", ""),
+                    "session_id": conn.session_id,
+                },
+            )
+
+        elif message.type == MessageType.CLIPBOARD_IMAGE:
+            conn = self._connection_for(websocket)
+            if conn is None:
+                logger.warning("Clipboard image from outside a session")
+                return
+            if len(message.payload.get("png", "")) > MAX_CLIPBOARD_IMAGE_CHARS:
+                logger.warning(f"Ignoring clipboard image over {MAX_CLIPBOARD_IMAGE_CHARS} characters")
+                return
+            self._emit(
+                "clipboard_image",
+                {
+                    "png": message.payload.get("png", ""),
+                    "origin": message.payload.get("origin", ""),
+                    "session_id": conn.session_id,
+                },
+            )
+
+        elif message.type in TRANSFER_MESSAGES:
+            conn = self._connection_for(websocket)
+            if conn is None:
+                logger.warning(f"{message.type.value} from outside a session")
+                return
+            self._handle_transfer_message(message, conn)
 
         else:
             logger.warning(f"Unhandled message type: {message.type}")
@@ -216,10 +489,47 @@ class DeckLinkServer:
         websocket: WebSocketServerProtocol,
     ) -> None:
         """Handle incoming connection request - generate challenge."""
-        if self._state == ConnectionState.CONNECTED:
-            # Already connected, reject
-            err = error("Already connected to another peer", "ALREADY_CONNECTED")
-            await websocket.send(err.to_json())
+        if self._handshake_in_progress:
+            # Pairing is one peer at a time
+            err = error("Device is busy pairing with another peer", "BUSY")
+            await self._send(websocket, err)
+            return
+
+        try:
+            fingerprint = check_proof(message.payload, message.session_id, "client")
+        except ValueError as e:
+            await self._send(websocket, error(str(e), "BAD_IDENTITY"))
+            return
+
+        token = message.payload.get("pairing_token")
+        if token is not None:
+            if not self._consume_pairing_token(token):
+                err = error("Pairing token invalid or expired", "INVALID_TOKEN")
+                await self._send(websocket, err)
+                return
+            await self._accept_paired(message, websocket, fingerprint, issue_secret=True)
+            return
+
+        secret = message.payload.get("pairing_secret")
+        if (
+            secret
+            and fingerprint
+            and self.pairing_secrets
+            and self.pairing_secrets.check(fingerprint, secret)
+        ):
+            await self._accept_paired(message, websocket, fingerprint, issue_secret=False)
+            return
+
+        refusal = await self._await_pairing_approval(message, fingerprint)
+        if refusal is None and self._handshake_in_progress:
+            # Someone else started pairing while the user was deciding
+            refusal = "busy"
+        if refusal is not None:
+            if refusal == "busy":
+                err = error("Device is busy pairing with another peer", "BUSY")
+            else:
+                err = error("Pairing request declined", "DECLINED")
+            await self._send(websocket, err)
             return
 
         # Generate passphrase for challenge
@@ -230,9 +540,10 @@ class DeckLinkServer:
             passphrase=passphrase,
             peer_info=PeerInfo(
                 name=message.payload.get("sender_name", "Unknown"),
-                ip=message.payload.get("sender_ip", ""),
+                ip=message.payload.get("sender_ip") or _remote_ip(websocket),
                 port=message.payload.get("sender_port", PORT),
                 websocket=websocket,
+                fingerprint=fingerprint,
             ),
         )
 
@@ -250,7 +561,131 @@ class DeckLinkServer:
 
         # Send challenge response (without passphrase - that's shown on screen)
         response = challenge_response(message.session_id, self.device_name)
-        await websocket.send(response.to_json())
+        await self._send(websocket, response)
+
+    async def _await_pairing_approval(
+        self, message: Message, fingerprint: Optional[str]
+    ) -> Optional[str]:
+        """Announce an incoming pairing request and wait for the verdict.
+
+        Returns None if accepted, otherwise the reason for refusing.
+        """
+        request_id = message.session_id
+        future: asyncio.Future[Optional[str]] = asyncio.get_running_loop().create_future()
+        self._pairing_requests[request_id] = future
+        self._emit(
+            "pairing_request",
+            {
+                "id": request_id,
+                "peer_name": message.payload.get("sender_name", "Unknown"),
+                "peer_ip": message.payload.get("sender_ip", ""),
+                "peer_port": message.payload.get("sender_port", PORT),
+                "peer_fingerprint": fingerprint,
+            },
+        )
+        try:
+            return await asyncio.wait_for(future, PAIRING_APPROVAL_TIMEOUT_SECONDS)
+        except asyncio.TimeoutError:
+            self._emit("pairing_request_expired", {"id": request_id})
+            return "timeout"
+        finally:
+            self._pairing_requests.pop(request_id, None)
+
+    def resolve_pairing_request(self, request_id: str, refusal: Optional[str]) -> None:
+        """Accept (refusal None) or refuse a request from _await_pairing_approval."""
+        future = self._pairing_requests.get(request_id)
+        if future is None or future.done():
+            raise ValueError(f"No pending pairing request {request_id}")
+        future.set_result(refusal)
+
+    def _add_identity_proof(self, message: Message, role: str) -> None:
+        """Attach our public key and a signature over the session, if we have a key."""
+        if self.identity:
+            message.payload.update(self.identity.proof(message.session_id, role))
+
+    def create_pairing_token(self) -> dict[str, Any]:
+        """Issue a short-lived pairing token, invalidating any previous one."""
+        token = secrets.token_urlsafe(16)
+        expires_at = time.time() + PAIRING_TOKEN_TTL_SECONDS
+        self._pairing_token = (token, expires_at)
+        return {"token": token, "expires_at": expires_at}
+
+    def _consume_pairing_token(self, token: str) -> bool:
+        """Check a presented token; a valid token is single-use."""
+        if not self._pairing_token:
+            return False
+
+        expected, expires_at = self._pairing_token
+        if time.time() > expires_at:
+            self._pairing_token = None
+            return False
+        if not hmac.compare_digest(token.encode(), expected.encode()):
+            return False
+
+        self._pairing_token = None
+        return True
+
+    def _issue_secret(self, result: Message, fingerprint: Optional[str]) -> None:
+        """Give the peer a new pairing secret with its auth result, if it has a key."""
+        if self.pairing_secrets and fingerprint:
+            result.payload["pairing_secret"] = self.pairing_secrets.issue(fingerprint)
+
+    async def _accept_paired(
+        self,
+        message: Message,
+        websocket: WebSocketServerProtocol,
+        fingerprint: Optional[str],
+        issue_secret: bool,
+    ) -> None:
+        """Accept a connection that presented our pairing token or its pairing secret.
+
+        Neither asks the user to approve, and the passphrase is skipped.
+        """
+        peer = PeerInfo(
+            name=message.payload.get("sender_name", "Unknown"),
+            ip=message.payload.get("sender_ip") or _remote_ip(websocket),
+            port=message.payload.get("sender_port", PORT),
+            websocket=websocket,
+            fingerprint=fingerprint,
+        )
+
+        result = auth_result(message.session_id, True, "Connected!")
+        self._add_identity_proof(result, "server")
+        if issue_secret:
+            self._issue_secret(result, fingerprint)
+        await self._send(websocket, result)
+
+        self._establish(message.session_id, peer, websocket)
+
+    def _establish(
+        self,
+        session_id: str,
+        peer: PeerInfo,
+        websocket: WebSocketServerProtocol | WebSocketClientProtocol,
+        pairing_secret: Optional[str] = None,
+    ) -> None:
+        """Add a session that finished its handshake and free the handshake slot.
+
+        pairing_secret is the one the peer issued us, if it did, passed on
+        for the app to keep.
+        """
+        conn = PeerConnection(session_id=session_id, peer=peer, websocket=websocket)
+        self._connections[session_id] = conn
+        self._current_session = None
+        self._set_state(ConnectionState.CONNECTED)
+
+        self._emit(
+            "connected",
+            {
+                "peer_name": peer.name,
+                "peer_ip": peer.ip,
+                "session_id": session_id,
+                "peer_fingerprint": peer.fingerprint,
+                **({"pairing_secret": pairing_secret} if pairing_secret else {}),
+            },
+        )
+        self._start_ping_loop(conn)
+        self._start_stats_loop(conn)
 
     async def _handle_challenge_response(self, message: Message) -> None:
         """Handle challenge response - prompt user for passphrase input."""
@@ -277,44 +712,53 @@ class DeckLinkServer:
         """Handle authentication attempt - verify passphrase."""
         if not self._current_session:
             err = error("No active session", "NO_SESSION")
-            await websocket.send(err.to_json())
+            await self._send(websocket, err)
             return
 
         if message.session_id != self._current_session.session_id:
             err = error("Invalid session", "INVALID_SESSION")
-            await websocket.send(err.to_json())
+            await self._send(websocket, err)
+            return
+
+        remote_ip = _remote_ip(websocket)
+        if self._is_rate_limited(remote_ip):
+            result = auth_result(
+                message.session_id, False, "Too many failed attempts", rate_limited=True
+            )
+            await self._send(websocket, result)
+            self._emit(
+                "auth_failed",
+                {"reason": "Too many failed attempts", "rate_limited": True},
+            )
+            self._set_state(ConnectionState.DISCONNECTED)
+            self._current_session = None
             return
 
         input_passphrase = message.payload.get("passphrase", "")
 
         if validate_passphrase(input_passphrase, self._current_session.passphrase):
             # Success!
-            self._websocket = websocket
-            self._peer = self._current_session.peer_info
-            self._set_state(ConnectionState.CONNECTED)
+            peer = self._current_session.peer_info or PeerInfo(
+                name="Unknown", ip=remote_ip, port=PORT, websocket=websocket
+            )
 
             result = auth_result(message.session_id, True, "Connected!")
-            await websocket.send(result.to_json())
-
-            self._emit(
-                "connected",
-                {
-                    "peer_name": self._peer.name if self._peer else "Unknown",
-                    "peer_ip": self._peer.ip if self._peer else "",
-                },
-            )
+            self._add_identity_proof(result, "server")
+            self._issue_secret(result, peer.fingerprint)
+            await self._send(websocket, result)
 
-            # Start ping loop
-            self._start_ping_loop()
+            self._establish(message.session_id, peer, websocket)
         else:
             # Failed
+            self._auth_failures.setdefault(remote_ip, []).append(time.time())
             result = auth_result(message.session_id, False, "Incorrect passphrase")
-            await websocket.send(result.to_json())
+            await self._send(websocket, result)
 
             self._emit(
                 "auth_failed",
                 {
                     "reason": "Incorrect passphrase",
+                    "code": "wrong_passphrase",
                 },
             )
 
@@ -322,6 +766,13 @@ class DeckLinkServer:
             self._set_state(ConnectionState.DISCONNECTED)
             self._current_session = None
 
+    def _is_rate_limited(self, remote_ip: str) -> bool:
+        """Whether an address has used up its failed attempts for now."""
+        cutoff = time.time() - AUTH_LOCKOUT_SECONDS
+        failures = [t for t in self._auth_failures.get(remote_ip, []) if t > cutoff]
+        self._auth_failures[remote_ip] = failures
+        return len(failures) >= MAX_AUTH_FAILURES
+
     async def _handle_auth_result(
         self,
         message: Message,
@@ -331,25 +782,37 @@ class DeckLinkServer:
         success = message.payload.get("success", False)
 
         if success:
-            self._websocket = websocket
-            self._set_state(ConnectionState.CONNECTED)
-
-            self._emit(
-                "connected",
-                {
-                    "peer_name": self._peer.name if self._peer else "Unknown",
-                    "peer_ip": self._peer.ip if self._peer else "",
-                },
+            try:
+                fingerprint = check_proof(message.payload, message.session_id, "server")
+            except ValueError as e:
+                # Whoever answered can't prove the key it claims
+                self._set_state(ConnectionState.DISCONNECTED)
+                self._current_session = None
+                self._emit("connection_error", {"error": str(e), "code": "tls_error"})
+                await websocket.close()
+                return
+            session = self._current_session
+            peer = session.peer_info if session and session.peer_info else None
+            if peer is None:
+                logger.warning("Authentication result without a handshake in progress")
+                await websocket.close()
+                return
+            peer.fingerprint = fingerprint
+            secret = message.payload.get("pairing_secret")
+            self._establish(
+                message.session_id,
+                peer,
+                websocket,
+                pairing_secret=secret if isinstance(secret, str) else None,
             )
-
-            # Start ping loop
-            self._start_ping_loop()
         else:
             self._set_state(ConnectionState.ERROR)
             self._emit(
                 "auth_failed",
                 {
                     "reason": message.payload.get("message", "Authentication failed"),
+                    "rate_limited": message.payload.get("rate_limited", False),
+                    "code": "wrong_passphrase",
                 },
             )
 
@@ -360,157 +823,474 @@ class DeckLinkServer:
     ) -> None:
         """Respond to ping with pong."""
         response = pong(message.session_id)
-        await websocket.send(response.to_json())
+        await self._send(websocket, response)
 
-    def _handle_pong(self, message: Message) -> None:
+    def _handle_pong(
+        self, websocket: WebSocketServerProtocol | WebSocketClientProtocol
+    ) -> None:
         """Record pong received."""
-        import time
-
-        self._last_pong = time.time()
+        conn = self._connection_for(websocket)
+        if not conn:
+            return
+        conn.last_pong = time.time()
+        if conn.ping_sent_at is not None:
+            conn.rtt_ms = (time.monotonic() - conn.ping_sent_at) * 1000
+            conn.ping_sent_at = None
 
-    def _handle_disconnect(self) -> None:
-        """Handle disconnection."""
-        self._set_state(ConnectionState.DISCONNECTED)
-        self._websocket = None
-        self._peer = None
+    def _handle_disconnect(
+        self, websocket: WebSocketServerProtocol | WebSocketClientProtocol
+    ) -> None:
+        """Handle the session (or the handshake) on websocket going away."""
+        conn = self._connection_for(websocket)
+        if conn:
+            self._close_connection(conn)
+            logger.info(f"Disconnected from {conn.peer.name or conn.peer.ip}")
+            self._emit("disconnected", {"session_id": conn.session_id})
+        elif self._is_handshake_socket(websocket):
+            self._abort_handshake()
+
+    def _abort_handshake(self) -> None:
+        """Give up on the handshake in progress."""
+        session_id = self._current_session.session_id if self._current_session else None
         self._current_session = None
+        self._set_state(ConnectionState.DISCONNECTED)
+        self._emit("disconnected", {"session_id": session_id})
+
+    def _close_connection(self, conn: PeerConnection) -> None:
+        """Forget a session, its transfers and its background tasks."""
+        self._connections.pop(conn.session_id, None)
+        for transfer in [t for t in self._outgoing_transfers.values() if t.conn is conn]:
+            del self._outgoing_transfers[transfer.transfer_id]
+            if not transfer.answer.done():
+                transfer.answer.set_result("Peer disconnected")
+        for transfer_id in [i for i, s in self._incoming_transfers.items() if s == conn.session_id]:
+            del self._incoming_transfers[transfer_id]
+        for task in (conn.ping_task, conn.stats_task):
+            if task:
+                task.cancel()
+        conn.ping_task = None
+        conn.stats_task = None
+
+    def _start_stats_loop(self, conn: PeerConnection) -> None:
+        """Report the session's byte counters every STATS_INTERVAL_SECONDS."""
+
+        async def stats_loop() -> None:
+            while conn.session_id in self._connections:
+                await asyncio.sleep(STATS_INTERVAL_SECONDS)
+                if conn.session_id not in self._connections:
+                    break
+                self._emit(
+                    "stats",
+                    {
+                        "session_id": conn.session_id,
+                        "bytes_sent": conn.bytes_sent,
+                        "bytes_received": conn.bytes_received,
+                    },
+                )
 
-        if self._ping_task:
-            self._ping_task.cancel()
-            self._ping_task = None
-
-        self._emit("disconnected", {})
+        conn.stats_task = asyncio.create_task(stats_loop())
 
-    def _start_ping_loop(self) -> None:
-        """Start the ping/pong heartbeat loop."""
+    def _start_ping_loop(self, conn: PeerConnection) -> None:
+        """Start the ping/pong heartbeat loop for one session."""
 
         async def ping_loop() -> None:
-            import time
-
-            while self._state == ConnectionState.CONNECTED and self._websocket:
+            while conn.session_id in self._connections:
                 try:
-                    self._last_pong = time.time()
-                    await self._websocket.send(ping().to_json())
+                    conn.last_pong = time.time()
+                    if conn.ping_sent_at is not None:
+                        # The previous ping was never answered
+                        conn.rtt_ms = None
+                    conn.ping_sent_at = time.monotonic()
+                    await self._send(conn.websocket, ping())
                     await asyncio.sleep(5)
 
                     # Check if pong was received
-                    if time.time() - self._last_pong > 10:
+                    if time.time() - conn.last_pong > 10:
                         logger.warning("Ping timeout - disconnecting")
-                        self._handle_disconnect()
+                        self._handle_disconnect(conn.websocket)
                         break
                 except Exception as e:
                     logger.error(f"Ping error: {e}")
-                    self._handle_disconnect()
+                    self._handle_disconnect(conn.websocket)
                     break
 
-        self._ping_task = asyncio.create_task(ping_loop())
+        conn.ping_task = asyncio.create_task(ping_loop())
 
     # Client-side methods (for initiating connections)
 
-    async def connect_to(self, host: str, port: int = PORT) -> None:
-        """Initiate a connection to another Deck-Link instance."""
-        if self._state != ConnectionState.DISCONNECTED:
-            logger.warning("Already connected or connecting")
-            return
+    async def connect_to(
+        self,
+        host: str,
+        port: int = PORT,
+        pairing_token: Optional[str] = None,
+        pairing_secret: Optional[str] = None,
+    ) -> Optional[str]:
+        """Initiate a connection to another Deck-Link instance.
+
+        A pairing_secret the peer issued us earlier skips the passphrase; if
+        the peer no longer knows it, pairing goes ahead as usual.
+
+        Returns the new session's id, or None if the peer couldn't be reached.
+        Peers already connected stay connected.
+        """
+        if self._handshake_in_progress:
+            raise RuntimeError("Already connecting to another peer")
+        host = normalize_host(host)
 
         self._set_state(ConnectionState.AWAITING_CHALLENGE)
 
         try:
-            uri = f"ws://{host}:{port}"
-            websocket = await connect(uri)
+            websocket = await asyncio.wait_for(
+                connect(
+                    ws_uri(host, port),
+                    max_size=MAX_MESSAGE_BYTES,
+                    **connect_kwargs(host, self.bind_addresses),
+                ),
+                CONNECT_TIMEOUT_SECONDS,
+            )
 
-            self._peer = PeerInfo(name="", ip=host, port=port, websocket=websocket)
+            peer = PeerInfo(name="", ip=host, port=port, websocket=websocket)
 
             # Send connection request
             request = connection_request(
                 sender_name=self.device_name,
                 sender_ip="",  # Will be determined by receiver
                 sender_port=self.port,
+                pairing_token=pairing_token,
+                pairing_secret=pairing_secret,
             )
-            await websocket.send(request.to_json())
+            self._add_identity_proof(request, "client")
+            await self._send(websocket, request)
 
             # Store session info
             self._current_session = ConnectionSession(
                 session_id=request.session_id,
                 passphrase="",  # We don't know it yet
-                peer_info=self._peer,
+                peer_info=peer,
             )
 
             # Start listening for responses
             asyncio.create_task(self._client_listen(websocket))
+            return request.session_id
 
         except Exception as e:
             logger.error(f"Connection failed: {e}")
             self._set_state(ConnectionState.ERROR)
-            self._emit("connection_error", {"error": str(e)})
+            self._emit("connection_error", {"error": str(e), "code": classify_connect_error(e)})
+            return None
 
     async def _client_listen(self, websocket: WebSocketClientProtocol) -> None:
         """Listen for messages as a client."""
         try:
             async for raw_message in websocket:
+                conn = self._connection_for(websocket)
                 if isinstance(raw_message, bytes):
+                    size = len(raw_message)
                     message = Message.from_bytes(raw_message)
                 else:
+                    size = len(raw_message.encode())
                     message = Message.from_json(raw_message)
+                if conn:
+                    conn.bytes_received += size
 
                 await self._handle_message(message, websocket, is_server=False)
         except ConnectionClosed:
             logger.info("Client connection closed")
-            self._handle_disconnect()
+            self._handle_disconnect(websocket)
         except Exception as e:
             logger.error(f"Client error: {e}")
-            self._handle_disconnect()
+            self._handle_disconnect(websocket)
 
     async def submit_passphrase(self, passphrase: str) -> None:
         """Submit passphrase (called from UI after user enters it)."""
         if self._state != ConnectionState.AWAITING_AUTH_INPUT:
-            logger.warning("Not awaiting passphrase input")
-            return
+            raise RuntimeError("Not awaiting passphrase input")
 
-        if not self._current_session or not self._peer or not self._peer.websocket:
-            logger.error("No active session")
-            return
+        session = self._current_session
+        if not session or not session.peer_info or not session.peer_info.websocket:
+            raise RuntimeError("No active session")
 
-        attempt = auth_attempt(self._current_session.session_id, passphrase)
-        await self._peer.websocket.send(attempt.to_json())
+        attempt = auth_attempt(session.session_id, passphrase)
+        await self._send(session.peer_info.websocket, attempt)
+
+    async def probe_peer(self, host: str, port: int = PORT) -> dict[str, Any]:
+        """Check that a Deck-Link instance answers at host:port.
+
+        Only the WebSocket handshake is done: no connection request is
+        sent, so neither side starts a session, and the socket is closed
+        straight after.
+        """
+        host = normalize_host(host)
+        started = time.monotonic()
+        try:
+            websocket = await asyncio.wait_for(
+                connect(ws_uri(host, port), **connect_kwargs(host, self.bind_addresses)), PROBE_TIMEOUT_SECONDS
+            )
+        except asyncio.TimeoutError:
+            return {"reachable": False, "rtt_ms": None, "error": "timed out"}
+        except Exception as e:
+            return {"reachable": False, "rtt_ms": None, "error": str(e) or type(e).__name__}
+        rtt_ms = (time.monotonic() - started) * 1000
+        # Don't hold up the command loop on the closing handshake
+        asyncio.create_task(websocket.close())
+        return {"reachable": True, "rtt_ms": rtt_ms, "error": None}
+
+    async def disconnect_peer(self, session_id: Optional[str] = None) -> None:
+        """Disconnect one session, or every session and any handshake when None."""
+        if session_id is None:
+            targets = list(self._connections.values())
+        elif session_id in self._connections:
+            targets = [self._connections[session_id]]
+        elif self._current_session and self._current_session.session_id == session_id:
+            targets = []
+        else:
+            raise ValueError(f"No session {session_id}")
 
-    async def disconnect_peer(self) -> None:
-        """Disconnect from current peer."""
-        if self._websocket:
+        for conn in targets:
             try:
-                await self._websocket.send(disconnect().to_json())
-                await self._websocket.close()
+                await self._send(conn.websocket, disconnect())
+                await conn.websocket.close()
             except Exception:
                 pass
+            self._handle_disconnect(conn.websocket)
 
-        self._handle_disconnect()
+        session = self._current_session
+        if session and session_id in (None, session.session_id):
+            if session.peer_info and session.peer_info.websocket:
+                try:
+                    await session.peer_info.websocket.close()
+                except Exception:
+                    pass
+            # Closing may already have aborted it, from the listener
+            if self._current_session is session:
+                self._abort_handshake()
 
-    async def send_notification(self, title: str, body: str) -> None:
-        """Send a notification to the connected peer."""
-        if not self.is_connected or not self._websocket:
-            logger.warning("Not connected")
-            return
+    async def verify_connections(self) -> list[str]:
+        """Ping every session now, dropping the ones that don't answer.
+
+        Returns the ids of the sessions still alive; each dropped one is
+        reported as disconnected.
+        """
+
+        async def alive(conn: PeerConnection) -> bool:
+            try:
+                waiter = await conn.websocket.ping()
+                await asyncio.wait_for(waiter, VERIFY_TIMEOUT_SECONDS)
+                return True
+            except Exception as e:
+                logger.warning(f"{conn.peer.name or conn.peer.ip} stopped answering: {e}")
+                return False
+
+        connections = list(self._connections.values())
+        results = await asyncio.gather(*(alive(conn) for conn in connections))
+        for conn, ok in zip(connections, results):
+            if not ok:
+                self._handle_disconnect(conn.websocket)
+                # A dead peer never finishes the closing handshake; don't wait on it
+                asyncio.create_task(conn.websocket.close())
+        return [conn.session_id for conn, ok in zip(connections, results) if ok]
+
+    def _resolve_session(self, session_id: Optional[str]) -> PeerConnection:
+        """The session to talk to: session_id, or the only one if it is None."""
+        if session_id is not None:
+            conn = self._connections.get(session_id)
+            if conn is None:
+                raise RuntimeError(f"Not connected to session {session_id}")
+            return conn
+        if not self._connections:
+            raise RuntimeError("Not connected")
+        if len(self._connections) > 1:
+            raise ValueError("Several peers are connected; pass a session_id")
+        return next(iter(self._connections.values()))
+
+    async def send_notification(
+        self,
+        title: str,
+        body: str,
+        priority: Optional[str] = None,
+        session_id: Optional[str] = None,
+    ) -> None:
+        """Send a notification to a connected peer."""
+        conn = self._resolve_session(session_id)
 
         from .protocol import notification
 
-        msg = notification(title, body)
-        await self._websocket.send(msg.to_json())
+        msg = notification(title, body, priority=priority)
+        await self._send(conn.websocket, msg)
+
+    async def _broadcast(self, message: Message) -> None:
+        """Send a message to every connected peer."""
+        if not self._connections:
+            raise RuntimeError("Not connected")
+        connections = list(self._connections.values())
+        results = await asyncio.gather(
+            *(self._send(conn.websocket, message) for conn in connections), return_exceptions=True
+        )
+        for conn, result in zip(connections, results):
+            if isinstance(result, Exception):
+                peer = conn.peer.name or conn.peer.ip
+                logger.warning(f"Could not send {message.type.value} to {peer}: {result}")
+
+    async def send_clipboard(self, text: str, origin: str) -> None:
+        """Share clipboard text with every connected peer."""
+        await self._broadcast(clipboard(text, origin))
+
+    async def send_clipboard_image(self, png: str, origin: str) -> None:
+        """Share a clipboard image, as base64 PNG, with every connected peer."""
+        if len(png) > MAX_CLIPBOARD_IMAGE_CHARS:
+            raise ValueError(f"Clipboard image is over {MAX_CLIPBOARD_IMAGE_CHARS} characters of base64")
+        await self._broadcast(clipboard_image(png, origin))
+
+    # File transfers
+
+    def _handle_transfer_message(self, message: Message, conn: PeerConnection) -> None:
+        """Act on a transfer message from the peer in session conn."""
+        transfer_id = message.payload.get("id", "")
+        outgoing = self._outgoing_transfers.get(transfer_id)
+        if outgoing is not None and outgoing.conn is not conn:
+            outgoing = None
+        incoming = self._incoming_transfers.get(transfer_id) == conn.session_id
+
+        if message.type == MessageType.TRANSFER_START:
+            if transfer_id in self._incoming_transfers or outgoing is not None:
+                logger.warning(f"Ignoring repeated offer of transfer {transfer_id}")
+                return
+            self._incoming_transfers[transfer_id] = conn.session_id
+            self._emit(
+                "transfer_request",
+                {
+                    "id": transfer_id,
+                    "filename": message.payload.get("filename", ""),
+                    "size": message.payload.get("size", 0),
+                    "session_id": conn.session_id,
+                    "peer_name": conn.peer.name,
+                },
+            )
+
+        elif message.type in (MessageType.TRANSFER_ACCEPT, MessageType.TRANSFER_REJECT) and outgoing:
+            if not outgoing.answer.done():
+                accepted = message.type == MessageType.TRANSFER_ACCEPT
+                outgoing.answer.set_result(None if accepted else "Peer rejected the transfer")
+
+        elif message.type == MessageType.TRANSFER_CHUNK and incoming:
+            self._emit(
+                "transfer_chunk",
+                {
+                    "id": transfer_id,
+                    "offset": message.payload.get("offset", 0),
+                    "data": message.payload.get("data", ""),
+                    "session_id": conn.session_id,
+                },
+            )
+
+        elif message.type in (MessageType.TRANSFER_FINISH, MessageType.TRANSFER_CANCEL) and incoming:
+            del self._incoming_transfers[transfer_id]
+            self._emit(message.type.value, {"id": transfer_id, "session_id": conn.session_id})
+
+        elif message.type == MessageType.TRANSFER_CANCEL and outgoing:
+            # The next chunk fails, and the Tauri side gives up on the file
+            outgoing.cancelled = True
+            if not outgoing.answer.done():
+                outgoing.answer.set_result("Peer cancelled the transfer")
+
+        else:
+            logger.warning(f"Ignoring {message.type.value} for unknown transfer {transfer_id}")
+
+    def _outgoing(self, transfer_id: str, session_id: Optional[str] = None) -> OutgoingTransfer:
+        """An offered transfer that may still be sent, in session_id if given."""
+        transfer = self._outgoing_transfers.get(transfer_id)
+        if transfer is None or session_id not in (None, transfer.conn.session_id):
+            raise ValueError(f"No transfer {transfer_id}")
+        if transfer.cancelled:
+            raise RuntimeError("Peer cancelled the transfer")
+        return transfer
+
+    async def start_transfer(
+        self, transfer_id: str, filename: str, size: int, session_id: Optional[str] = None
+    ) -> None:
+        """Offer a file to a connected peer; chunks follow once it accepts."""
+        conn = self._resolve_session(session_id)
+        if transfer_id in self._outgoing_transfers:
+            raise ValueError(f"Transfer {transfer_id} already started")
+        answer: asyncio.Future[Optional[str]] = asyncio.get_running_loop().create_future()
+        self._outgoing_transfers[transfer_id] = OutgoingTransfer(transfer_id, conn, answer)
+        try:
+            await self._send(conn.websocket, transfer_start(transfer_id, filename, size))
+        except Exception:
+            self._outgoing_transfers.pop(transfer_id, None)
+            raise
+
+    async def send_transfer_chunk(
+        self, transfer_id: str, offset: int, data: str, session_id: Optional[str] = None
+    ) -> None:
+        """Send a chunk of base64 data, first waiting for the peer to accept."""
+        transfer = self._outgoing(transfer_id, session_id)
+        try:
+            # Shielded so a cancelled request doesn't lose the peer's answer
+            refusal = await asyncio.wait_for(
+                asyncio.shield(transfer.answer), TRANSFER_ACCEPT_TIMEOUT_SECONDS
+            )
+        except asyncio.TimeoutError:
+            raise RuntimeError("Peer did not accept the transfer in time")
+        if refusal is not None:
+            self._outgoing_transfers.pop(transfer_id, None)
+            raise RuntimeError(refusal)
+        transfer = self._outgoing(transfer_id, session_id)
+        await self._send(transfer.conn.websocket, transfer_chunk(transfer_id, offset, data))
+
+    async def finish_transfer(self, transfer_id: str, session_id: Optional[str] = None) -> None:
+        """Tell the peer every chunk of an accepted transfer has been sent."""
+        transfer = self._outgoing(transfer_id, session_id)
+        if not transfer.answer.done() or transfer.answer.result() is not None:
+            raise RuntimeError(f"Transfer {transfer_id} was never accepted")
+        del self._outgoing_transfers[transfer_id]
+        await self._send(transfer.conn.websocket, transfer_control(MessageType.TRANSFER_FINISH, transfer_id))
+
+    async def answer_transfer(
+        self, transfer_id: str, accept: bool, session_id: Optional[str] = None
+    ) -> None:
+        """Accept or reject a transfer a peer offered us, in session_id if given."""
+        offered_in = self._incoming_transfers.get(transfer_id)
+        conn = self._connections.get(offered_in) if offered_in else None
+        if conn is None or session_id not in (None, offered_in):
+            raise ValueError(f"No pending transfer {transfer_id}")
+        if not accept:
+            del self._incoming_transfers[transfer_id]
+        kind = MessageType.TRANSFER_ACCEPT if accept else MessageType.TRANSFER_REJECT
+        await self._send(conn.websocket, transfer_control(kind, transfer_id))
+
+    async def cancel_transfer(self, transfer_id: str) -> None:
+        """Give up on a transfer in either direction, telling the peer.
+
+        Transfers already over are ignored: the peer may well have ended
+        the transfer first.
+        """
+        outgoing = self._outgoing_transfers.pop(transfer_id, None)
+        session_id = outgoing.conn.session_id if outgoing else self._incoming_transfers.pop(transfer_id, None)
+        conn = self._connections.get(session_id) if session_id else None
+        if outgoing and not outgoing.answer.done():
+            outgoing.answer.set_result("Transfer cancelled")
+        if conn:
+            await self._send(conn.websocket, transfer_control(MessageType.TRANSFER_CANCEL, transfer_id))
 
     def get_status(self) -> dict[str, Any]:
         """Get current status for UI."""
+        connections = [conn.to_dict() for conn in self._connections.values()]
+        # The most recent session, for callers that only know about one peer
+        latest = connections[-1] if connections else None
         return {
-            "state": self._state.value,
+            "state": self.state.value,
             "device_name": self.device_name,
             "device_type": self.device_type,
             "port": self.port,
+            "fingerprint": self.identity.fingerprint if self.identity else None,
+            "connections": connections,
             "peer": {
-                "name": self._peer.name,
-                "ip": self._peer.ip,
-                "port": self._peer.port,
+                "name": latest["name"],
+                "ip": latest["ip"],
+                "port": latest["port"],
             }
-            if self._peer
-            else None,
-            "session_id": self._current_session.session_id
-            if self._current_session
+            if latest
             else None,
+            "session_id": latest["session_id"] if latest else None,
         }
@@ -0,0 +1,135 @@
+//! IP addresses and hostnames, IPv4 and IPv6 alike.
+//!
+//! Hosts are normalized once, where they enter (commands, links), so the
+//! rest of the app can compare them as strings: IPv6 in its compressed
+//! form without brackets, IPv4-mapped IPv6 as plain IPv4, and a link-local
+//! address with the zone (`%eth0`) it is only reachable through.
+
+use std::net::{IpAddr, Ipv6Addr};
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::messages::Text;
+use crate::RpcError;
+
+/// One of this device's addresses, as reported in `local_info`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LocalAddress {
+    pub ip: String,
+    /// 4 or 6.
+    pub family: u8,
+    /// The address peers should try first; exactly one is primary.
+    pub primary: bool,
+    pub link_local: bool,
+    /// The interface it belongs to, e.g. `eth0`; `None` if it wasn't
+    /// among those listed.
+    pub interface: Option<String>,
+}
+
+/// `host` as an IP address or RFC 1123 hostname in canonical form, or an
+/// error saying why it is neither. Brackets around IPv6 are accepted.
+pub fn normalize_host(host: &str) -> Result<String, RpcError> {
+    let invalid = || RpcError::InvalidInput(Text::new("input.invalid_host").with("host", host));
+    let trimmed = host.trim();
+    let unbracketed = trimmed
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(trimmed);
+
+    if let Some((ip, zone)) = unbracketed.split_once('%') {
+        let ip: Ipv6Addr = ip.parse().map_err(|_| invalid())?;
+        let valid_zone = !zone.is_empty()
+            && zone.len() <= 64
+            && zone.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        if !valid_zone {
+            return Err(invalid());
+        }
+        // A zone only means something on a link-local address
+        return Ok(if is_link_local(&IpAddr::V6(ip)) {
+            format!("{}%{}", ip, zone)
+        } else {
+            ip.to_string()
+        });
+    }
+    if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+        return Ok(canonical(ip).to_string());
+    }
+    if unbracketed != trimmed {
+        return Err(invalid());
+    }
+
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    let name = trimmed.strip_suffix('.').unwrap_or(trimmed);
+    if name.is_empty() || name.len() > 253 || !name.split('.').all(valid_label) {
+        return Err(invalid());
+    }
+    Ok(name.to_ascii_lowercase())
+}
+
+/// IPv4-mapped IPv6 (`::ffff:192.0.2.1`) as the IPv4 address it maps.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_link_local(),
+        IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+/// Every address of this device except loopback, the primary first, then
+/// IPv4 before IPv6, routable before link-local, and otherwise in address
+/// order so the list is the same from one call to the next. `primary` is
+/// the address the network monitor settled on, when there is one.
+pub fn local_addresses(primary: Option<IpAddr>) -> Vec<LocalAddress> {
+    let mut found: Vec<(String, IpAddr)> = match local_ip_address::list_afinet_netifas() {
+        Ok(interfaces) => interfaces,
+        Err(e) => {
+            warn!("Cannot list network interfaces: {}", e);
+            Vec::new()
+        }
+    };
+    if let Some(ip) = primary {
+        if !found.iter().any(|(_, found)| *found == ip) {
+            found.push((String::new(), ip));
+        }
+    }
+    found.retain(|(_, ip)| !ip.is_loopback() && !ip.is_unspecified());
+
+    let mut addresses: Vec<(IpAddr, LocalAddress)> = found
+        .into_iter()
+        .map(|(interface, ip)| {
+            let ip = canonical(ip);
+            let link_local = is_link_local(&ip);
+            let text = match ip {
+                IpAddr::V6(_) if link_local && !interface.is_empty() => format!("{}%{}", ip, interface),
+                _ => ip.to_string(),
+            };
+            let family = if ip.is_ipv4() { 4 } else { 6 };
+            let interface = Some(interface).filter(|interface| !interface.is_empty());
+            (ip, LocalAddress { ip: text, family, primary: false, link_local, interface })
+        })
+        .collect();
+    addresses.sort_by(|(a, a_info), (b, b_info)| {
+        (Some(*a) != primary, a_info.family, a_info.link_local, a, &a_info.ip)
+            .cmp(&(Some(*b) != primary, b_info.family, b_info.link_local, b, &b_info.ip))
+    });
+    addresses.dedup_by(|(_, a), (_, b)| a.ip == b.ip);
+
+    let mut addresses: Vec<LocalAddress> = addresses.into_iter().map(|(_, address)| address).collect();
+    if let Some(first) = addresses.first_mut() {
+        first.primary = true;
+    }
+    addresses
+}
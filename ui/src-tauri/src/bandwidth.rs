@@ -0,0 +1,160 @@
+//! Traffic over peer sessions.
+//!
+//! While connected the sidecar reports each session's running byte
+//! counters as `stats` events, about once a second. This turns them into
+//! totals and current throughput, keeps the last minute of throughput for
+//! a sparkline, and passes each update on as `sidecar:bandwidth`.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+
+use crate::{emit_event, unix_millis, RpcError, SharedState};
+
+/// Samples kept for the sparkline.
+const HISTORY: usize = 60;
+
+/// Traffic over one session; see `connections`.
+#[derive(Default)]
+pub struct Bandwidth {
+    bytes_sent: u64,
+    bytes_received: u64,
+    /// When the counters were last updated.
+    sampled_at: Option<Instant>,
+    history: VecDeque<Sample>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Sample {
+    /// Unix time (ms) of the sample.
+    pub at: u64,
+    /// Bytes per second.
+    pub upload: f64,
+    pub download: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BandwidthReport {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Current throughput in bytes per second.
+    pub upload: f64,
+    pub download: f64,
+    /// Recent throughput, oldest first.
+    pub history: Vec<Sample>,
+}
+
+impl BandwidthReport {
+    /// Add another session's traffic to this one. Histories are lined up
+    /// from their newest samples, which the sidecar takes at the same pace.
+    fn add(&mut self, other: BandwidthReport) {
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.upload += other.upload;
+        self.download += other.download;
+        let mut shorter = other.history;
+        if shorter.len() > self.history.len() {
+            std::mem::swap(&mut shorter, &mut self.history);
+        }
+        let offset = self.history.len() - shorter.len();
+        for (sample, theirs) in self.history[offset..].iter_mut().zip(shorter) {
+            sample.upload += theirs.upload;
+            sample.download += theirs.download;
+        }
+    }
+}
+
+impl Bandwidth {
+    fn report(&self) -> BandwidthReport {
+        let current = self.history.back().copied().unwrap_or_default();
+        BandwidthReport {
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            upload: current.upload,
+            download: current.download,
+            history: self.history.iter().copied().collect(),
+        }
+    }
+
+    fn update(&mut self, bytes_sent: u64, bytes_received: u64) {
+        if bytes_sent < self.bytes_sent || bytes_received < self.bytes_received {
+            // Counters only grow; start over rather than report nonsense
+            *self = Self::default();
+        }
+
+        let now = Instant::now();
+        if let Some(previous) = self.sampled_at {
+            let elapsed = now.duration_since(previous).as_secs_f64();
+            if elapsed > 0.0 {
+                if self.history.len() >= HISTORY {
+                    self.history.pop_front();
+                }
+                self.history.push_back(Sample {
+                    at: unix_millis(),
+                    upload: (bytes_sent - self.bytes_sent) as f64 / elapsed,
+                    download: (bytes_received - self.bytes_received) as f64 / elapsed,
+                });
+            }
+        }
+        self.sampled_at = Some(now);
+        self.bytes_sent = bytes_sent;
+        self.bytes_received = bytes_received;
+    }
+}
+
+/// A session's cumulative byte counters, as the sidecar reports them.
+#[derive(Deserialize)]
+pub struct Stats {
+    pub session_id: Option<String>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Fold in a `stats` event. Returns whether it should still be forwarded,
+/// which it isn't: `sidecar:bandwidth` replaces it.
+pub async fn handle_event(app: &AppHandle, stats: Stats) -> bool {
+    let Some(session_id) = stats.session_id.as_deref() else {
+        return false;
+    };
+
+    let report = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        // Counters still in flight from a session that just ended are dropped
+        let Some(connection) = state.connections.get_mut(session_id) else {
+            return false;
+        };
+        connection.bandwidth.update(stats.bytes_sent, stats.bytes_received);
+        connection.bandwidth.report()
+    };
+    match serde_json::to_value(report) {
+        Ok(mut report) => {
+            report["session_id"] = session_id.into();
+            emit_event(app, "sidecar:bandwidth", report).await
+        }
+        Err(e) => warn!("Failed to serialize bandwidth report: {}", e),
+    }
+    false
+}
+
+/// Totals and throughput for session `session_id`, or for all sessions
+/// together without one; all zeros while disconnected.
+#[tauri::command]
+pub async fn get_bandwidth_stats(
+    state: tauri::State<'_, SharedState>,
+    session_id: Option<String>,
+) -> Result<BandwidthReport, RpcError> {
+    let state = state.lock().await;
+    let mut total = BandwidthReport::default();
+    let sessions = state
+        .connections
+        .values()
+        .filter(|connection| session_id.as_ref().is_none_or(|id| *id == connection.session_id));
+    for connection in sessions {
+        total.add(connection.bandwidth.report());
+    }
+    Ok(total)
+}
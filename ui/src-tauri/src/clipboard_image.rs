@@ -0,0 +1,166 @@
+//! Images on the clipboard, shared as PNG.
+//!
+//! The clipboard plugin hands out raw RGBA pixels, so images are encoded
+//! to PNG before they leave and decoded again when they arrive. They carry
+//! the same `origin` as text updates, so an image doesn't bounce back to
+//! the device it was copied on.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tauri::image::Image;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tracing::{debug, error, warn};
+
+use crate::messages::Text;
+use crate::{call_sidecar, emit_event, RpcError, SharedState};
+
+/// Largest encoded image we send or accept. As base64 it still has to fit
+/// in one line of sidecar output, `ipc::MAX_LINE_LEN`.
+const MAX_IMAGE_BYTES: usize = 700 * 1024;
+
+#[derive(Serialize, Deserialize)]
+pub struct ClipboardImage {
+    /// Base64 PNG.
+    png: String,
+    origin: String,
+}
+
+/// Decoded RGBA pixels.
+pub struct Pixels {
+    rgba: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl Pixels {
+    /// Identifies the picture itself, since re-encoding needn't give the
+    /// same PNG bytes.
+    fn digest(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.width, self.height, &self.rgba).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// The image on the local clipboard, if there is one the platform can
+/// give us.
+pub fn read_local(app: &AppHandle) -> Option<Pixels> {
+    match app.clipboard().read_image() {
+        Ok(image) => Some(Pixels {
+            rgba: image.rgba().to_vec(),
+            width: image.width(),
+            height: image.height(),
+        }),
+        Err(e) => {
+            debug!("No clipboard image: {}", e);
+            None
+        }
+    }
+}
+
+/// Send `pixels` to the peer.
+pub async fn send(state: &SharedState, pixels: Pixels) -> Result<(), RpcError> {
+    // Re-sending an image we just received keeps its origin, as for text
+    let digest = pixels.digest();
+    let origin = {
+        let state = state.lock().await;
+        match &state.last_remote_image {
+            Some((last, origin)) if *last == digest => origin.clone(),
+            _ => state.origin_id.clone(),
+        }
+    };
+    send_as(state, pixels, origin).await
+}
+
+/// Send `pixels` to the peer as first copied at `origin`.
+pub async fn send_as(state: &SharedState, pixels: Pixels, origin: String) -> Result<(), RpcError> {
+    let png = encode(&pixels)
+        .map_err(|e| RpcError::InvalidInput(Text::detail("input.image_unencodable", e)))?;
+    if png.len() > MAX_IMAGE_BYTES {
+        return Err(RpcError::InvalidInput(Text::new("input.image_too_large").with("max_bytes", MAX_IMAGE_BYTES)));
+    }
+
+    let image = ClipboardImage {
+        png: base64::engine::general_purpose::STANDARD.encode(png),
+        origin,
+    };
+    call_sidecar(state, "set_clipboard_image", serde_json::to_value(image)?).await?;
+    Ok(())
+}
+
+/// Write a peer's clipboard image to the local clipboard.
+///
+/// Never forwards the raw event, which would push megabytes of base64 to
+/// the frontend; `sidecar:clipboard_image` carries just the dimensions.
+pub async fn apply_remote(app: &AppHandle, image: ClipboardImage) -> bool {
+    // Base64 is 4/3 the size of what it encodes
+    if image.png.len() > MAX_IMAGE_BYTES / 3 * 4 + 4 {
+        warn!("Ignoring clipboard image over {} bytes", MAX_IMAGE_BYTES);
+        return false;
+    }
+    let pixels = match base64::engine::general_purpose::STANDARD
+        .decode(&image.png)
+        .map_err(|e| e.to_string())
+        .and_then(|png| decode(&png))
+    {
+        Ok(pixels) => pixels,
+        Err(e) => {
+            warn!("Ignoring unreadable clipboard image: {}", e);
+            return false;
+        }
+    };
+
+    {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        if !state.settings.clipboard_sync_enabled || image.origin == state.origin_id {
+            return false;
+        }
+        state.last_remote_image = Some((pixels.digest(), image.origin));
+    }
+
+    let (width, height) = (pixels.width, pixels.height);
+    let local = Image::new_owned(pixels.rgba, width, height);
+    if let Err(e) = app.clipboard().write_image(&local) {
+        error!("Failed to write clipboard image: {}", e);
+        return false;
+    }
+    emit_event(app, "sidecar:clipboard_image", serde_json::json!({
+        "width": width,
+        "height": height
+    }))
+    .await;
+    false
+}
+
+fn encode(pixels: &Pixels) -> Result<Vec<u8>, png::EncodingError> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, pixels.width, pixels.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels.rgba)?;
+    Ok(png)
+}
+
+fn decode(png: &[u8]) -> Result<Pixels, String> {
+    let mut decoder = png::Decoder::new(png);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    buf.truncate(info.buffer_size());
+
+    // Everything is 8-bit after normalising; only the channels differ
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err("unsupported PNG colour type".to_string()),
+    };
+    Ok(Pixels { rgba, width: info.width, height: info.height })
+}
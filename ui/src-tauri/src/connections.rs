@@ -0,0 +1,115 @@
+//! Peers we're connected to.
+//!
+//! Several peers can be connected at once, each in a session of its own.
+//! The sidecar names sessions in its `connected` event and tags the events
+//! that belong to one with its `session_id`; connections are kept here by
+//! that id. Only one connection can be in its handshake at a time.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::bandwidth::Bandwidth;
+use crate::events::Connected;
+use crate::{trust, unix_millis, AppState, RpcError, SharedState};
+
+pub struct PeerConnection {
+    pub session_id: String,
+    pub name: String,
+    pub ip: String,
+    /// Identity fingerprint the peer presented, if it sent one.
+    pub fingerprint: Option<String>,
+    /// Discovery id, if the peer matches a discovered one.
+    pub peer_id: Option<String>,
+    /// Unix time (ms) the session was established.
+    pub connected_at: u64,
+    pub bandwidth: Bandwidth,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionInfo {
+    pub session_id: String,
+    pub name: String,
+    pub ip: String,
+    pub fingerprint: Option<String>,
+    pub fingerprint_display: Option<String>,
+    pub peer_id: Option<String>,
+    pub connected_at: u64,
+}
+
+impl PeerConnection {
+    /// The connection a `connected` event announces, or `None` if it has
+    /// no session id.
+    pub fn from_event(state: &AppState, event: &Connected) -> Option<Self> {
+        let ip = event.peer_ip.clone().unwrap_or_default();
+        let peer_id = state.peers.values().find(|peer| peer.ip == ip).map(|peer| peer.id.clone());
+        Some(Self {
+            session_id: event.session_id.clone()?,
+            name: event.peer_name.clone(),
+            ip,
+            fingerprint: event.peer_fingerprint.clone(),
+            peer_id,
+            connected_at: unix_millis(),
+            bandwidth: Bandwidth::default(),
+        })
+    }
+
+    pub fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            session_id: self.session_id.clone(),
+            name: self.name.clone(),
+            ip: self.ip.clone(),
+            fingerprint: self.fingerprint.clone(),
+            fingerprint_display: self.fingerprint.as_deref().map(trust::display),
+            peer_id: self.peer_id.clone(),
+            connected_at: self.connected_at,
+        }
+    }
+}
+
+/// The connection an event is about: the one its `session_id` names, or
+/// the only one if it names none.
+pub fn for_event<'a>(state: &'a AppState, data: &serde_json::Value) -> Option<&'a PeerConnection> {
+    match data.get("session_id").and_then(|id| id.as_str()) {
+        Some(session_id) => state.connections.get(session_id),
+        None => only(&state.connections),
+    }
+}
+
+fn only(connections: &HashMap<String, PeerConnection>) -> Option<&PeerConnection> {
+    let mut all = connections.values();
+    match (all.next(), all.next()) {
+        (Some(connection), None) => Some(connection),
+        _ => None,
+    }
+}
+
+/// Whether we're connected to the peer with discovery id `peer_id`, or at `ip`.
+pub fn is_connected_to(state: &AppState, peer_id: Option<&str>, ip: &str) -> bool {
+    state
+        .connections
+        .values()
+        .any(|connection| connection.ip == ip || (peer_id.is_some() && connection.peer_id.as_deref() == peer_id))
+}
+
+/// Who we're connected to, for the tray: the peer's name, or how many
+/// peers there are.
+pub fn label(state: &AppState) -> Option<String> {
+    match state.connections.len() {
+        0 => None,
+        1 => only(&state.connections).map(|connection| connection.name.clone()),
+        count => Some(format!("{} devices", count)),
+    }
+}
+
+/// Every established connection, oldest first.
+#[tauri::command]
+pub async fn get_connections(
+    state: tauri::State<'_, SharedState>,
+) -> Result<Vec<ConnectionInfo>, RpcError> {
+    let state = state.lock().await;
+    let mut connections: Vec<ConnectionInfo> =
+        state.connections.values().map(PeerConnection::info).collect();
+    connections.sort_by_key(|connection| connection.connected_at);
+    Ok(connections)
+}
@@ -0,0 +1,138 @@
+//! Where settings, logs and the transfer history live.
+//!
+//! By default that's Tauri's per-app config, log and data dirs. For
+//! portable installs (and tests) everything can instead go under one
+//! directory, named by `PATCH_DATA_DIR` or set with `set_data_dir`. The
+//! latter is remembered in a small file in the default config dir, since
+//! settings can't say where settings are; it applies from the next launch.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+
+use crate::messages::Text;
+use crate::RpcError;
+
+/// Overrides everything else when set.
+const ENV_VAR: &str = "PATCH_DATA_DIR";
+
+/// Holds the override from `set_data_dir`, in the default config dir.
+const OVERRIDE_FILE: &str = "data_dir";
+
+/// Directories in use for this run. Resolved once, before anything is opened.
+#[derive(Debug, Clone, Serialize)]
+pub struct DataDirs {
+    /// `settings.json` and the identity key.
+    pub config: PathBuf,
+    /// `history.sqlite3`.
+    pub data: PathBuf,
+    pub log: PathBuf,
+    /// Set when these come from an override rather than the defaults.
+    pub root: Option<PathBuf>,
+}
+
+impl DataDirs {
+    fn under(root: PathBuf) -> Self {
+        DataDirs {
+            config: root.clone(),
+            data: root.clone(),
+            log: root.join("logs"),
+            root: Some(root),
+        }
+    }
+}
+
+/// Work out this run's directories. Call once from setup, before logging
+/// starts; the returned warning, if any, should be logged once it has.
+///
+/// An override that can't be written to is ignored in favour of the
+/// defaults.
+pub fn resolve(app: &AppHandle) -> Result<(DataDirs, Option<String>), tauri::Error> {
+    let defaults = DataDirs {
+        config: app.path().app_config_dir()?,
+        data: app.path().app_data_dir()?,
+        log: app.path().app_log_dir()?,
+        root: None,
+    };
+    let Some(root) = configured(&defaults.config) else {
+        return Ok((defaults, None));
+    };
+    match check_writable(&root) {
+        Ok(()) => Ok((DataDirs::under(root), None)),
+        Err(e) => {
+            let warning = format!("Ignoring data dir {}: {}", root.display(), e);
+            Ok((defaults, Some(warning)))
+        }
+    }
+}
+
+/// The override in effect: the env var, then the saved one.
+fn configured(default_config: &Path) -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(ENV_VAR).filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    let saved = fs::read_to_string(default_config.join(OVERRIDE_FILE)).ok()?;
+    let saved = saved.trim();
+    (!saved.is_empty()).then(|| PathBuf::from(saved))
+}
+
+/// Create `dir` if needed and prove a file can be written in it.
+fn check_writable(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".write-test");
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+/// The directories in use, and whatever will be used from the next launch.
+#[tauri::command]
+pub fn get_data_dir(app: AppHandle) -> Result<serde_json::Value, RpcError> {
+    let current = app.state::<DataDirs>();
+    let default_config = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| RpcError::Transport(e.to_string()))?;
+    Ok(serde_json::json!({
+        "current": current.inner(),
+        "next_launch": configured(&default_config),
+        "env_override": std::env::var_os(ENV_VAR).is_some()
+    }))
+}
+
+/// Keep everything under `path` from the next launch, or go back to the
+/// default dirs with `None`. Existing files are not moved.
+///
+/// `PATCH_DATA_DIR` still wins while it is set.
+#[tauri::command]
+pub fn set_data_dir(app: AppHandle, path: Option<String>) -> Result<(), RpcError> {
+    let default_config = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| RpcError::Transport(e.to_string()))?;
+    let override_file = default_config.join(OVERRIDE_FILE);
+
+    let Some(path) = path.map(|path| path.trim().to_string()).filter(|path| !path.is_empty()) else {
+        return match fs::remove_file(&override_file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(RpcError::Transport(e.to_string())),
+            _ => Ok(()),
+        };
+    };
+    let dir = PathBuf::from(&path);
+    if !dir.is_absolute() {
+        return Err(RpcError::InvalidInput(Text::new("input.data_dir_not_absolute")));
+    }
+    check_writable(&dir).map_err(|e| {
+        RpcError::InvalidInput(Text::detail("input.data_dir_unwritable", e).with("path", dir.display().to_string()))
+    })?;
+
+    fs::create_dir_all(&default_config)
+        .and_then(|()| fs::write(&override_file, path.as_bytes()))
+        .map_err(|e| RpcError::Transport(e.to_string()))?;
+    if std::env::var_os(ENV_VAR).is_some() {
+        warn!("{} is set and takes precedence over the saved data dir", ENV_VAR);
+    }
+    Ok(())
+}
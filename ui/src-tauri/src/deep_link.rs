@@ -0,0 +1,233 @@
+//! `deck-link://pair?host=..&port=..&peer_id=..` links, e.g. from a QR code
+//! shown on the other device.
+//!
+//! A link may also carry a `token` the other device issued; presenting it
+//! skips the passphrase. `get_pairing_payload` builds such a link for us.
+//!
+//! Links that launch the app arrive via `get_current`; links opened while it
+//! runs arrive via `on_open_url` (forwarded by single-instance on Windows and
+//! Linux). Either way a pairing request made before the sidecar is ready is
+//! held until it is.
+
+use tauri::{AppHandle, Manager};
+use tauri_plugin_deep_link::DeepLinkExt;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::messages::Text;
+use crate::{
+    address, call_sidecar, connect, emit_event, RpcError, SharedState, DEFAULT_PORT,
+};
+
+const SCHEME: &str = "deck-link";
+
+/// Longest peer id we accept; mDNS names can't be longer anyway.
+const MAX_PEER_ID_LEN: usize = 255;
+
+/// Sidecar tokens are 22 characters; anything much longer isn't one.
+const MAX_TOKEN_LEN: usize = 128;
+
+/// Where a pairing request wants us to connect.
+#[derive(Debug, Clone)]
+pub struct PairTarget {
+    /// May be omitted when `peer_id` names a peer discovery already knows.
+    pub host: Option<String>,
+    pub port: u16,
+    pub peer_id: Option<String>,
+    /// Single-use pairing token issued by the peer.
+    pub token: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct PairingToken {
+    token: String,
+    peer_id: String,
+    local_info: LocalInfo,
+}
+
+#[derive(serde::Deserialize)]
+struct LocalInfo {
+    name: String,
+    ip: String,
+    port: u16,
+    #[serde(rename = "type")]
+    device_type: String,
+}
+
+/// Build a `deck-link://pair` link for another device to scan.
+///
+/// Each call asks the sidecar for a fresh token, so an earlier code stops
+/// working as soon as a new one is shown.
+#[tauri::command]
+pub async fn get_pairing_payload(state: tauri::State<'_, SharedState>) -> Result<String, RpcError> {
+    let result = call_sidecar(state.inner(), "create_pairing_token", serde_json::json!({})).await?;
+    let issued: PairingToken = serde_json::from_value(result)?;
+
+    let mut url = Url::parse(&format!("{}://pair", SCHEME)).expect("static URL is valid");
+    url.query_pairs_mut()
+        .append_pair("host", &issued.local_info.ip)
+        .append_pair("port", &issued.local_info.port.to_string())
+        .append_pair("peer_id", &issued.peer_id)
+        .append_pair("name", &issued.local_info.name)
+        .append_pair("device_type", &issued.local_info.device_type)
+        .append_pair("token", &issued.token);
+    Ok(url.into())
+}
+
+/// Start listening for links. Call once from setup.
+pub fn init(app: &AppHandle) {
+    // Installed builds register the scheme at install time; this covers
+    // dev builds and AppImages
+    #[cfg(any(windows, target_os = "linux"))]
+    if let Err(e) = app.deep_link().register_all() {
+        warn!("Could not register {}:// links: {}", SCHEME, e);
+    }
+
+    let handle = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        for url in event.urls() {
+            open_url(&handle, &url);
+        }
+    });
+
+    match app.deep_link().get_current() {
+        Ok(Some(urls)) => urls.iter().for_each(|url| open_url(app, url)),
+        Ok(None) => {}
+        Err(e) => warn!("Could not read launch link: {}", e),
+    }
+}
+
+fn open_url(app: &AppHandle, url: &Url) {
+    match parse_pair_url(url) {
+        Ok(target) => pair_with(app, target),
+        Err(error) => {
+            let url = without_token(url);
+            warn!("Rejected link {}: {}", url, error);
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                emit_event(&app, "sidecar:deep_link_error", serde_json::json!({
+                    "url": url,
+                    "error": error.english(),
+                    "message_code": error.code,
+                    "params": error.params
+                }))
+                .await;
+            });
+        }
+    }
+}
+
+/// `url` with its pairing token blanked, so it can be logged.
+fn without_token(url: &Url) -> String {
+    let mut url = url.clone();
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    if pairs.iter().any(|(key, _)| key == "token") {
+        let mut query = url.query_pairs_mut();
+        query.clear();
+        for (key, value) in &pairs {
+            query.append_pair(key, if key == "token" { "<redacted>" } else { value });
+        }
+    }
+    url.into()
+}
+
+/// Validate a `deck-link://pair?...` URL.
+fn parse_pair_url(url: &Url) -> Result<PairTarget, Text> {
+    if url.scheme() != SCHEME || url.host_str() != Some("pair") {
+        return Err(Text::new("link.not_pairing"));
+    }
+
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.trim().to_string())
+    };
+
+    let host = match param("host").filter(|host| !host.is_empty()) {
+        Some(host) => Some(address::normalize_host(&host).map_err(|e| e.text())?),
+        None => None,
+    };
+    let port = match param("port") {
+        Some(port) => match port.parse::<u16>() {
+            Ok(0) | Err(_) => return Err(Text::new("link.invalid_port").with("port", port)),
+            Ok(port) => port,
+        },
+        None => DEFAULT_PORT,
+    };
+    let peer_id = param("peer_id").filter(|id| !id.is_empty());
+    if let Some(id) = &peer_id {
+        if id.len() > MAX_PEER_ID_LEN || id.chars().any(char::is_control) {
+            return Err(Text::new("link.invalid_peer_id"));
+        }
+    }
+    if host.is_none() && peer_id.is_none() {
+        return Err(Text::new("link.no_target"));
+    }
+    let token = param("token").filter(|token| !token.is_empty());
+    if let Some(token) = &token {
+        let url_safe = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if token.len() > MAX_TOKEN_LEN || !token.chars().all(url_safe) {
+            return Err(Text::new("link.invalid_token"));
+        }
+    }
+
+    Ok(PairTarget { host, port, peer_id, token })
+}
+
+/// Connect to `target` now, or once the sidecar is ready.
+pub fn pair_with(app: &AppHandle, target: PairTarget) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<SharedState>().inner().clone();
+        {
+            let mut state = state.lock().await;
+            if !state.sidecar.is_ready() {
+                info!("Holding pairing request until the sidecar is ready");
+                state.pending_pair = Some(target);
+                return;
+            }
+        }
+        run_pairing(&app, target).await;
+    });
+}
+
+/// Start a pairing request held by `pair_with`, if any. Returns whether
+/// there was one.
+pub async fn spawn_pending(app: &AppHandle) -> bool {
+    let Some(target) = app.state::<SharedState>().lock().await.pending_pair.take() else {
+        return false;
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move { run_pairing(&app, target).await });
+    true
+}
+
+async fn run_pairing(app: &AppHandle, target: PairTarget) {
+    // A discovered address beats a missing one; the link's own host wins otherwise
+    let (host, port) = match target.host {
+        Some(host) => (host, target.port),
+        None => {
+            let state = app.state::<SharedState>();
+            let state = state.lock().await;
+            let known = target.peer_id.as_ref().and_then(|id| state.peers.get(id));
+            match known {
+                Some(peer) => (peer.ip.clone(), peer.port),
+                None => {
+                    drop(state);
+                    let error = Text::new("link.peer_not_found");
+                    emit_event(app, "sidecar:deep_link_error", serde_json::json!({
+                        "error": error.english(),
+                        "message_code": error.code,
+                        "params": error.params
+                    }))
+                    .await;
+                    return;
+                }
+            }
+        }
+    };
+
+    if let Err(e) = connect(app, host, port, target.token).await {
+        warn!("Pairing from link failed: {}", e);
+    }
+}
@@ -0,0 +1,173 @@
+//! This device's identity as peers see it, worked out locally so UI that
+//! only needs it doesn't wait on the sidecar.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::address::{self, LocalAddress};
+use crate::messages::Text;
+use crate::{call_sidecar, AppState, RpcError, SharedState};
+
+/// Longest display name we accept, in characters.
+const MAX_NAME_LEN: usize = 64;
+
+/// What kind of device this is, so peers can show the right icon.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceType {
+    #[default]
+    Laptop,
+    Desktop,
+    Phone,
+    Tablet,
+    Server,
+}
+
+impl DeviceType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeviceType::Laptop => "laptop",
+            DeviceType::Desktop => "desktop",
+            DeviceType::Phone => "phone",
+            DeviceType::Tablet => "tablet",
+            DeviceType::Server => "server",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Result<Self, RpcError> {
+        match kind {
+            "laptop" => Ok(DeviceType::Laptop),
+            "desktop" => Ok(DeviceType::Desktop),
+            "phone" => Ok(DeviceType::Phone),
+            "tablet" => Ok(DeviceType::Tablet),
+            "server" => Ok(DeviceType::Server),
+            _ => Err(RpcError::InvalidInput(Text::new("input.unknown_device_type").with("device_type", kind))),
+        }
+    }
+}
+
+/// Name, kind and address of this device.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceInfo {
+    /// The configured display name, or the hostname.
+    pub name: String,
+    pub device_type: DeviceType,
+    /// Primary local IP; loopback when offline.
+    pub ip: String,
+    /// Every local address, the primary first.
+    pub addresses: Vec<LocalAddress>,
+    /// Port the sidecar listens on.
+    pub port: u16,
+}
+
+fn hostname() -> String {
+    hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or("unknown".to_string())
+}
+
+/// The device info, computed on first use and cached until `invalidate`.
+pub fn local_device(state: &mut AppState) -> DeviceInfo {
+    if let Some(device) = &state.local_device {
+        return device.clone();
+    }
+    let addresses = address::local_addresses(state.local_ip);
+    let device = DeviceInfo {
+        name: state
+            .settings
+            .device_name
+            .clone()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(hostname),
+        device_type: state.settings.device_type,
+        ip: addresses
+            .first()
+            .map(|address| address.ip.clone())
+            .unwrap_or("127.0.0.1".to_string()),
+        addresses,
+        port: state.settings.sidecar.port(),
+    };
+    state.local_device = Some(device.clone());
+    device
+}
+
+/// Drop the cached info after the network or settings change.
+pub fn invalidate(state: &mut AppState) {
+    state.local_device = None;
+}
+
+/// Strip control characters and surrounding whitespace from a display
+/// name, rejecting it if nothing (or too much) is left.
+pub fn sanitize_name(name: &str) -> Result<String, RpcError> {
+    let name: String = name.chars().filter(|c| !c.is_control()).collect();
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(RpcError::InvalidInput(Text::new("input.device_name_empty")));
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(RpcError::InvalidInput(Text::new("input.device_name_too_long").with("max_chars", MAX_NAME_LEN)));
+    }
+    Ok(name.to_string())
+}
+
+/// The name peers see: the configured one, or the hostname.
+#[tauri::command]
+pub async fn get_device_name(state: tauri::State<'_, SharedState>) -> Result<String, RpcError> {
+    Ok(local_device(&mut *state.lock().await).name)
+}
+
+/// Set and persist the name peers see, and tell the running sidecar.
+/// A sidecar that isn't running picks it up when it starts.
+#[tauri::command]
+pub async fn set_device_name(app: AppHandle, name: String) -> Result<String, RpcError> {
+    let name = sanitize_name(&name)?;
+    let state = app.state::<SharedState>().inner().clone();
+    {
+        let mut state = state.lock().await;
+        state.settings.device_name = Some(name.clone());
+        state.save_settings();
+        invalidate(&mut state);
+    }
+    push_identity(&state).await?;
+    Ok(name)
+}
+
+/// Send the current display name to the sidecar, which re-advertises
+/// under it. Nothing to do if it isn't running: it gets `--name` on start.
+pub async fn push_identity(state: &SharedState) -> Result<(), RpcError> {
+    let name = local_device(&mut *state.lock().await).name;
+    match call_sidecar(state, "set_device_name", serde_json::json!({ "name": name })).await {
+        Ok(_) | Err(RpcError::SidecarNotRunning) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Set and persist the kind of device peers see.
+#[tauri::command]
+pub async fn set_device_type(app: AppHandle, kind: String) -> Result<DeviceType, RpcError> {
+    let device_type = DeviceType::parse(&kind)?;
+    let state = app.state::<SharedState>().inner().clone();
+    {
+        let mut state = state.lock().await;
+        state.settings.device_type = device_type;
+        state.save_settings();
+        invalidate(&mut state);
+    }
+    push_device_type(&state).await?;
+    Ok(device_type)
+}
+
+/// Like `push_identity`, for the device type (`--mode` on start).
+pub async fn push_device_type(state: &SharedState) -> Result<(), RpcError> {
+    let device_type = state.lock().await.settings.device_type;
+    let params = serde_json::json!({ "device_type": device_type });
+    match call_sidecar(state, "set_device_type", params).await {
+        Ok(_) | Err(RpcError::SidecarNotRunning) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[tauri::command]
+pub async fn get_local_device(state: tauri::State<'_, SharedState>) -> Result<DeviceInfo, RpcError> {
+    Ok(local_device(&mut *state.lock().await))
+}
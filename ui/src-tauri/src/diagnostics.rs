@@ -0,0 +1,182 @@
+//! A zip of what a bug report needs: the log files, settings, recent
+//! events, versions and the requests still in flight.
+//!
+//! Nothing secret goes in. Pairing secrets stay in the keychain and are
+//! never read here; passphrases and tokens are redacted from settings,
+//! events and every line of the logs, and the values of the sidecar's environment, which may hold
+//! credentials, are dropped.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::ipc::{redact_line, redact_secrets};
+use crate::messages::Text;
+use crate::{emit_event, version_info, DataDirs, RpcError, SharedState};
+
+/// Log files whose names start with these are included, rotated ones too.
+const LOG_FILES: [&str; 2] = ["app.log", "sidecar.log"];
+
+/// Progress is reported every this many bytes of logs copied.
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// Write the diagnostics bundle to `dest_path`, replacing any file there.
+///
+/// Copying the logs is reported as `sidecar:diagnostics_progress`;
+/// resolves with the path and the size of the bundle.
+#[tauri::command]
+pub async fn export_diagnostics(app: AppHandle, dest_path: String) -> Result<serde_json::Value, RpcError> {
+    if dest_path.trim().is_empty() {
+        return Err(RpcError::InvalidInput(Text::new("input.destination_empty")));
+    }
+    let dest = PathBuf::from(&dest_path);
+
+    let documents = {
+        let state = app.state::<SharedState>();
+        let state = state.lock().await;
+        let mut settings = serde_json::to_value(&state.settings)?;
+        if let Some(env) = settings.pointer_mut("/sidecar/env").and_then(|env| env.as_object_mut()) {
+            env.values_mut().for_each(|value| *value = "<redacted>".into());
+        }
+        let mut events = serde_json::to_value(&state.event_log)?;
+        redact_secrets(&mut settings);
+        // Already redacted as they were logged; this catches anything new
+        redact_secrets(&mut events);
+        vec![
+            ("settings.json", settings),
+            ("events.json", events),
+            ("version.json", serde_json::to_value(version_info(&state))?),
+            ("pending_requests.json", serde_json::to_value(state.sidecar.client.pending())?),
+        ]
+    };
+    let logs = match app.try_state::<DataDirs>() {
+        Some(dirs) => log_files(&dirs.log),
+        None => Vec::new(),
+    };
+
+    let handle = app.clone();
+    let target = dest.clone();
+    let written = tokio::task::spawn_blocking(move || write_bundle(&handle, &target, &documents, &logs))
+        .await
+        .map_err(|e| RpcError::Transport(format!("diagnostics export failed: {}", e)))?
+        .map_err(|e| RpcError::Transport(format!("cannot write {}: {}", dest.display(), e)))?;
+
+    info!("Exported diagnostics to {} ({} bytes)", dest.display(), written);
+    Ok(serde_json::json!({ "path": dest_path, "bytes": written }))
+}
+
+/// The log files in `dir`, current and rotated, by name.
+fn log_files(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Cannot list logs in {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            LOG_FILES.iter().any(|log| name.starts_with(log))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Returns the size of the finished bundle.
+fn write_bundle(
+    app: &AppHandle,
+    dest: &Path,
+    documents: &[(&str, serde_json::Value)],
+    logs: &[PathBuf],
+) -> io::Result<u64> {
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(File::create(dest)?);
+
+    for (name, document) in documents {
+        zip.start_file(*name, options).map_err(io::Error::other)?;
+        zip.write_all(&serde_json::to_vec_pretty(document)?)?;
+    }
+
+    let total: u64 = logs.iter().filter_map(|path| path.metadata().ok()).map(|meta| meta.len()).sum();
+    let mut copied = 0;
+    let mut reported = 0;
+    for path in logs {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        // Rotation may have moved it on since it was listed
+        let Ok(file) = File::open(path) else {
+            continue;
+        };
+        zip.start_file(format!("logs/{}", name), options).map_err(io::Error::other)?;
+        copy_redacted(file, &mut zip, |n| {
+            copied += n;
+            if copied - reported >= PROGRESS_INTERVAL {
+                reported = copied;
+                progress(app, copied, total);
+            }
+        })?;
+    }
+    progress(app, copied, total);
+
+    let file = zip.finish().map_err(io::Error::other)?;
+    file.metadata().map(|meta| meta.len())
+}
+
+/// Copy a log line by line through `redact_line`, calling `read` with the
+/// size of each line. Lines are redacted as they are logged, but older
+/// files may predate that.
+fn copy_redacted(from: impl Read, to: &mut impl Write, mut read: impl FnMut(u64)) -> io::Result<()> {
+    let mut from = BufReader::with_capacity(64 * 1024, from);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let n = from.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            return Ok(());
+        }
+        to.write_all(redact_line(&String::from_utf8_lossy(&line)).as_bytes())?;
+        read(n as u64);
+    }
+}
+
+fn progress(app: &AppHandle, bytes: u64, total: u64) {
+    tauri::async_runtime::block_on(emit_event(app, "sidecar:diagnostics_progress", serde_json::json!({
+        "bytes": bytes,
+        // Logs still being written can outgrow what was measured
+        "total_bytes": total.max(bytes)
+    })));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copied_logs_are_redacted() {
+        let log = "12:00:01 INFO Connecting to 192.0.2.10\n\
+                   12:00:02 DEBUG stored pairing_secret=hunter2 for peer\n\
+                   12:00:03 DEBUG -> {\"method\": \"pair\", \"token\": \"abc123\"}";
+        let mut bundled = Vec::new();
+        let mut read = 0;
+        copy_redacted(log.as_bytes(), &mut bundled, |n| read += n).unwrap();
+
+        let bundled = String::from_utf8(bundled).unwrap();
+        assert!(!bundled.contains("hunter2") && !bundled.contains("abc123"));
+        let lines: Vec<&str> = bundled.lines().collect();
+        assert_eq!(lines[0], "12:00:01 INFO Connecting to 192.0.2.10");
+        assert_eq!(lines[1], "12:00:02 DEBUG stored pairing_secret=<redacted> for peer");
+        assert_eq!(lines.len(), 3);
+        assert_eq!(read, log.len() as u64);
+    }
+}
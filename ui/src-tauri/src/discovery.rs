@@ -0,0 +1,69 @@
+//! Whether mDNS discovery is actually working.
+//!
+//! A firewall or a network without multicast makes discovery fail
+//! silently: no peers, no error. The sidecar reports its discovery state
+//! as `discovery_status` events, cached here, and a newly reported error
+//! is announced as `sidecar:discovery_error` so the UI can point at the
+//! firewall.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+
+use crate::{emit_event, recovery, RpcError, SharedState};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryStatus {
+    /// Browsing and advertising without errors.
+    pub active: bool,
+    /// Unix time (ms) the current browse started.
+    pub last_scan_at: Option<u64>,
+    /// Addresses we advertise on.
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Cache a `discovery_status` event. Returns whether it should still be
+/// forwarded, which it is.
+pub async fn handle_event(app: &AppHandle, status: DiscoveryStatus) -> bool {
+    let previous_error = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        state.discovery.replace(status.clone()).and_then(|previous| previous.error)
+    };
+    if let Some(error) = status.error.as_ref().filter(|error| previous_error.as_ref() != Some(error)) {
+        warn!("Discovery failing: {}", error);
+        emit_event(app, "sidecar:discovery_error", serde_json::json!({
+            "error": error,
+            "interfaces": status.interfaces
+        }))
+        .await;
+    }
+    true
+}
+
+/// The sidecar's discovery state and how many peers it has found.
+///
+/// Answered from the cache; the sidecar is only asked before its first
+/// report has arrived.
+#[tauri::command]
+pub async fn get_discovery_status(app: AppHandle) -> Result<serde_json::Value, RpcError> {
+    let state = app.state::<SharedState>().inner().clone();
+    let cached = state.lock().await.discovery.clone();
+    let status = match cached {
+        Some(status) => status,
+        None => {
+            let result = recovery::call(&app, "get_discovery_status", serde_json::json!({})).await?;
+            let status: DiscoveryStatus = serde_json::from_value(result)?;
+            state.lock().await.discovery = Some(status.clone());
+            status
+        }
+    };
+
+    let mut report = serde_json::to_value(status)?;
+    let state = state.lock().await;
+    report["peer_count"] = state.peers.len().into();
+    report["bind_interface"] = state.settings.bind_interface.clone().into();
+    Ok(report)
+}
@@ -0,0 +1,165 @@
+//! Events the sidecar pushes, with the payloads they carry.
+//!
+//! Each line's `data` is parsed into its `SidecarEvent` before anything
+//! acts on it, so a payload that has drifted from what the sidecar sends
+//! is caught where it arrives rather than deep in a handler. Events are
+//! parsed by name, not with a serde tag, so that an event this build
+//! doesn't know can be told apart from a known one that is malformed.
+//!
+//! What is forwarded to the frontend is `for_frontend` of the payload:
+//! `connected` carries the peer's pairing secret, which only ever goes to
+//! the keychain.
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::bandwidth::Stats;
+use crate::clipboard_image::ClipboardImage;
+use crate::discovery::DiscoveryStatus;
+use crate::notifications::PeerNotification;
+use crate::pairing::PairingRequest;
+use crate::peers::PeerLost;
+use crate::transfers::{TransferChunk, TransferRef, TransferRequest};
+use crate::{ClipboardUpdate, Peer};
+
+/// Payload fields that never leave the backend.
+const PRIVATE_FIELDS: [&str; 1] = ["pairing_secret"];
+
+pub enum SidecarEvent {
+    /// The sidecar's handshake, as a `ping` would answer it.
+    Ready(serde_json::Value),
+    ClipboardUpdated(ClipboardUpdate),
+    ClipboardImage(ClipboardImage),
+    TransferRequest(TransferRequest),
+    TransferChunk(TransferChunk),
+    TransferFinish(TransferRef),
+    TransferCancel(TransferRef),
+    PeerDiscovered(Peer),
+    PeerLost(PeerLost),
+    DiscoveryStatus(DiscoveryStatus),
+    Stats(Stats),
+    PairingRequest(PairingRequest),
+    PairingRequestExpired(PairingRequest),
+    PassphraseRequired(Handshake),
+    ChallengeGenerated(Handshake),
+    Connected(Connected),
+    AuthFailed(Failure),
+    ConnectionError(Failure),
+    Disconnected(Handshake),
+    Notification(PeerNotification),
+    /// Only forwarded; nothing here depends on their payloads.
+    StateChanged,
+    ServerStarted,
+    ServerStopped,
+}
+
+/// The session a handshake or disconnect is about.
+#[derive(Debug, Deserialize)]
+pub struct Handshake {
+    pub session_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Connected {
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub peer_name: String,
+    pub peer_ip: Option<String>,
+    /// Identity fingerprint the peer presented, if it sent one.
+    pub peer_fingerprint: Option<String>,
+    /// Secret to skip the passphrase next time, when pairing issued one.
+    pub pairing_secret: Option<String>,
+}
+
+/// `auth_failed` and `connection_error`.
+#[derive(Debug, Deserialize)]
+pub struct Failure {
+    pub reason: Option<String>,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub rate_limited: bool,
+    /// The sidecar's classification, taken leniently; see `ConnectError`.
+    pub code: Option<serde_json::Value>,
+}
+
+impl SidecarEvent {
+    /// Parse event `name`; `None` if it isn't one this build knows.
+    pub fn parse(name: &str, data: &serde_json::Value) -> Result<Option<Self>, serde_json::Error> {
+        fn payload<T: DeserializeOwned>(data: &serde_json::Value) -> Result<T, serde_json::Error> {
+            T::deserialize(data)
+        }
+        Ok(Some(match name {
+            "ready" => SidecarEvent::Ready(data.clone()),
+            "clipboard_updated" => SidecarEvent::ClipboardUpdated(payload(data)?),
+            "clipboard_image" => SidecarEvent::ClipboardImage(payload(data)?),
+            "transfer_request" => SidecarEvent::TransferRequest(payload(data)?),
+            "transfer_chunk" => SidecarEvent::TransferChunk(payload(data)?),
+            "transfer_finish" => SidecarEvent::TransferFinish(payload(data)?),
+            "transfer_cancel" => SidecarEvent::TransferCancel(payload(data)?),
+            "peer_discovered" => SidecarEvent::PeerDiscovered(payload(data)?),
+            "peer_lost" => SidecarEvent::PeerLost(payload(data)?),
+            "discovery_status" => SidecarEvent::DiscoveryStatus(payload(data)?),
+            "stats" => SidecarEvent::Stats(payload(data)?),
+            "pairing_request" => SidecarEvent::PairingRequest(payload(data)?),
+            "pairing_request_expired" => SidecarEvent::PairingRequestExpired(payload(data)?),
+            "passphrase_required" => SidecarEvent::PassphraseRequired(payload(data)?),
+            "challenge_generated" => SidecarEvent::ChallengeGenerated(payload(data)?),
+            "connected" => SidecarEvent::Connected(payload(data)?),
+            "auth_failed" => SidecarEvent::AuthFailed(payload(data)?),
+            "connection_error" => SidecarEvent::ConnectionError(payload(data)?),
+            "disconnected" => SidecarEvent::Disconnected(payload(data)?),
+            "notification" => SidecarEvent::Notification(payload(data)?),
+            "state_changed" => SidecarEvent::StateChanged,
+            "server_started" => SidecarEvent::ServerStarted,
+            "server_stopped" => SidecarEvent::ServerStopped,
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// `data` with `PRIVATE_FIELDS` taken out, once it has been parsed.
+pub fn for_frontend(mut data: serde_json::Value) -> serde_json::Value {
+    if let Some(fields) = data.as_object_mut() {
+        for field in PRIVATE_FIELDS {
+            fields.remove(field);
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairing_secret_is_parsed_but_not_forwarded() {
+        let data = serde_json::json!({
+            "session_id": "s1",
+            "peer_name": "Deck",
+            "peer_ip": "10.0.0.2",
+            "peer_fingerprint": "ab:cd",
+            "pairing_secret": "s3cret"
+        });
+        let Ok(Some(SidecarEvent::Connected(connected))) = SidecarEvent::parse("connected", &data) else {
+            panic!("not a connected event");
+        };
+        assert_eq!(connected.pairing_secret.as_deref(), Some("s3cret"));
+
+        let forwarded = for_frontend(data);
+        assert!(forwarded.get("pairing_secret").is_none());
+        assert_eq!(forwarded, serde_json::json!({
+            "session_id": "s1",
+            "peer_name": "Deck",
+            "peer_ip": "10.0.0.2",
+            "peer_fingerprint": "ab:cd"
+        }));
+    }
+
+    #[test]
+    fn for_frontend_leaves_other_payloads_alone() {
+        let data = serde_json::json!([1, 2]);
+        assert_eq!(for_frontend(data.clone()), data);
+        let data = serde_json::json!({ "id": "t1", "secret_santa": true });
+        assert_eq!(for_frontend(data.clone()), data);
+    }
+}
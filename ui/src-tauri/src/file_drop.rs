@@ -0,0 +1,114 @@
+//! Files dragged onto the main window from the OS.
+//!
+//! A drop is expanded into the files it contains and offered to the
+//! frontend as `sidecar:files_dropped`; nothing is sent until the user
+//! confirms, which the frontend does by passing the paths to `send_files`.
+
+use std::path::{Path, PathBuf};
+
+use tauri::{DragDropEvent, Manager, Window, WindowEvent};
+use tracing::warn;
+
+use crate::messages::Text;
+use crate::{emit_event, SharedState};
+
+/// How many directories deep a dropped folder is followed.
+const MAX_DEPTH: usize = 4;
+
+/// Most files a single drop can offer.
+const MAX_FILES: usize = 500;
+
+/// Most bytes a single drop can offer in total.
+const MAX_TOTAL_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/// What a drop expanded to.
+#[derive(Default)]
+struct Expanded {
+    paths: Vec<String>,
+    total_bytes: u64,
+    /// Entries left out for hitting a limit, or for being unreadable.
+    skipped: usize,
+}
+
+/// Handle a file drop on `window`.
+pub fn handle(window: &Window, event: &WindowEvent) {
+    let WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) = event else {
+        return;
+    };
+    if paths.is_empty() {
+        return;
+    }
+
+    let app = window.app_handle().clone();
+    let paths = paths.clone();
+    tauri::async_runtime::spawn(async move {
+        let connected = !app.state::<SharedState>().lock().await.connections.is_empty();
+        if !connected {
+            let error = Text::new("files.not_connected");
+            emit_event(&app, "sidecar:files_dropped", serde_json::json!({
+                "paths": [],
+                "error": error.english(),
+                "message_code": error.code,
+                "params": error.params
+            }))
+            .await;
+            return;
+        }
+
+        let expanded = match tokio::task::spawn_blocking(move || expand(&paths)).await {
+            Ok(expanded) => expanded,
+            Err(e) => {
+                warn!("Could not read dropped files: {}", e);
+                return;
+            }
+        };
+        emit_event(&app, "sidecar:files_dropped", serde_json::json!({
+            "paths": expanded.paths,
+            "total_bytes": expanded.total_bytes,
+            "skipped": expanded.skipped
+        }))
+        .await;
+    });
+}
+
+fn expand(paths: &[PathBuf]) -> Expanded {
+    let mut expanded = Expanded::default();
+    for path in paths {
+        visit(path, 0, &mut expanded);
+    }
+    expanded
+}
+
+/// Add `path`, or the files under it, to `expanded`.
+///
+/// Symlinks are skipped rather than followed, so a link back up the tree
+/// can't loop.
+fn visit(path: &Path, depth: usize, expanded: &mut Expanded) {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        expanded.skipped += 1;
+        return;
+    };
+
+    if metadata.is_file() {
+        let fits = expanded.paths.len() < MAX_FILES
+            && expanded.total_bytes + metadata.len() <= MAX_TOTAL_BYTES;
+        if fits {
+            expanded.paths.push(path.to_string_lossy().to_string());
+            expanded.total_bytes += metadata.len();
+        } else {
+            expanded.skipped += 1;
+        }
+    } else if metadata.is_dir() && depth < MAX_DEPTH {
+        let Ok(entries) = std::fs::read_dir(path) else {
+            expanded.skipped += 1;
+            return;
+        };
+        let mut children: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        children.sort();
+        for child in children {
+            visit(&child, depth + 1, expanded);
+        }
+    } else {
+        expanded.skipped += 1;
+    }
+}
@@ -0,0 +1,164 @@
+//! Removing everything we keep about a peer: the pairing secret, its
+//! pinned fingerprint, allow/block entries and the last-peer record. The
+//! sidecar is told to stop honouring the secret it issued to the peer's
+//! key too, or the peer could still reconnect without pairing again.
+//!
+//! Each step runs even if an earlier one failed, so a keychain outage
+//! doesn't leave the settings half-cleaned; the error says what was left.
+//! `sidecar:peer_forgotten` says for each peer whether everything about
+//! it went.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use tauri::{AppHandle, Manager};
+use tracing::info;
+
+use crate::messages::Text;
+use crate::{call_sidecar, disconnect, emit_event, keychain, AppState, RpcError, SharedState};
+
+/// Forget `id`, disconnecting first if it is the connected peer.
+#[tauri::command]
+pub async fn forget_peer(app: AppHandle, id: String) -> Result<(), RpcError> {
+    let id = id.trim().to_string();
+    if id.is_empty() {
+        return Err(RpcError::InvalidInput(Text::new("input.peer_id_empty")));
+    }
+    let failed = forget(&app, std::slice::from_ref(&id), false).await;
+    report(failed)
+}
+
+/// Forget every peer we have any record of.
+///
+/// Pairing secrets can only be found by peer id, so secrets for peers
+/// known nowhere else are left in the keychain; the sidecar drops every
+/// secret it issued all the same.
+#[tauri::command]
+pub async fn forget_all_peers(app: AppHandle) -> Result<(), RpcError> {
+    let ids: BTreeSet<String> = {
+        let state = app.state::<SharedState>();
+        let state = state.lock().await;
+        let settings = &state.settings;
+        settings
+            .trusted_fingerprints
+            .keys()
+            .chain(&settings.blocked_peers)
+            .chain(&settings.allowed_peers)
+            .chain(settings.last_peer.as_ref().and_then(|peer| peer.id.as_ref()))
+            .chain(state.connections.values().filter_map(|connection| connection.peer_id.as_ref()))
+            .cloned()
+            .collect()
+    };
+    let ids: Vec<String> = ids.into_iter().collect();
+    let failed = forget(&app, &ids, true).await;
+    report(failed)
+}
+
+/// The fingerprints recorded for each of `ids`: pinned, with the last peer
+/// or presented on a connection.
+fn fingerprints(state: &AppState, ids: &[String]) -> BTreeMap<String, BTreeSet<String>> {
+    let settings = &state.settings;
+    let last_peer = settings.last_peer.iter().filter_map(|peer| Some((peer.id.as_ref()?, peer.fingerprint.as_ref()?)));
+    let connected = state
+        .connections
+        .values()
+        .filter_map(|connection| Some((connection.peer_id.as_ref()?, connection.fingerprint.as_ref()?)));
+    let mut fingerprints: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (id, fingerprint) in settings.trusted_fingerprints.iter().chain(last_peer).chain(connected) {
+        if ids.contains(id) {
+            fingerprints.entry(id.clone()).or_default().insert(fingerprint.clone());
+        }
+    }
+    fingerprints
+}
+
+/// Have the sidecar stop honouring the pairing secrets issued to
+/// `fingerprints`, or to anyone with `all`.
+async fn revoke(
+    state: &SharedState,
+    fingerprints: &BTreeMap<String, BTreeSet<String>>,
+    all: bool,
+) -> Result<(), String> {
+    let params = if all {
+        serde_json::json!({ "all": true })
+    } else {
+        let fingerprints: BTreeSet<&String> = fingerprints.values().flatten().collect();
+        if fingerprints.is_empty() {
+            return Ok(());
+        }
+        serde_json::json!({ "fingerprints": fingerprints })
+    };
+    call_sidecar(state, "revoke_pairing_secrets", params).await.map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Returns the steps that failed, as `"<step> for <id>: <error>"`.
+async fn forget(app: &AppHandle, ids: &[String], all: bool) -> Vec<String> {
+    let state = app.state::<SharedState>().inner().clone();
+    let (sessions, fingerprints) = {
+        let state = state.lock().await;
+        let sessions: Vec<String> = state
+            .connections
+            .values()
+            .filter(|connection| connection.peer_id.as_ref().is_some_and(|id| ids.contains(id)))
+            .map(|connection| connection.session_id.clone())
+            .collect();
+        (sessions, fingerprints(&state, ids))
+    };
+    for session_id in sessions {
+        disconnect(app, Some(session_id), false).await;
+    }
+
+    let mut failed = Vec::new();
+    let saved = {
+        let mut state = state.lock().await;
+        let settings = &mut state.settings;
+        for id in ids {
+            settings.trusted_fingerprints.remove(id);
+            settings.blocked_peers.remove(id);
+            settings.allowed_peers.remove(id);
+        }
+        if settings
+            .last_peer
+            .as_ref()
+            .and_then(|peer| peer.id.as_ref())
+            .is_some_and(|id| ids.contains(id))
+        {
+            settings.last_peer = None;
+        }
+        match &state.settings_path {
+            Some(path) => state.settings.save(path).map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    };
+    if let Err(e) = saved {
+        failed.push(format!("saving settings: {}", e));
+    }
+    let settings_saved = failed.is_empty();
+    let revoked = revoke(&state, &fingerprints, all).await;
+    if let Err(e) = &revoked {
+        failed.push(format!("revoking pairing secrets in the sidecar: {}", e));
+    }
+
+    for id in ids {
+        let cleared = keychain::clear_pairing_secret(id);
+        if let Err(e) = &cleared {
+            failed.push(format!("clearing pairing secret for {}: {}", id, e));
+        }
+        info!("Forgot peer {}", id);
+        emit_event(app, "sidecar:peer_forgotten", serde_json::json!({
+            "peer_id": id,
+            "complete": settings_saved
+                && cleared.is_ok()
+                && (revoked.is_ok() || (!all && !fingerprints.contains_key(id)))
+        }))
+        .await;
+    }
+    failed
+}
+
+fn report(failed: Vec<String>) -> Result<(), RpcError> {
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(RpcError::Storage(Text::new("storage.partly_forgotten").with("failed", failed.join("; "))))
+    }
+}
@@ -0,0 +1,263 @@
+//! A log of past transfers, kept in `history.sqlite3` in the data dir
+//! so it survives restarts.
+//!
+//! The database is only touched from one background thread, fed through a
+//! channel, so recording a transfer never waits on disk. If the database
+//! can't be opened the log is simply not kept.
+
+use std::path::Path;
+use std::sync::mpsc;
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+use tracing::{error, warn};
+
+use crate::messages::Text;
+use crate::{connections, unix_millis, DataDirs, RpcError, SharedState};
+
+/// Rows returned by `get_transfer_history` when no limit is given.
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Most rows one `get_transfer_history` call returns.
+const MAX_PAGE_SIZE: usize = 500;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS transfers (
+        id TEXT PRIMARY KEY,
+        filename TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        peer TEXT,
+        bytes INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS transfers_by_time ON transfers (timestamp);
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    InProgress,
+    Completed,
+    Failed,
+    Cancelled,
+    Rejected,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::InProgress => "in_progress",
+            Status::Completed => "completed",
+            Status::Failed => "failed",
+            Status::Cancelled => "cancelled",
+            Status::Rejected => "rejected",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "in_progress" => Status::InProgress,
+            "completed" => Status::Completed,
+            "cancelled" => Status::Cancelled,
+            "rejected" => Status::Rejected,
+            _ => Status::Failed,
+        }
+    }
+
+    /// The status a transfer that failed with `error` ends in.
+    pub fn from_error(error: &Text) -> Self {
+        if matches!(error.code, "transfer.cancelled" | "transfer.cancelled_by_peer") {
+            Status::Cancelled
+        } else {
+            Status::Failed
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    pub id: String,
+    pub filename: String,
+    /// `"outgoing"` or `"incoming"`.
+    pub direction: String,
+    /// Name of the peer at the time, if connected.
+    pub peer: Option<String>,
+    /// Size of the file.
+    pub bytes: u64,
+    pub status: Status,
+    /// Unix time (ms) of the last change.
+    pub timestamp: u64,
+}
+
+enum Op {
+    Start { record: Record, keep: usize },
+    Finish { id: String, status: Status, timestamp: u64 },
+    Query { limit: usize, offset: usize, reply: oneshot::Sender<Result<Vec<Record>, String>> },
+    Clear { reply: oneshot::Sender<Result<(), String>> },
+}
+
+/// Handle to the writer thread; `None` if the database is unavailable.
+pub struct History(Option<mpsc::Sender<Op>>);
+
+/// Open the database and start its thread. Call once from setup.
+pub fn init(app: &AppHandle) {
+    let opened = match app.try_state::<DataDirs>() {
+        Some(dirs) => open(&dirs.data).map_err(|e| e.to_string()),
+        None => Err("app dirs unavailable".to_string()),
+    };
+    let sender = match opened {
+        Ok(conn) => {
+            let (tx, rx) = mpsc::channel();
+            std::thread::spawn(move || run(conn, rx));
+            Some(tx)
+        }
+        Err(e) => {
+            warn!("Transfer history unavailable: {}", e);
+            None
+        }
+    };
+    app.manage(History(sender));
+}
+
+fn open(dir: &Path) -> Result<Connection, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir)?;
+    let conn = Connection::open(dir.join("history.sqlite3"))?;
+    conn.execute_batch(SCHEMA)?;
+    // A restart interrupts whatever was running
+    conn.execute(
+        "UPDATE transfers SET status = ?1 WHERE status = ?2",
+        params![Status::Failed.as_str(), Status::InProgress.as_str()],
+    )?;
+    Ok(conn)
+}
+
+fn run(conn: Connection, rx: mpsc::Receiver<Op>) {
+    while let Ok(op) = rx.recv() {
+        match op {
+            Op::Start { record, keep } => {
+                if let Err(e) = insert(&conn, &record, keep) {
+                    error!("Failed to record transfer {}: {}", record.id, e);
+                }
+            }
+            Op::Finish { id, status, timestamp } => {
+                let updated = conn.execute(
+                    "UPDATE transfers SET status = ?1, timestamp = ?2 WHERE id = ?3",
+                    params![status.as_str(), timestamp as i64, id],
+                );
+                if let Err(e) = updated {
+                    error!("Failed to update transfer {}: {}", id, e);
+                }
+            }
+            Op::Query { limit, offset, reply } => {
+                let _ = reply.send(query(&conn, limit, offset).map_err(|e| e.to_string()));
+            }
+            Op::Clear { reply } => {
+                let cleared = conn.execute("DELETE FROM transfers", params![]);
+                let _ = reply.send(cleared.map(|_| ()).map_err(|e| e.to_string()));
+            }
+        }
+    }
+}
+
+/// Add `record`, then prune all but the newest `keep` rows.
+fn insert(conn: &Connection, record: &Record, keep: usize) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO transfers (id, filename, direction, peer, bytes, status, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            record.id,
+            record.filename,
+            record.direction,
+            record.peer,
+            record.bytes as i64,
+            record.status.as_str(),
+            record.timestamp as i64,
+        ],
+    )?;
+    conn.execute(
+        "DELETE FROM transfers WHERE id NOT IN
+         (SELECT id FROM transfers ORDER BY timestamp DESC LIMIT ?1)",
+        params![keep as i64],
+    )?;
+    Ok(())
+}
+
+fn query(conn: &Connection, limit: usize, offset: usize) -> rusqlite::Result<Vec<Record>> {
+    let mut statement = conn.prepare(
+        "SELECT id, filename, direction, peer, bytes, status, timestamp FROM transfers
+         ORDER BY timestamp DESC LIMIT ?1 OFFSET ?2",
+    )?;
+    let rows = statement.query_map(params![limit as i64, offset as i64], |row| {
+        Ok(Record {
+            id: row.get(0)?,
+            filename: row.get(1)?,
+            direction: row.get(2)?,
+            peer: row.get(3)?,
+            bytes: row.get::<_, i64>(4)? as u64,
+            status: Status::parse(&row.get::<_, String>(5)?),
+            timestamp: row.get::<_, i64>(6)? as u64,
+        })
+    })?;
+    rows.collect()
+}
+
+fn send(app: &AppHandle, op: Op) {
+    if let Some(tx) = &app.state::<History>().0 {
+        let _ = tx.send(op);
+    }
+}
+
+/// Record a transfer that has just started.
+pub async fn start(app: &AppHandle, id: &str, filename: &str, direction: &str, bytes: u64) {
+    let (peer, keep) = {
+        let state = app.state::<SharedState>();
+        let state = state.lock().await;
+        let peer = connections::for_event(&state, &serde_json::Value::Null).map(|connection| connection.name.clone());
+        (peer, state.settings.transfer_history_limit)
+    };
+    let record = Record {
+        id: id.to_string(),
+        filename: filename.to_string(),
+        direction: direction.to_string(),
+        peer,
+        bytes,
+        status: Status::InProgress,
+        timestamp: unix_millis(),
+    };
+    send(app, Op::Start { record, keep });
+}
+
+/// Record how a transfer ended.
+pub fn finish(app: &AppHandle, id: &str, status: Status) {
+    send(app, Op::Finish { id: id.to_string(), status, timestamp: unix_millis() });
+}
+
+/// Past transfers, newest first.
+#[tauri::command]
+pub async fn get_transfer_history(
+    app: AppHandle,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<Vec<Record>, RpcError> {
+    let (reply, rx) = oneshot::channel();
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    send(&app, Op::Query { limit, offset: offset.unwrap_or(0), reply });
+    match rx.await {
+        Ok(records) => records.map_err(RpcError::Transport),
+        // No database, so nothing was ever recorded
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+#[tauri::command]
+pub async fn clear_transfer_history(app: AppHandle) -> Result<(), RpcError> {
+    let (reply, rx) = oneshot::channel();
+    send(&app, Op::Clear { reply });
+    match rx.await {
+        Ok(cleared) => cleared.map_err(RpcError::Transport),
+        Err(_) => Ok(()),
+    }
+}
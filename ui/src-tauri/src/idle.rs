@@ -0,0 +1,98 @@
+//! Dropping the link after a spell of inactivity.
+//!
+//! With `idle_disconnect_minutes` set, every peer is disconnected once
+//! that long has passed without activity, and `sidecar:idle_disconnected`
+//! says why. Requests to the sidecar count as activity, and so do
+//! transfers moving in either direction; pings and the status polls the
+//! frontend makes on its own don't. The clock starts afresh with each
+//! `connected` and stops once no peer is left.
+
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager};
+use tracing::info;
+
+use crate::{disconnect, emit_event, AppState, SharedState};
+
+/// Longest the timer sleeps before looking at the settings again, so a
+/// changed `idle_disconnect_minutes` applies to the current link too.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether a request for `method` is activity.
+fn is_activity(method: &str) -> bool {
+    !matches!(method, "ping" | "get_status" | "get_peers" | "get_discovery_status" | "verify_connections")
+}
+
+fn limit(state: &AppState) -> Option<Duration> {
+    let minutes = state.settings.idle_disconnect_minutes;
+    (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+}
+
+/// Note a request for `method`.
+pub fn record(state: &mut AppState, method: &str) {
+    if is_activity(method) {
+        touch(state);
+    }
+}
+
+/// Note activity, restarting the clock if it is running.
+pub fn touch(state: &mut AppState) {
+    if state.last_activity.is_some() {
+        state.last_activity = Some(Instant::now());
+    }
+}
+
+/// Start the clock afresh, for a new connection.
+pub async fn start(app: &AppHandle) {
+    let state = app.state::<SharedState>();
+    let mut state = state.lock().await;
+    state.last_activity = Some(Instant::now());
+    if let Some(timer) = state.idle_timer.take() {
+        timer.abort();
+    }
+    state.idle_timer = Some(spawn_timer(app));
+}
+
+/// Stop the clock, once no peer is connected.
+pub fn stop(state: &mut AppState) {
+    state.last_activity = None;
+    if let Some(timer) = state.idle_timer.take() {
+        timer.abort();
+    }
+}
+
+/// Seconds left before an idle disconnect; `None` if none is due.
+pub fn remaining_secs(state: &AppState) -> Option<u64> {
+    let deadline = state.last_activity? + limit(state)?;
+    Some(deadline.saturating_duration_since(Instant::now()).as_secs())
+}
+
+fn spawn_timer(app: &AppHandle) -> tauri::async_runtime::JoinHandle<()> {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<SharedState>().inner().clone();
+        let minutes = loop {
+            let wait = {
+                let mut state = state.lock().await;
+                let Some(last_activity) = state.last_activity else {
+                    return;
+                };
+                match limit(&state) {
+                    Some(limit) if last_activity.elapsed() >= limit => {
+                        // Taken so that disconnecting doesn't abort us on the way
+                        state.idle_timer = None;
+                        state.last_activity = None;
+                        break state.settings.idle_disconnect_minutes;
+                    }
+                    Some(limit) => (limit - last_activity.elapsed()).min(CHECK_INTERVAL),
+                    None => CHECK_INTERVAL,
+                }
+            };
+            tokio::time::sleep(wait).await;
+        };
+
+        info!("Idle for {} minutes; disconnecting", minutes);
+        emit_event(&app, "sidecar:idle_disconnected", serde_json::json!({ "idle_minutes": minutes })).await;
+        disconnect(&app, None, false).await;
+    })
+}
@@ -0,0 +1,124 @@
+//! Keeping discovery and connections to one network interface.
+//!
+//! By default the sidecar listens, advertises and browses on every
+//! interface, which on a machine with a VPN or a VM bridge can put peers
+//! on the wrong network. `set_bind_interface` names the one to use; the
+//! choice is persisted as `bind_interface` and handed to each new sidecar
+//! as `--interface` and `--bind`. An interface that has gone away by then
+//! is ignored, and the sidecar binds everywhere.
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+
+use crate::address::{self, LocalAddress};
+use crate::messages::Text;
+use crate::{call_sidecar, spawn_auto_reconnect, AppState, RpcError, SharedState};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkInterface {
+    pub name: String,
+    /// Its addresses, as `local_addresses` orders them.
+    pub addresses: Vec<LocalAddress>,
+    /// Discovery and connections are bound to it.
+    pub active: bool,
+}
+
+/// Every interface with a non-loopback address, by name.
+fn interfaces(state: &AppState) -> Vec<NetworkInterface> {
+    let mut interfaces: Vec<NetworkInterface> = Vec::new();
+    for address in address::local_addresses(state.local_ip) {
+        let Some(name) = address.interface.clone() else {
+            continue;
+        };
+        match interfaces.iter_mut().find(|interface| interface.name == name) {
+            Some(interface) => interface.addresses.push(address),
+            None => interfaces.push(NetworkInterface {
+                active: state.settings.bind_interface.as_ref() == Some(&name),
+                name,
+                addresses: vec![address],
+            }),
+        }
+    }
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    interfaces
+}
+
+/// The addresses of interface `name`, or an error if it has none.
+fn addresses_of(state: &AppState, name: &str) -> Result<Vec<String>, RpcError> {
+    interfaces(state)
+        .into_iter()
+        .find(|interface| interface.name == name)
+        .map(|interface| interface.addresses.into_iter().map(|address| address.ip).collect())
+        .ok_or_else(|| RpcError::InvalidInput(Text::new("input.unknown_interface").with("interface", name)))
+}
+
+/// Fails if `name` isn't an interface of this device.
+pub fn validate(state: &AppState, name: &str) -> Result<(), RpcError> {
+    addresses_of(state, name).map(|_| ())
+}
+
+/// Arguments binding a new sidecar to `bind_interface`, if one is set and
+/// still there.
+pub fn sidecar_args(state: &AppState) -> Vec<String> {
+    let Some(name) = &state.settings.bind_interface else {
+        return Vec::new();
+    };
+    let addresses = match addresses_of(state, name) {
+        Ok(addresses) => addresses,
+        Err(_) => {
+            warn!("Interface {} is gone; binding to every interface", name);
+            return Vec::new();
+        }
+    };
+    let mut args = vec!["--interface".to_string(), name.clone()];
+    for address in addresses {
+        args.extend(["--bind".to_string(), address]);
+    }
+    args
+}
+
+/// Tell the running sidecar to rebind to `bind_interface`. Nothing to do
+/// if it isn't running: it gets `--interface` on start.
+pub async fn push(state: &SharedState) -> Result<(), RpcError> {
+    let params = {
+        let state = state.lock().await;
+        let interface = state.settings.bind_interface.clone();
+        let addresses = match &interface {
+            Some(name) => addresses_of(&state, name)?,
+            None => Vec::new(),
+        };
+        serde_json::json!({ "interface": interface, "addresses": addresses })
+    };
+    match call_sidecar(state, "set_bind_interface", params).await {
+        Ok(_) | Err(RpcError::SidecarNotRunning) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// This device's network interfaces and their addresses.
+#[tauri::command]
+pub async fn list_interfaces(state: tauri::State<'_, SharedState>) -> Result<Vec<NetworkInterface>, RpcError> {
+    Ok(interfaces(&*state.lock().await))
+}
+
+/// Bind discovery and connections to interface `name`, or to every
+/// interface with `None`, and persist the choice.
+///
+/// The sidecar rebinds and restarts discovery, which drops current
+/// connections; the last peer is reconnected to if `auto_reconnect` is on.
+#[tauri::command]
+pub async fn set_bind_interface(app: AppHandle, name: Option<String>) -> Result<(), RpcError> {
+    let state = app.state::<SharedState>().inner().clone();
+    {
+        let mut state = state.lock().await;
+        if let Some(name) = &name {
+            validate(&state, name)?;
+        }
+        state.settings.bind_interface = name;
+        state.save_settings();
+    }
+    push(&state).await?;
+    spawn_auto_reconnect(&app, false);
+    Ok(())
+}
@@ -0,0 +1,665 @@
+//! The sidecar's wire format: newline-delimited JSON over stdin/stdout.
+//!
+//! Nothing here knows about Tauri or `AppState`; it turns requests into
+//! bytes and bytes back into messages, so it works the same against the
+//! real process, the mock, or any other pair of pipes. `SidecarClient`
+//! adds the bookkeeping on top: request ids, and which caller is waiting
+//! for which reply.
+//!
+//! A request made while a trace id is in scope (see `traced`) carries it
+//! on the wire; the sidecar echoes it on the reply and on every event the
+//! request leads to, so one user action can be followed across all of them.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+use crate::{RpcError, RpcResult};
+
+/// Longest stdout line we are willing to buffer while waiting for its `\n`.
+pub const MAX_LINE_LEN: usize = 1024 * 1024;
+
+/// Keys whose values never appear in logs.
+const SECRET_KEYS: [&str; 4] = ["passphrase", "pairing_secret", "pairing_token", "token"];
+
+/// A request written to the sidecar's stdin.
+///
+/// Each request is serialized as a single line of JSON followed by `\n`:
+///
+/// ```json
+/// {"id": 1, "method": "connect", "params": {"host": "192.168.1.100", "port": 52525}}
+/// ```
+///
+/// `params` is always an object (use `{}` for methods without arguments).
+/// The sidecar echoes `id` back on the matching result/error line.
+#[derive(Debug, Serialize)]
+struct RpcRequest<'a> {
+    id: u64,
+    method: &'a str,
+    params: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RpcResponse {
+    id: Option<u64>,
+    result: Option<serde_json::Value>,
+    error: Option<String>,
+    event: Option<String>,
+    data: Option<serde_json::Value>,
+    trace_id: Option<String>,
+}
+
+/// One line of sidecar output.
+#[derive(Debug)]
+pub enum Message {
+    /// The outcome of a request; `id` is `None` when the sidecar couldn't
+    /// tell which one (say, the request line didn't parse).
+    Reply {
+        id: Option<u64>,
+        result: Result<serde_json::Value, String>,
+        trace_id: Option<String>,
+    },
+    /// Something the sidecar pushed unprompted.
+    Event {
+        name: String,
+        data: serde_json::Value,
+        trace_id: Option<String>,
+    },
+}
+
+tokio::task_local! {
+    static TRACE_ID: Option<String>;
+}
+
+/// Run `future` with `trace_id` in scope: requests it makes are tagged
+/// with it, and so are the events it emits. Tasks it spawns don't inherit
+/// the id unless wrapped again.
+pub async fn traced<F: Future>(trace_id: Option<String>, future: F) -> F::Output {
+    TRACE_ID.scope(trace_id, future).await
+}
+
+/// The trace id in scope, if any.
+pub fn trace_id() -> Option<String> {
+    TRACE_ID.try_with(Clone::clone).ok().flatten()
+}
+
+/// Serialize a request as one line, including its `\n`.
+pub fn encode_request(id: u64, method: &str, params: serde_json::Value) -> serde_json::Result<Vec<u8>> {
+    let trace_id = trace_id();
+    let mut line = serde_json::to_vec(&RpcRequest { id, method, params, trace_id })?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Parse one line, as produced by `LineFramer`.
+pub fn decode_line(line: &[u8]) -> serde_json::Result<Message> {
+    let response: RpcResponse = serde_json::from_slice(line)?;
+    let trace_id = response.trace_id;
+    Ok(match response.event {
+        Some(name) => Message::Event { name, data: response.data.unwrap_or_default(), trace_id },
+        None => Message::Reply {
+            id: response.id,
+            result: match response.error {
+                Some(error) => Err(error),
+                None => Ok(response.result.unwrap_or_default()),
+            },
+            trace_id,
+        },
+    })
+}
+
+/// Reassembles newline-delimited JSON from stdout chunks that may split a
+/// message anywhere (or hold several).
+#[derive(Default)]
+pub struct LineFramer {
+    buf: Vec<u8>,
+    /// Skipping the rest of an oversized line up to its newline.
+    discarding: bool,
+}
+
+impl LineFramer {
+    /// Feed a chunk and get back every line it completed, without the `\n`.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+        let mut lines = Vec::new();
+        for piece in chunk.split_inclusive(|&b| b == b'\n') {
+            let complete = piece.ends_with(b"\n");
+            if self.discarding {
+                self.discarding = !complete;
+                continue;
+            }
+
+            self.buf.extend_from_slice(piece);
+            if complete {
+                let mut line = std::mem::take(&mut self.buf);
+                line.pop();
+                if !line.is_empty() {
+                    lines.push(line);
+                }
+            } else if self.buf.len() > MAX_LINE_LEN {
+                warn!("Dropping sidecar output line over {} bytes", MAX_LINE_LEN);
+                self.buf = Vec::new();
+                self.discarding = true;
+            }
+        }
+        lines
+    }
+}
+
+/// Replace secret values anywhere in `value`, for debug logging of payloads.
+pub fn redact_secrets(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) {
+                    *value = "<redacted>".into();
+                } else {
+                    redact_secrets(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// `line` with the values of secret keys replaced, for log text rather
+/// than payloads: `key=value`, `key: value` and JSON's `"key": "value"`.
+pub fn redact_line(line: &str) -> String {
+    let mut redacted = String::with_capacity(line.len());
+    let mut i = 0;
+    while let Some(c) = line[i..].chars().next() {
+        let at_word_start = line[..i].chars().next_back().is_none_or(|c| !c.is_alphanumeric() && c != '_');
+        let secret = at_word_start
+            .then(|| SECRET_KEYS.iter().find_map(|key| secret_value(&line[i..], key)))
+            .flatten();
+        match secret {
+            Some((prefix, value)) => {
+                redacted.push_str(&line[i..i + prefix]);
+                redacted.push_str("<redacted>");
+                i += prefix + value;
+            }
+            None => {
+                redacted.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    redacted
+}
+
+/// If `text` starts with `key` and its value, the length of everything
+/// before the value and of the value itself.
+fn secret_value(text: &str, key: &str) -> Option<(usize, usize)> {
+    let rest = text.strip_prefix(key)?;
+    let rest = rest.strip_prefix('"').unwrap_or(rest);
+    let rest = rest.trim_start().strip_prefix(['=', ':'])?.trim_start();
+    let (rest, value) = match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let inner = &rest[1..];
+            (inner, inner.find(quote).unwrap_or(inner.len()))
+        }
+        _ => (rest, rest.find(|c: char| c.is_whitespace() || ",;)]}".contains(c)).unwrap_or(rest.len())),
+    };
+    (value > 0).then(|| (text.len() - rest.len(), value))
+}
+
+/// An outstanding request, as `SidecarClient::pending` reports it.
+/// Params are deliberately not kept.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingRequest {
+    pub id: u64,
+    pub method: String,
+    pub waiting_ms: u64,
+}
+
+struct Waiter {
+    tx: oneshot::Sender<RpcResult>,
+    method: String,
+    sent_at: Instant,
+}
+
+/// Where requests are written; the sidecar's stdin.
+pub trait Transport {
+    /// Write one whole request. Nothing may be held back in a buffer:
+    /// there is no flush, and the sidecar has to see each request before
+    /// the next one is written.
+    fn write(&mut self, buf: &[u8]) -> Result<(), String>;
+}
+
+/// Request/response correlation over a `Transport`.
+///
+/// Requests are written whole, one per call, so as long as the client sits
+/// behind a lock concurrent callers can never interleave partial lines.
+/// Replies are handed back through `route`, which the stdout reader calls
+/// for every decoded line.
+pub struct SidecarClient<T> {
+    transport: Option<T>,
+    /// Last request id handed out. Lives as long as the client, not the
+    /// transport, so a stale reply from a previous sidecar can never match
+    /// a request made to its replacement.
+    last_id: u64,
+    /// Requests awaiting a reply with a matching `id`.
+    pending: HashMap<u64, Waiter>,
+    /// Requests given up by `cancel` whose reply hasn't arrived; it is
+    /// dropped when it does.
+    cancelled: HashSet<u64>,
+}
+
+impl<T: Transport> SidecarClient<T> {
+    pub fn new() -> Self {
+        SidecarClient {
+            transport: None,
+            last_id: 0,
+            pending: HashMap::new(),
+            cancelled: HashSet::new(),
+        }
+    }
+
+    /// Start writing to `transport`, e.g. a freshly spawned sidecar.
+    pub fn attach(&mut self, transport: T) {
+        self.transport = Some(transport);
+    }
+
+    /// Stop writing anywhere, returning the transport that was attached.
+    pub fn detach(&mut self) -> Option<T> {
+        self.transport.take()
+    }
+
+    pub fn transport(&self) -> Option<&T> {
+        self.transport.as_ref()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    /// Allocate the next request id; ids start at 1 and never repeat.
+    pub fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// The most recently allocated request id (0 if none yet).
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Requests still waiting for a reply; callers that went away count as
+    /// resolved.
+    pub fn pending_count(&self) -> usize {
+        self.pending.values().filter(|waiter| !waiter.tx.is_closed()).count()
+    }
+
+    /// Requests still waiting for a reply, oldest first.
+    pub fn pending(&self) -> Vec<PendingRequest> {
+        let mut pending: Vec<PendingRequest> = self
+            .pending
+            .iter()
+            .filter(|(_, waiter)| !waiter.tx.is_closed())
+            .map(|(&id, waiter)| PendingRequest {
+                id,
+                method: waiter.method.clone(),
+                waiting_ms: waiter.sent_at.elapsed().as_millis() as u64,
+            })
+            .collect();
+        pending.sort_by_key(|request| request.id);
+        pending
+    }
+
+    /// `write`, refused with `Overloaded` once `limit` requests are
+    /// outstanding.
+    pub fn send(
+        &mut self,
+        method: &str,
+        params: serde_json::Value,
+        limit: usize,
+    ) -> Result<(u64, oneshot::Receiver<RpcResult>), RpcError> {
+        self.pending.retain(|_, waiter| !waiter.tx.is_closed());
+        if self.pending.len() >= limit {
+            warn!(method, "Refusing request: {} already pending", self.pending.len());
+            return Err(RpcError::Overloaded);
+        }
+        self.write(method, params)
+    }
+
+    /// Write a request as one line. The returned receiver resolves once
+    /// `route` sees the matching reply.
+    pub fn write(
+        &mut self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<(u64, oneshot::Receiver<RpcResult>), RpcError> {
+        let id = self.next_id();
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            let mut params = params.clone();
+            redact_secrets(&mut params);
+            debug!(id, method, "-> {}", params);
+        }
+        let line = encode_request(id, method, params)?;
+
+        let transport = self.transport.as_mut().ok_or(RpcError::SidecarNotRunning)?;
+        transport.write(&line).map_err(RpcError::Transport)?;
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.insert(id, Waiter { tx, method: method.to_string(), sent_at: Instant::now() });
+        Ok((id, rx))
+    }
+
+    /// Deliver a reply to whoever is waiting for it. Returns the messages
+    /// nobody asked for, events and unclaimed replies, for broadcasting.
+    pub fn route(&mut self, message: Message) -> Option<Message> {
+        let Message::Reply { id: Some(id), result, trace_id } = message else {
+            return Some(message);
+        };
+        if let Some(Waiter { tx, .. }) = self.pending.remove(&id) {
+            let result = result.map_err(|message| RpcError::Remote { code: 0, message });
+            // The caller may have given up; nothing left to do then
+            let _ = tx.send(result);
+            return None;
+        }
+        if self.cancelled.remove(&id) {
+            debug!(id, "Dropping response to cancelled request");
+            return None;
+        }
+        Some(Message::Reply { id: Some(id), result, trace_id })
+    }
+
+    /// Fail request `id` with `Cancelled` and drop its reply when it comes.
+    /// Returns false if it isn't pending.
+    pub fn cancel(&mut self, id: u64) -> bool {
+        let Some(Waiter { tx, .. }) = self.pending.remove(&id) else {
+            return false;
+        };
+        self.cancelled.insert(id);
+        let _ = tx.send(Err(RpcError::Cancelled));
+        true
+    }
+
+    /// Stop waiting for request `id`, so a late reply can't find a sender.
+    pub fn forget(&mut self, id: u64) {
+        self.pending.remove(&id);
+    }
+
+    /// Fail every outstanding request with `error` and forget cancelled
+    /// ones, e.g. because the sidecar they went to is gone.
+    pub fn fail_all(&mut self, error: impl Fn() -> RpcError) {
+        for (_, waiter) in self.pending.drain() {
+            let _ = waiter.tx.send(Err(error()));
+        }
+        self.cancelled.clear();
+    }
+}
+
+/// Wait up to `timeout` for a reply from `SidecarClient::write`.
+///
+/// On `Timeout` the request is still registered; the caller should
+/// `forget` it.
+pub async fn wait(rx: oneshot::Receiver<RpcResult>, timeout: Duration) -> RpcResult {
+    match tokio::time::timeout(timeout, rx).await {
+        Ok(response) => response
+            .map_err(|_| RpcError::Transport("sidecar dropped the request".to_string()))?,
+        Err(_) => Err(RpcError::Timeout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the sidecar's stdin.
+    #[derive(Default)]
+    struct Pipe {
+        written: Vec<u8>,
+    }
+
+    impl Transport for Pipe {
+        fn write(&mut self, buf: &[u8]) -> Result<(), String> {
+            self.written.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    fn client() -> SidecarClient<Pipe> {
+        let mut client = SidecarClient::new();
+        client.attach(Pipe::default());
+        client
+    }
+
+    fn reply(id: u64, result: Result<serde_json::Value, String>) -> Message {
+        Message::Reply { id: Some(id), result, trace_id: None }
+    }
+
+    /// The requests written so far, parsed back.
+    fn written(client: &SidecarClient<Pipe>) -> Vec<serde_json::Value> {
+        let written = &client.transport().unwrap().written;
+        written
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn write_sends_one_line_per_request() {
+        let mut client = client();
+        let (first, _rx1) = client.write("get_status", serde_json::json!({})).unwrap();
+        let (second, _rx2) = client.write("connect", serde_json::json!({ "host": "10.0.0.2" })).unwrap();
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(written(&client), vec![
+            serde_json::json!({ "id": 1, "method": "get_status", "params": {} }),
+            serde_json::json!({ "id": 2, "method": "connect", "params": { "host": "10.0.0.2" } }),
+        ]);
+    }
+
+    #[test]
+    fn route_delivers_replies_by_id() {
+        let mut client = client();
+        let (first, mut rx1) = client.write("get_status", serde_json::json!({})).unwrap();
+        let (second, mut rx2) = client.write("get_peers", serde_json::json!({})).unwrap();
+
+        assert!(client.route(reply(second, Err("boom".to_string()))).is_none());
+        assert!(matches!(rx2.try_recv(), Ok(Err(RpcError::Remote { code: 0, message })) if message == "boom"));
+        assert!(rx1.try_recv().is_err());
+
+        assert!(client.route(reply(first, Ok(serde_json::json!({ "ok": true })))).is_none());
+        assert_eq!(rx1.try_recv().unwrap().unwrap(), serde_json::json!({ "ok": true }));
+        assert_eq!(client.pending_count(), 0);
+    }
+
+    #[test]
+    fn route_returns_events_and_unclaimed_replies() {
+        let mut client = client();
+        let event = Message::Event { name: "peer_lost".to_string(), data: serde_json::json!({}), trace_id: None };
+        assert!(matches!(client.route(event), Some(Message::Event { name, .. }) if name == "peer_lost"));
+        let unclaimed = client.route(reply(42, Ok(serde_json::Value::Null)));
+        assert!(matches!(unclaimed, Some(Message::Reply { id: Some(42), .. })));
+        let anonymous = Message::Reply { id: None, result: Err("bad line".to_string()), trace_id: None };
+        assert!(matches!(client.route(anonymous), Some(Message::Reply { id: None, .. })));
+    }
+
+    #[test]
+    fn framer_reassembles_split_lines() {
+        let mut framer = LineFramer::default();
+        assert!(framer.push(br#"{"id": 1, "res"#).is_empty());
+        assert_eq!(framer.push(b"ult\": 2}\n{\"id\""), vec![br#"{"id": 1, "result": 2}"#.to_vec()]);
+        assert_eq!(framer.push(b": 2}\n"), vec![br#"{"id": 2}"#.to_vec()]);
+    }
+
+    #[test]
+    fn framer_splits_several_lines_in_one_chunk() {
+        let mut framer = LineFramer::default();
+        let lines = framer.push(b"{\"id\": 1}\n\n{\"id\": 2}\n{\"id\"");
+        assert_eq!(lines, vec![br#"{"id": 1}"#.to_vec(), br#"{"id": 2}"#.to_vec()]);
+    }
+
+    #[test]
+    fn framer_drops_oversized_line_up_to_its_newline() {
+        let mut framer = LineFramer::default();
+        assert!(framer.push(&vec![b'x'; MAX_LINE_LEN + 1]).is_empty());
+        assert!(framer.push(&vec![b'x'; 1024]).is_empty());
+        assert_eq!(framer.push(b"xx\n{\"id\": 3}\n"), vec![br#"{"id": 3}"#.to_vec()]);
+    }
+
+    #[test]
+    fn framer_keeps_line_at_the_cap() {
+        let mut framer = LineFramer::default();
+        let line = vec![b'x'; MAX_LINE_LEN];
+        assert!(framer.push(&line).is_empty());
+        assert_eq!(framer.push(b"\n"), vec![line]);
+    }
+
+    #[test]
+    fn crlf_lines_decode() {
+        let mut framer = LineFramer::default();
+        let lines = framer.push(b"{\"id\": 4, \"result\": {\"ok\": true}}\r\n");
+        assert_eq!(lines.len(), 1);
+        let Ok(Message::Reply { id, result, .. }) = decode_line(&lines[0]) else {
+            panic!("not a reply");
+        };
+        assert_eq!(id, Some(4));
+        assert_eq!(result.unwrap(), serde_json::json!({ "ok": true }));
+    }
+
+    #[test]
+    fn decode_reply_error_and_event() {
+        let Ok(Message::Reply { id, result, trace_id }) =
+            decode_line(br#"{"id": 5, "error": "Not connected", "trace_id": "t1"}"#)
+        else {
+            panic!("not a reply");
+        };
+        assert_eq!((id, result.unwrap_err(), trace_id), (Some(5), "Not connected".to_string(), Some("t1".into())));
+
+        let Ok(Message::Event { name, data, trace_id }) = decode_line(br#"{"event": "peer_lost", "data": {"id": "a"}}"#)
+        else {
+            panic!("not an event");
+        };
+        assert_eq!((name.as_str(), data, trace_id), ("peer_lost", serde_json::json!({ "id": "a" }), None));
+    }
+
+    #[test]
+    fn decode_defaults_missing_fields() {
+        let Ok(Message::Reply { id: None, result: Ok(result), .. }) = decode_line(b"{}") else {
+            panic!("not an empty reply");
+        };
+        assert!(result.is_null());
+        let Ok(Message::Event { data, .. }) = decode_line(br#"{"event": "ready"}"#) else {
+            panic!("not an event");
+        };
+        assert!(data.is_null());
+        assert!(decode_line(b"not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn wait_times_out_and_forget_unclaims_the_reply() {
+        let mut client = client();
+        let (id, rx) = client.write("get_status", serde_json::json!({})).unwrap();
+        assert!(matches!(wait(rx, Duration::from_millis(10)).await, Err(RpcError::Timeout)));
+        client.forget(id);
+        assert_eq!(client.pending_count(), 0);
+        assert!(client.route(reply(id, Ok(serde_json::Value::Null))).is_some());
+    }
+
+    #[tokio::test]
+    async fn wait_returns_the_routed_reply() {
+        let mut client = client();
+        let (id, rx) = client.write("get_status", serde_json::json!({})).unwrap();
+        client.route(reply(id, Ok(serde_json::json!("pong"))));
+        assert_eq!(wait(rx, Duration::from_secs(1)).await.unwrap(), serde_json::json!("pong"));
+    }
+
+    #[test]
+    fn write_without_transport_fails_and_ids_survive_reattach() {
+        let mut client = client();
+        client.write("get_status", serde_json::json!({})).unwrap();
+        let detached = client.detach().unwrap();
+        assert!(!detached.written.is_empty());
+        assert!(!client.is_attached());
+        assert!(matches!(client.write("get_status", serde_json::json!({})), Err(RpcError::SidecarNotRunning)));
+
+        client.attach(Pipe::default());
+        let (id, _rx) = client.write("get_status", serde_json::json!({})).unwrap();
+        assert_eq!(id, client.last_id());
+        assert_eq!(written(&client), vec![serde_json::json!({ "id": 3, "method": "get_status", "params": {} })]);
+    }
+
+    #[test]
+    fn redact_secrets_replaces_nested_values() {
+        let mut value = serde_json::json!({
+            "host": "10.0.0.2",
+            "passphrase": "hunter2",
+            "peers": [{ "id": "a", "pairing_secret": "s3cret" }],
+            "auth": { "token": null }
+        });
+        redact_secrets(&mut value);
+        assert_eq!(value, serde_json::json!({
+            "host": "10.0.0.2",
+            "passphrase": "<redacted>",
+            "peers": [{ "id": "a", "pairing_secret": "<redacted>" }],
+            "auth": { "token": "<redacted>" }
+        }));
+    }
+
+    #[test]
+    fn redact_line_covers_each_form() {
+        assert_eq!(redact_line("token=abc123 next"), "token=<redacted> next");
+        assert_eq!(redact_line("pairing_token: 'a b', ok"), "pairing_token: '<redacted>', ok");
+        let json = r#"{"passphrase": "hunter2", "port": 1}"#;
+        assert_eq!(redact_line(json), r#"{"passphrase": "<redacted>", "port": 1}"#);
+        assert_eq!(redact_line("pairing_secret=x;y"), "pairing_secret=<redacted>;y");
+    }
+
+    #[test]
+    fn redact_line_leaves_other_words_alone() {
+        for line in ["tokens=5", "my_token=abc", "token", "token= ", "Got passphrase prompt", "ünïcode token"] {
+            assert_eq!(redact_line(line), line);
+        }
+    }
+
+    #[test]
+    fn cancel_fails_the_request_and_swallows_its_reply() {
+        let mut client = client();
+        let (id, mut rx) = client.write("probe", serde_json::json!({})).unwrap();
+        assert!(client.cancel(id));
+        assert!(matches!(rx.try_recv(), Ok(Err(RpcError::Cancelled))));
+        assert!(!client.cancel(id));
+        assert!(client.route(reply(id, Ok(serde_json::Value::Null))).is_none());
+        // Only one reply was owed; another with the id is nobody's
+        assert!(client.route(reply(id, Ok(serde_json::Value::Null))).is_some());
+    }
+
+    #[test]
+    fn fail_all_fails_every_pending_request() {
+        let mut client = client();
+        let (_, mut rx1) = client.write("connect", serde_json::json!({})).unwrap();
+        let (_, mut rx2) = client.write("get_status", serde_json::json!({})).unwrap();
+        let (cancelled, _rx3) = client.write("probe", serde_json::json!({})).unwrap();
+        client.cancel(cancelled);
+
+        client.fail_all(|| RpcError::SidecarNotRunning);
+        assert!(matches!(rx1.try_recv(), Ok(Err(RpcError::SidecarNotRunning))));
+        assert!(matches!(rx2.try_recv(), Ok(Err(RpcError::SidecarNotRunning))));
+        assert_eq!(client.pending_count(), 0);
+        assert!(client.route(reply(cancelled, Ok(serde_json::Value::Null))).is_some());
+    }
+
+    #[test]
+    fn send_refuses_beyond_the_limit() {
+        let mut client = client();
+        let (_, _rx1) = client.send("connect", serde_json::json!({}), 2).unwrap();
+        let (id, _rx2) = client.send("probe", serde_json::json!({}), 2).unwrap();
+        assert!(matches!(client.send("ping", serde_json::json!({}), 2), Err(RpcError::Overloaded)));
+        assert_eq!(written(&client).len(), 2);
+
+        // Answered and abandoned requests both free a slot
+        client.route(reply(id, Ok(serde_json::Value::Null)));
+        let (_, rx3) = client.send("ping", serde_json::json!({}), 2).unwrap();
+        drop(rx3);
+        assert!(client.send("ping", serde_json::json!({}), 2).is_ok());
+    }
+}
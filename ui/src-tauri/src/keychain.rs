@@ -0,0 +1,63 @@
+//! Pairing secrets kept in the platform keychain, one entry per peer id.
+//!
+//! Everything here degrades to "nothing stored": if no keychain backend is
+//! available the secret is simply not persisted and the next connection
+//! pairs with a passphrase again.
+
+use keyring::{Entry, Error};
+use tracing::{error, warn};
+
+/// Service name the entries are filed under.
+const SERVICE: &str = "deck-link";
+
+fn entry(peer_id: &str) -> Option<Entry> {
+    Entry::new(SERVICE, peer_id)
+        .map_err(|e| warn!("Keychain unavailable: {}", e))
+        .ok()
+}
+
+pub fn store_pairing_secret(peer_id: &str, secret: &str) {
+    if let Some(entry) = entry(peer_id) {
+        if let Err(e) = entry.set_password(secret) {
+            warn!("Not persisting pairing secret for {}: {}", peer_id, e);
+        }
+    }
+}
+
+pub fn load_pairing_secret(peer_id: &str) -> Option<String> {
+    match entry(peer_id)?.get_password() {
+        Ok(secret) => Some(secret),
+        Err(Error::NoEntry) => None,
+        Err(e) => {
+            error!("Failed to read pairing secret for {}: {}", peer_id, e);
+            None
+        }
+    }
+}
+
+/// Whether a secret is stored for `peer_id`, telling a missing one apart
+/// from a keychain that can't be read.
+pub fn has_pairing_secret(peer_id: &str) -> Result<bool, String> {
+    let Some(entry) = entry(peer_id) else {
+        return Err("keychain unavailable".to_string());
+    };
+    match entry.get_password() {
+        Ok(_) => Ok(true),
+        Err(Error::NoEntry) => Ok(false),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Errors are logged, and returned for callers that report them.
+pub fn clear_pairing_secret(peer_id: &str) -> Result<(), String> {
+    let Some(entry) = entry(peer_id) else {
+        return Err("keychain unavailable".to_string());
+    };
+    match entry.delete_credential() {
+        Ok(()) | Err(Error::NoEntry) => Ok(()),
+        Err(e) => {
+            error!("Failed to clear pairing secret for {}: {}", peer_id, e);
+            Err(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Link quality to the peer, sampled by the sidecar health check.
+//!
+//! The sidecar times its own heartbeat pings to the peer and reports the
+//! last round trip in each `ping` response; this keeps a short moving
+//! average of those and reports when the link looks bad.
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+
+use crate::messages::Text;
+use crate::{emit_event, ConnectionState, RpcError, SharedState};
+
+/// Samples in the moving average.
+const WINDOW: usize = 5;
+
+/// Round trips slower than this count as a degraded link.
+const DEGRADED_RTT_MS: f64 = 500.0;
+
+#[derive(Default)]
+pub struct Latency {
+    samples: VecDeque<f64>,
+    /// Whether `sidecar:connection_degraded` has been emitted for the
+    /// current stretch of bad samples.
+    degraded: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyReport {
+    /// Most recent round trip, if there is one.
+    pub rtt_ms: Option<f64>,
+    /// Mean of the last few round trips.
+    pub average_ms: Option<f64>,
+    pub degraded: bool,
+}
+
+impl Latency {
+    fn report(&self) -> LatencyReport {
+        let average_ms = (!self.samples.is_empty())
+            .then(|| self.samples.iter().sum::<f64>() / self.samples.len() as f64);
+        LatencyReport {
+            rtt_ms: self.samples.back().copied(),
+            average_ms,
+            degraded: self.degraded,
+        }
+    }
+}
+
+/// Fold in the outcome of one health-check ping: the response, or `None`
+/// if the ping failed.
+pub async fn record(app: &AppHandle, pong: Option<&serde_json::Value>) {
+    let state = app.state::<SharedState>().inner().clone();
+    let (sample, peer_id, newly_degraded) = {
+        let mut state = state.lock().await;
+        if state.connection_state != ConnectionState::Connected {
+            state.latency = Latency::default();
+            return;
+        }
+
+        let rtt = pong.and_then(|pong| pong.get("peer_rtt_ms")).and_then(|rtt| rtt.as_f64());
+        let reason = match rtt {
+            None if pong.is_none() => Some(Text::new("connection.sidecar_unresponsive")),
+            None => Some(Text::new("connection.peer_unresponsive")),
+            Some(rtt) if rtt > DEGRADED_RTT_MS => Some(Text::new("connection.high_latency")),
+            Some(_) => None,
+        };
+        if let Some(rtt) = rtt {
+            if state.latency.samples.len() >= WINDOW {
+                state.latency.samples.pop_front();
+            }
+            state.latency.samples.push_back(rtt);
+        }
+
+        let was_degraded = std::mem::replace(&mut state.latency.degraded, reason.is_some());
+        let peer_id = state.settings.last_peer.as_ref().and_then(|peer| peer.id.clone());
+        (rtt, peer_id, reason.filter(|_| !was_degraded))
+    };
+
+    if let Some(rtt) = sample {
+        emit_event(app, "sidecar:latency", serde_json::json!({
+            "rtt_ms": rtt,
+            "peer_id": peer_id
+        }))
+        .await;
+    }
+    if let Some(reason) = newly_degraded {
+        warn!("Connection degraded: {}", reason);
+        emit_event(app, "sidecar:connection_degraded", serde_json::json!({
+            "reason": reason.english(),
+            "message_code": reason.code,
+            "params": reason.params,
+            "rtt_ms": sample,
+            "peer_id": peer_id
+        }))
+        .await;
+    }
+}
+
+#[tauri::command]
+pub async fn get_latency(state: tauri::State<'_, SharedState>) -> Result<LatencyReport, RpcError> {
+    Ok(state.lock().await.latency.report())
+}
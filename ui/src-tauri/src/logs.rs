@@ -0,0 +1,148 @@
+//! Rotating on-disk logs: sidecar stderr, and the app's own `tracing` output.
+//!
+//! Release builds have no console, so anything printed there would
+//! otherwise be lost.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Registry};
+
+/// Adjusts the log level of the running subscriber.
+pub type LogLevelHandle = reload::Handle<LevelFilter, Registry>;
+
+/// Rotate the current file once it grows past this size.
+const MAX_LOG_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Number of log files kept, including the one being written.
+const KEEP_FILES: usize = 3;
+
+pub struct RotatingLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingLog {
+    /// Open (or create) the log at `path`, appending to existing content.
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `line` prefixed with a UTC timestamp, rotating first if needed.
+    pub fn write_line(&mut self, timestamp: &str, line: &str) -> io::Result<()> {
+        let entry = format!("{} {}\n", timestamp, line.trim_end());
+        self.write_entry(entry.as_bytes())
+    }
+
+    /// Append an already formatted entry, rotating first if needed.
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        if self.size >= MAX_LOG_SIZE {
+            self.rotate()?;
+        }
+
+        self.file.write_all(entry)?;
+        self.size += entry.len() as u64;
+        Ok(())
+    }
+
+    /// Shift `log.1` -> `log.2` etc., dropping the oldest, and start afresh.
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..KEEP_FILES).rev() {
+            let from = self.rotated_path(n - 1);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(n))?;
+            }
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// `log` for 0, `log.1`, `log.2`, ... for older files.
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        if n == 0 {
+            return self.path.clone();
+        }
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+}
+
+/// How much of a log `tail` reads at a time, working back from the end.
+const TAIL_BLOCK: u64 = 64 * 1024;
+
+/// The last `lines` lines of the log at `path`, oldest first.
+///
+/// Reads back from the end a block at a time, so a large log costs no
+/// more than the lines asked for.
+pub fn tail(path: &Path, lines: usize) -> io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let mut start = file.metadata()?.len();
+    let mut buf = Vec::new();
+    let mut newlines = 0;
+    // One newline more than lines: the one ending the line before the first
+    while start > 0 && newlines <= lines {
+        let end = start;
+        start = end.saturating_sub(TAIL_BLOCK);
+        let mut block = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut block)?;
+        newlines += block.iter().filter(|&&b| b == b'\n').count();
+        block.extend_from_slice(&buf);
+        buf = block;
+    }
+    let text = String::from_utf8_lossy(&buf);
+    let found: Vec<&str> = text.lines().collect();
+    Ok(found[found.len().saturating_sub(lines)..].iter().map(|line| line.to_string()).collect())
+}
+
+/// Writer handed to the `fmt` layer for each event; the layer formats a
+/// whole line (timestamp included) before writing it.
+struct LogFileWriter(Arc<Mutex<RotatingLog>>);
+
+impl Write for LogFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut log = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        log.write_entry(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Install the global `tracing` subscriber, starting at `info`.
+///
+/// Events go to `app_log` when there is one, and to stdout in debug builds.
+pub fn init_tracing(app_log: Option<RotatingLog>) -> LogLevelHandle {
+    let (filter, handle) = reload::Layer::new(LevelFilter::INFO);
+
+    let stdout = cfg!(debug_assertions).then(fmt::layer);
+    let file = app_log.map(|log| {
+        let log = Arc::new(Mutex::new(log));
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(move || LogFileWriter(log.clone()))
+    });
+
+    tracing_subscriber::registry().with(filter).with(stdout).with(file).init();
+    handle
+}
@@ -1,160 +1,2441 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use serde::{Deserialize, Serialize};
+mod address;
+mod bandwidth;
+mod clipboard_image;
+mod connections;
+mod data_dir;
+mod deep_link;
+mod device;
+mod diagnostics;
+mod discovery;
+mod events;
+mod file_drop;
+mod forget;
+mod history;
+mod idle;
+mod interfaces;
+mod ipc;
+mod keychain;
+mod latency;
+mod logs;
+mod messages;
+mod mock;
+mod network;
+mod notifications;
+mod onboarding;
+mod pairing;
+mod peers;
+mod policy;
+mod probe;
+mod quit;
+mod recovery;
+mod resume;
+mod settings;
+mod sidecar;
+mod storage;
+mod transfer_list;
+mod transfers;
+mod tray;
+mod trust;
+mod updates;
+mod window;
+
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::AtomicBool;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tauri::{AppHandle, Emitter, Manager};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, WindowEvent};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_shell::process::CommandChild;
 use tauri_plugin_shell::ShellExt;
-use tokio::sync::Mutex;
+use tokio::sync::{oneshot, Mutex, Notify};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use connections::PeerConnection;
+use data_dir::DataDirs;
+use deep_link::PairTarget;
+use device::DeviceInfo;
+use discovery::DiscoveryStatus;
+use events::{Connected, SidecarEvent};
+use ipc::{redact_line, redact_secrets, Message};
+use logs::{LogLevelHandle, RotatingLog};
+use messages::Text;
+use notifications::LastNotification;
+use settings::{LastPeer, Settings};
+use sidecar::{Child as _, Sidecar};
+use transfer_list::TransferList;
+use transfers::IncomingTransfer;
+
+/// Where we are in finding and talking to a peer.
+///
+/// Cached in `AppState` from sidecar events so the UI can read it without
+/// a round-trip; changes are announced as `sidecar:connection_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ConnectionState {
+    /// The sidecar isn't running, or the last connection ended.
+    #[default]
+    Disconnected,
+    /// The sidecar is up and browsing for peers.
+    Discovering,
+    Connecting,
+    /// Waiting on the passphrase exchange.
+    Pairing,
+    /// To at least one peer; see `connections`.
+    Connected,
+}
+
+/// A peer as reported by the sidecar's discovery.
+///
+/// This is the frontend's peer contract; evolve the schema here.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Peer {
+    /// mDNS service name; stable for as long as the peer advertises.
+    id: String,
+    name: String,
+    /// Same vocabulary as `device_type` in `get_status` ("laptop", "deck", ...).
+    device_type: String,
+    /// The address to connect to; the first of `addresses`.
+    ip: String,
+    /// Every address the peer advertises, IPv4 first.
+    #[serde(default)]
+    addresses: Vec<String>,
+    port: u16,
+    #[serde(default)]
+    paired: bool,
+}
+
+/// Errors returned by commands that talk to the sidecar.
+///
+/// Serialized to the frontend as `{ "kind": ..., "message": ...,
+/// "message_code": ..., "params": ... }`, where `kind` is one of
+/// `sidecar_not_running`, `not_connected`, `timeout`, `transport`,
+/// `remote`, `rate_limited`, `cancelled`, `overloaded`, `busy`, `serde`,
+/// `invalid_input` or `storage`. `remote` errors also carry the
+/// sidecar's numeric `code`: one of `ConnectError`'s for a failed
+/// connection, 0 when the sidecar didn't classify the failure. `kind`,
+/// `code` and `message_code` values are part of the frontend contract;
+/// `message` is English for logs, see `messages`.
+#[derive(Debug, thiserror::Error)]
+enum RpcError {
+    #[error("sidecar not running")]
+    SidecarNotRunning,
+    /// The command needs a connected peer and there is none.
+    #[error("not connected to a peer")]
+    NotConnected,
+    #[error("request timed out")]
+    Timeout,
+    #[error("transport error: {0}")]
+    Transport(String),
+    #[error("{message}")]
+    Remote { code: i32, message: String },
+    /// The peer refused further passphrase attempts for now.
+    #[error("{0}")]
+    RateLimited(String),
+    /// Abandoned through `cancel_request`, or by a sidecar restart.
+    #[error("request cancelled")]
+    Cancelled,
+    /// Too many requests already waiting on the sidecar.
+    #[error("too many pending requests")]
+    Overloaded,
+    /// Already talking to a peer; we pair with one at a time.
+    #[error("busy with another peer")]
+    Busy,
+    #[error("invalid payload: {0}")]
+    Serde(#[from] serde_json::Error),
+    /// Command arguments rejected before anything was sent.
+    #[error("{0}")]
+    InvalidInput(Text),
+    /// Reading or writing the settings, the keychain or other local files
+    /// failed; the sidecar wasn't involved.
+    #[error("{0}")]
+    Storage(Text),
+}
+
+impl RpcError {
+    fn kind(&self) -> &'static str {
+        match self {
+            RpcError::SidecarNotRunning => "sidecar_not_running",
+            RpcError::NotConnected => "not_connected",
+            RpcError::Timeout => "timeout",
+            RpcError::Transport(_) => "transport",
+            RpcError::Remote { .. } => "remote",
+            RpcError::RateLimited(_) => "rate_limited",
+            RpcError::Cancelled => "cancelled",
+            RpcError::Overloaded => "overloaded",
+            RpcError::Busy => "busy",
+            RpcError::Serde(_) => "serde",
+            RpcError::InvalidInput(_) => "invalid_input",
+            RpcError::Storage(_) => "storage",
+        }
+    }
+
+    /// The message as a code for the frontend to localize.
+    fn text(&self) -> Text {
+        match self {
+            RpcError::SidecarNotRunning => Text::new("error.sidecar_not_running"),
+            RpcError::NotConnected => Text::new("error.not_connected"),
+            RpcError::Timeout => Text::new("error.timeout"),
+            RpcError::Transport(detail) => Text::detail("error.transport", detail),
+            RpcError::Remote { code, message } => Text::detail(ConnectError::from_code(*code).message_code(), message),
+            RpcError::RateLimited(detail) => Text::detail("error.rate_limited", detail),
+            RpcError::Cancelled => Text::new("error.cancelled"),
+            RpcError::Overloaded => Text::new("error.overloaded"),
+            RpcError::Busy => Text::new("error.busy"),
+            RpcError::Serde(e) => Text::detail("error.invalid_payload", e),
+            RpcError::InvalidInput(text) | RpcError::Storage(text) => text.clone(),
+        }
+    }
+}
+
+impl Serialize for RpcError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let code = match self {
+            RpcError::Remote { code, .. } => Some(*code),
+            _ => None,
+        };
+
+        let text = self.text();
+        let mut error = serializer.serialize_struct("RpcError", 4 + code.is_some() as usize)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.serialize_field("message_code", text.code)?;
+        error.serialize_field("params", &text.params)?;
+        if let Some(code) = code {
+            error.serialize_field("code", &code)?;
+        }
+        error.end()
+    }
+}
+
+type RpcResult = Result<serde_json::Value, RpcError>;
+
+/// Why a connection attempt failed, as the sidecar classifies it in the
+/// `code` of its `connection_error` and `auth_failed` events. Surfaced as
+/// `RpcError::Remote` with `code()` as its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ConnectError {
+    /// The peer's host couldn't be reached at all.
+    PeerOffline,
+    /// The host is up but turned the connection down: nothing listening,
+    /// a firewall, or the peer declining.
+    Refused,
+    Timeout,
+    WrongPassphrase,
+    /// Whatever answered doesn't speak our protocol.
+    IncompatibleVersion,
+    /// The peer's identity or the secure channel couldn't be verified.
+    TlsError,
+    /// A code this build doesn't know, from a newer sidecar.
+    #[serde(other)]
+    Unknown,
+}
+
+impl ConnectError {
+    const fn code(self) -> i32 {
+        match self {
+            ConnectError::Unknown => 0,
+            ConnectError::PeerOffline => 1001,
+            ConnectError::Refused => 1002,
+            ConnectError::Timeout => 1003,
+            ConnectError::WrongPassphrase => 1004,
+            ConnectError::IncompatibleVersion => 1005,
+            ConnectError::TlsError => 1006,
+        }
+    }
+
+    fn from_code(code: i32) -> Self {
+        [
+            ConnectError::PeerOffline,
+            ConnectError::Refused,
+            ConnectError::Timeout,
+            ConnectError::WrongPassphrase,
+            ConnectError::IncompatibleVersion,
+            ConnectError::TlsError,
+        ]
+        .into_iter()
+        .find(|error| error.code() == code)
+        .unwrap_or(ConnectError::Unknown)
+    }
+
+    /// Whether trying again later might work: the peer wasn't reachable,
+    /// rather than unwilling or unable to pair.
+    fn is_retryable(self) -> bool {
+        matches!(self, ConnectError::PeerOffline | ConnectError::Refused | ConnectError::Timeout)
+    }
 
+    const fn message_code(self) -> &'static str {
+        match self {
+            // Including sidecar errors that aren't about connecting at all
+            ConnectError::Unknown => "error.remote",
+            ConnectError::PeerOffline => "error.connect.peer_offline",
+            ConnectError::Refused => "error.connect.refused",
+            ConnectError::Timeout => "error.connect.timeout",
+            ConnectError::WrongPassphrase => "error.connect.wrong_passphrase",
+            ConnectError::IncompatibleVersion => "error.connect.incompatible_version",
+            ConnectError::TlsError => "error.connect.tls_error",
+        }
+    }
+
+    fn into_rpc(self, message: String) -> RpcError {
+        RpcError::Remote { code: self.code(), message }
+    }
+}
+
+impl RpcError {
+    /// Whether a failed connection attempt is worth retrying.
+    fn is_retryable_connect(&self) -> bool {
+        match self {
+            RpcError::Transport(_) => true,
+            RpcError::Remote { code, .. } => ConnectError::from_code(*code).is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+/// Largest clipboard text we send or accept: 1 MB.
+const MAX_CLIPBOARD_BYTES: usize = 1_000_000;
+
+/// What a line of sidecar output needs besides the clipboard text in it:
+/// the rest of the event, the origin and a trace id.
+const CLIPBOARD_LINE_OVERHEAD: usize = 1024;
+
+/// Clipboard text shared between devices.
+///
+/// `origin` identifies the app instance where the text was first copied;
+/// it is kept as the update bounces around so its originator can drop it.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct RpcResponse {
-    id: Option<u64>,
-    result: Option<serde_json::Value>,
-    error: Option<String>,
-    event: Option<String>,
-    data: Option<serde_json::Value>,
+struct ClipboardUpdate {
+    text: String,
+    origin: String,
+}
+
+/// How long to wait for a response when a method has no entry in `rpc_timeout`.
+const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Per-method response timeouts.
+///
+/// Pairing involves the user reading a passphrase off the other device,
+/// so those round-trips get far longer than a status poll; so does the
+/// first chunk of a transfer, which the sidecar holds until the user on
+/// the other side accepts it (for up to two minutes).
+fn rpc_timeout(method: &str) -> Duration {
+    match method {
+        "connect" | "submit_passphrase" => Duration::from_secs(60),
+        "transfer_chunk" => Duration::from_secs(130),
+        _ => DEFAULT_RPC_TIMEOUT,
+    }
 }
 
 struct AppState {
-    sidecar_running: bool,
+    /// The sidecar process and requests to it; see `sidecar`.
+    sidecar: Sidecar<SidecarChild>,
+    /// Set once quitting is confirmed despite transfers in progress, until
+    /// the quit is abandoned; notifying it abandons the quit. See `quit`.
+    pending_quit: Option<Arc<Notify>>,
+    /// Requests in a row the sidecar didn't answer in time; see `recovery`.
+    rpc_timeouts: u32,
+    /// When an unresponsive sidecar was last restarted by `recovery`.
+    last_recovery: Option<Instant>,
+    /// Last activity on the link while a peer is connected; see `idle`.
+    last_activity: Option<Instant>,
+    /// Disconnects once the link has been idle too long.
+    idle_timer: Option<tauri::async_runtime::JoinHandle<()>>,
+    /// Periodic ping task for the current sidecar process.
+    health_check: Option<tauri::async_runtime::JoinHandle<()>>,
+    /// Unix time (ms) of the last ping the sidecar answered.
+    last_pong_at: Option<u64>,
+    /// Round trips to the peer, from the health-check pings.
+    latency: latency::Latency,
+    /// Protocol version the latest sidecar reported in its ready handshake.
+    protocol_version: Option<String>,
+    /// Package version the latest sidecar reported in its ready handshake.
+    sidecar_version: Option<String>,
+    /// Payload of the last `sidecar:missing`, until a start succeeds.
+    sidecar_missing: Option<serde_json::Value>,
+    /// Set by `frontend_ready` once the webview's listeners are registered.
+    frontend_ready: bool,
+    /// Events emitted before the frontend was ready, oldest first.
+    event_buffer: VecDeque<(String, serde_json::Value)>,
+    /// The most recent events emitted, oldest first, for the debug console.
+    event_log: VecDeque<LoggedEvent>,
+    /// Identifies this app instance as the origin of clipboard updates.
+    origin_id: String,
+    /// The last clipboard update applied from a peer.
+    last_remote_clipboard: Option<ClipboardUpdate>,
+    /// Digest and origin of the last clipboard image applied from a peer.
+    last_remote_image: Option<(u64, String)>,
+    /// Cancel flags for outgoing transfers in progress, by transfer id.
+    transfers: HashMap<String, Arc<AtomicBool>>,
+    /// Transfer ids started together by `send_files`, by batch id.
+    batches: HashMap<String, Vec<String>>,
+    /// Transfers offered by the peer, pending or being received.
+    incoming_transfers: HashMap<String, IncomingTransfer>,
+    /// Transfers in either direction, for `list_transfers`.
+    transfer_list: TransferList,
+    /// Local IP as last seen by the network monitor; `None` when offline.
+    local_ip: Option<std::net::IpAddr>,
+    /// Cached by `device::local_device`.
+    local_device: Option<DeviceInfo>,
+    /// Discovered peers by id; see `peers`.
+    peers: HashMap<String, Peer>,
+    /// Last `discovery_status` the sidecar reported.
+    discovery: Option<DiscoveryStatus>,
+    /// When each discovered peer was last reported.
+    peers_seen: HashMap<String, Instant>,
+    /// When `sidecar:peers_changed` was last emitted; see `peers`.
+    peers_emitted_at: Option<Instant>,
+    /// A trailing `sidecar:peers_changed` is already queued.
+    peers_emit_scheduled: bool,
+    /// `Some` while a rescan runs, holding callers waiting on its result.
+    rescan_waiters: Option<Vec<oneshot::Sender<Vec<Peer>>>>,
+    /// Kept current by `set_connection_state`.
+    connection_state: ConnectionState,
+    /// Established connections by session id; see `connections`.
+    connections: HashMap<String, PeerConnection>,
+    /// Session id of the connection handshake in progress, once the
+    /// sidecar has named it.
+    handshake_session: Option<String>,
+    last_notification: Option<LastNotification>,
+    /// Persisted user settings; see `save_settings`.
+    settings: Settings,
+    /// Where `settings` is stored. `None` if the config dir is unavailable,
+    /// in which case settings last only for this run.
+    settings_path: Option<PathBuf>,
+    /// Address passed to the most recent `connect`, so the `connected`
+    /// event (which carries no port) can be recorded as the last peer.
+    connect_target: Option<(String, u16)>,
+    /// Wakes a `connect` waiting to retry, so it gives up.
+    connect_retry_cancel: Option<Arc<Notify>>,
+    /// Callers waiting for the current connection handshake to finish, by
+    /// the id announced in `sidecar:request_started`.
+    handshake_waiters: HashMap<u64, oneshot::Sender<RpcResult>>,
+    /// Id of the incoming pairing request the user is being asked about.
+    pairing_prompt: Option<String>,
+    /// Pairing request that arrived before the sidecar was ready.
+    pending_pair: Option<PairTarget>,
+    /// A debounced save of the window geometry is already queued.
+    window_save_scheduled: bool,
+}
+
+impl AppState {
+    /// Write `settings` to disk. Failures are logged, not returned: the
+    /// in-memory settings still apply for this run.
+    fn save_settings(&self) {
+        if let Some(path) = &self.settings_path {
+            if let Err(e) = self.settings.save(path) {
+                error!("Failed to save settings to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Write a request to the sidecar.
+    ///
+    /// Callers hold the state lock for the whole write, so concurrent
+    /// commands can never interleave partial lines.
+    ///
+    /// Fails with `SidecarNotRunning` until the sidecar is ready, and with
+    /// `Overloaded` once `settings.max_pending_requests` are outstanding.
+    fn send_rpc(
+        &mut self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<(u64, oneshot::Receiver<RpcResult>), RpcError> {
+        self.sidecar.send(method, params, self.settings.max_pending_requests)
+    }
+
+    /// Hand the outcome of the connection handshake to everyone waiting on it.
+    fn resolve_handshake(&mut self, result: impl Fn() -> RpcResult) {
+        self.handshake_session = None;
+        for (_, tx) in self.handshake_waiters.drain() {
+            let _ = tx.send(result());
+        }
+    }
+}
+
+impl sidecar::Host for AppState {
+    type Child = SidecarChild;
+
+    fn sidecar(&mut self) -> &mut Sidecar<SidecarChild> {
+        &mut self.sidecar
+    }
+
+    fn max_pending_requests(&self) -> usize {
+        self.settings.max_pending_requests
+    }
+
+    /// Nothing the dead process reported still holds.
+    fn exited(&mut self) {
+        self.discovery = None;
+        self.pairing_prompt = None;
+        self.connections.clear();
+        self.resolve_handshake(|| Err(RpcError::SidecarNotRunning));
+        if let Some(health_check) = self.health_check.take() {
+            health_check.abort();
+        }
+    }
 }
 
 type SharedState = Arc<Mutex<AppState>>;
 
-// Simple commands that just return mock data for now
-// The real logic is in the Python sidecar
+/// The running sidecar: the bundled process, or `mock` standing in for it.
+enum SidecarChild {
+    Process(CommandChild),
+    Mock(mock::MockChild),
+}
+
+impl ipc::Transport for SidecarChild {
+    /// Unbuffered either way: `CommandChild::write` goes straight into the
+    /// OS pipe with `write_all`, and the mock is handed each write whole.
+    fn write(&mut self, buf: &[u8]) -> Result<(), String> {
+        match self {
+            SidecarChild::Process(child) => child.write(buf).map_err(|e| e.to_string()),
+            SidecarChild::Mock(child) => child.write(buf),
+        }
+    }
+}
+
+impl sidecar::Child for SidecarChild {
+    fn pid(&self) -> Option<u32> {
+        match self {
+            SidecarChild::Process(child) => Some(child.pid()),
+            SidecarChild::Mock(_) => None,
+        }
+    }
+
+    fn kill(self) -> Result<(), String> {
+        match self {
+            SidecarChild::Process(child) => child.kill().map_err(|e| e.to_string()),
+            SidecarChild::Mock(child) => child.kill(),
+        }
+    }
+}
+
+/// Most events held back while waiting for the frontend; older ones are dropped.
+const MAX_BUFFERED_EVENTS: usize = 500;
+
+/// Events kept for `get_recent_events`.
+const MAX_EVENT_LOG: usize = 200;
+
+/// Payloads bigger than this (serialized) are logged as their size only.
+const MAX_LOGGED_PAYLOAD: usize = 4096;
+
+/// An emitted event as recorded for the debug console, secrets redacted.
+#[derive(Debug, Clone, Serialize)]
+struct LoggedEvent {
+    name: String,
+    payload: serde_json::Value,
+    /// Unix time (ms) it was emitted.
+    at: u64,
+}
+
+impl LoggedEvent {
+    fn new(name: &str, payload: &serde_json::Value) -> Self {
+        let size = serde_json::to_string(payload).map(|s| s.len()).unwrap_or_default();
+        let payload = if size > MAX_LOGGED_PAYLOAD {
+            serde_json::json!({ "truncated": true, "bytes": size })
+        } else {
+            let mut payload = payload.clone();
+            redact_secrets(&mut payload);
+            payload
+        };
+        Self { name: name.to_string(), payload, at: unix_millis() }
+    }
+}
+
+/// Emit an event to the frontend, or queue it if the frontend isn't listening yet.
+///
+/// Object payloads are tagged with the trace id in scope, if any.
+async fn emit_event(app: &AppHandle, event: &str, mut payload: serde_json::Value) {
+    if let (Some(trace_id), Some(fields)) = (ipc::trace_id(), payload.as_object_mut()) {
+        fields.entry("trace_id").or_insert(trace_id.into());
+    }
+    {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        if state.event_log.len() >= MAX_EVENT_LOG {
+            state.event_log.pop_front();
+        }
+        state.event_log.push_back(LoggedEvent::new(event, &payload));
+
+        if !state.frontend_ready {
+            if state.event_buffer.len() >= MAX_BUFFERED_EVENTS {
+                if let Some((dropped, _)) = state.event_buffer.pop_front() {
+                    warn!("Event buffer full, dropping {}", dropped);
+                }
+            }
+            state.event_buffer.push_back((event.to_string(), payload));
+            return;
+        }
+    }
+    let _ = app.emit(event, payload);
+}
 
+/// Sidecar stderr log; `None` if the log directory couldn't be opened.
+type SidecarLog = std::sync::Mutex<Option<RotatingLog>>;
+
+/// Load settings from `settings.json` in the config dir into `AppState`.
+fn load_settings(app: &AppHandle) {
+    let Some(dirs) = app.try_state::<DataDirs>() else {
+        warn!("Settings unavailable, using defaults");
+        return;
+    };
+    let path = dirs.config.join("settings.json");
+    let settings = Settings::load(&path);
+
+    let state = app.state::<SharedState>();
+    let mut state = tauri::async_runtime::block_on(state.lock());
+    state.settings = settings;
+    state.settings_path = Some(path);
+}
+
+/// Open `file_name` in the log dir.
+fn open_log(app: &AppHandle, file_name: &str) -> Result<RotatingLog, String> {
+    match app.try_state::<DataDirs>() {
+        Some(dirs) => RotatingLog::open(dirs.log.join(file_name)).map_err(|e| e.to_string()),
+        None => Err("app dirs unavailable".to_string()),
+    }
+}
+
+/// Start logging to `app.log` (and stdout in debug builds).
+fn init_logging(app: &AppHandle) -> LogLevelHandle {
+    let (app_log, app_log_error) = match open_log(app, "app.log") {
+        Ok(log) => (Some(log), None),
+        Err(e) => (None, Some(e)),
+    };
+    let handle = logs::init_tracing(app_log);
+    if let Some(e) = app_log_error {
+        warn!("App log file unavailable: {}", e);
+    }
+    handle
+}
+
+/// Apply a level name like `"debug"` to the running subscriber.
+fn apply_log_level(handle: &LogLevelHandle, level: &str) -> Result<(), RpcError> {
+    let filter: tracing_subscriber::filter::LevelFilter = level
+        .parse()
+        .map_err(|_| RpcError::InvalidInput(Text::new("input.unknown_log_level").with("level", level)))?;
+    handle
+        .reload(filter)
+        .map_err(|e| RpcError::InvalidInput(Text::detail("input.log_level_unchangeable", e)))
+}
+
+/// Stop the sidecar process, if one is running; see `sidecar::stop`.
+async fn stop_sidecar(app: &AppHandle) {
+    let state = app.state::<SharedState>().inner().clone();
+    if let Some(health_check) = state.lock().await.health_check.take() {
+        health_check.abort();
+    }
+    sidecar::stop(&state, emit_event(app, "sidecar:shutting_down", serde_json::Value::Null)).await;
+}
+
+/// Send a request to the sidecar and wait for its response.
+///
+/// The state lock is only held while writing, never across the await.
+async fn call_sidecar(state: &SharedState, method: &str, params: serde_json::Value) -> RpcResult {
+    call_sidecar_with_timeout(state, method, params, rpc_timeout(method)).await
+}
+
+/// Like `call_sidecar`, but with an explicit response timeout.
+async fn call_sidecar_with_timeout(
+    state: &SharedState,
+    method: &str,
+    params: serde_json::Value,
+    timeout: Duration,
+) -> RpcResult {
+    let (id, rx) = state.lock().await.send_rpc(method, params)?;
+    let result = sidecar::response(state, id, rx, timeout)
+        .instrument(info_span!("rpc", id, method))
+        .await;
+    {
+        let mut state = state.lock().await;
+        recovery::record(&mut state, method, &result);
+        idle::record(&mut state, method);
+    }
+    result
+}
+
+/// Send several requests at once and collect their responses in request order.
+///
+/// Everything is written under one lock, so the sidecar sees the requests
+/// back to back. Each slot holds that request's own outcome; a request that
+/// can't be sent, fails, or is still unanswered when the slowest method's
+/// timeout runs out doesn't affect the others.
+async fn batch_rpc(state: &SharedState, requests: Vec<(String, serde_json::Value)>) -> Vec<RpcResult> {
+    let timeout = requests
+        .iter()
+        .map(|(method, _)| rpc_timeout(method))
+        .max()
+        .unwrap_or(DEFAULT_RPC_TIMEOUT);
+    let sent: Vec<_> = {
+        let mut state = state.lock().await;
+        requests
+            .into_iter()
+            .map(|(method, params)| state.send_rpc(&method, params))
+            .collect()
+    };
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut results = Vec::with_capacity(sent.len());
+    for request in sent {
+        let result = match request {
+            Ok((id, rx)) => {
+                let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+                sidecar::response(state, id, rx, remaining).await
+            }
+            Err(e) => Err(e),
+        };
+        results.push(result);
+    }
+    results
+}
+
+/// Give up on request `id`: its caller fails with `Cancelled` straight
+/// away, and the sidecar is told with a `cancel` request. That stops the
+/// request there too if it is still waiting on a peer; anything else has
+/// been answered already, and the answer is dropped.
+///
+/// Ids come from `sidecar:request_started`. Cancelling a handshake wait
+/// also abandons the pairing attempt, leaving other connections be;
+/// transfers have `cancel_transfer`.
 #[tauri::command]
-async fn get_status() -> Result<serde_json::Value, String> {
-    // Return basic status - in a full implementation, 
-    // this would communicate with the Python sidecar
-    Ok(serde_json::json!({
+async fn cancel_request(app: AppHandle, id: u64) -> Result<(), RpcError> {
+    let state = app.state::<SharedState>().inner().clone();
+    let handshake = {
+        let mut state = state.lock().await;
+        if state.sidecar.client.cancel(id) {
+            None
+        } else if let Some(tx) = state.handshake_waiters.remove(&id) {
+            let _ = tx.send(Err(RpcError::Cancelled));
+            Some(state.handshake_session.clone())
+        } else {
+            return Err(RpcError::InvalidInput(Text::new("input.no_pending_request").with("id", id)));
+        }
+    };
+
+    info!(id, "Cancelled request");
+    if let Some(session_id) = handshake {
+        // Until the sidecar has named the session there is nothing to abandon there
+        match session_id {
+            Some(session_id) => disconnect(&app, Some(session_id), false).await,
+            None => cancel_handshake(&app).await,
+        }
+    } else if let Err(e) = call_sidecar(&state, "cancel", serde_json::json!({ "id": id })).await {
+        warn!("Sidecar did not acknowledge cancelling {}: {}", id, e);
+    }
+    Ok(())
+}
+
+// Commands forward to the Python sidecar, which holds the real logic
+
+/// Port the sidecar listens on unless told otherwise.
+const DEFAULT_PORT: u16 = 52525;
+
+/// Disconnected status built from local device info only, used until the
+/// sidecar is up (or when it stops answering).
+fn local_status(device: &DeviceInfo) -> serde_json::Value {
+    serde_json::json!({
         "state": "disconnected",
-        "device_name": hostname::get().map(|h| h.to_string_lossy().to_string()).unwrap_or("unknown".to_string()),
-        "device_type": "laptop",
-        "port": 52525,
+        "device_name": device.name,
+        "device_type": device.device_type,
+        "port": device.port,
+        "connections": [],
         "peer": null,
         "session_id": null,
         "local_info": {
-            "name": hostname::get().map(|h| h.to_string_lossy().to_string()).unwrap_or("unknown".to_string()),
-            "type": "laptop",
-            "ip": local_ip_address::local_ip().map(|ip| ip.to_string()).unwrap_or("127.0.0.1".to_string()),
-            "port": 52525
+            "name": device.name,
+            "type": device.device_type,
+            "ip": device.ip,
+            "addresses": device.addresses,
+            "port": device.port
+        }
+    })
+}
+
+/// Versions of everything involved, for the About dialog and bug reports.
+#[derive(Debug, Serialize)]
+struct VersionInfo {
+    app_version: &'static str,
+    tauri_version: &'static str,
+    /// `None` until the sidecar has completed its ready handshake.
+    sidecar_version: Option<String>,
+    protocol_version: Option<String>,
+    os: &'static str,
+    arch: &'static str,
+}
+
+#[tauri::command]
+async fn get_version(state: tauri::State<'_, SharedState>) -> Result<VersionInfo, RpcError> {
+    Ok(version_info(&*state.lock().await))
+}
+
+fn version_info(state: &AppState) -> VersionInfo {
+    let (sidecar_version, protocol_version) = if state.sidecar.is_ready() {
+        (state.sidecar_version.clone(), state.protocol_version.clone())
+    } else {
+        (None, None)
+    };
+    VersionInfo {
+        app_version: env!("CARGO_PKG_VERSION"),
+        tauri_version: tauri::VERSION,
+        sidecar_version,
+        protocol_version,
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+    }
+}
+
+#[tauri::command]
+async fn get_status(app: AppHandle, state: tauri::State<'_, SharedState>) -> Result<serde_json::Value, RpcError> {
+    let (sidecar_running, last_request_id, last_pong_at, device) = {
+        let mut state = state.lock().await;
+        (
+            state.sidecar.is_ready(),
+            state.sidecar.client.last_id(),
+            state.last_pong_at,
+            device::local_device(&mut state),
+        )
+    };
+
+    let mut status = if sidecar_running {
+        match recovery::call(&app, "get_status", serde_json::json!({})).await {
+            Ok(status) => status,
+            Err(e) => {
+                warn!("Sidecar status unavailable: {}", e);
+                local_status(&device)
+            }
         }
+    } else {
+        local_status(&device)
+    };
+
+    // Read after our own request has resolved, so it isn't counted as pending
+    let (pending_requests, protocol_version, sidecar_missing, clipboard_sync_enabled, bind_interface, idle_in) = {
+        let state = state.lock().await;
+        (
+            state.sidecar.client.pending_count(),
+            state.protocol_version.clone(),
+            state.sidecar_missing.clone(),
+            state.settings.clipboard_sync_enabled,
+            state.settings.bind_interface.clone(),
+            idle::remaining_secs(&state),
+        )
+    };
+
+    if let Some(status) = status.as_object_mut() {
+        status.insert("last_request_id".to_string(), last_request_id.into());
+        status.insert("last_ping_ok_at".to_string(), last_pong_at.into());
+        status.insert("pending_requests".to_string(), pending_requests.into());
+        status.insert("protocol_version".to_string(), protocol_version.into());
+        status.insert("supported_protocol".to_string(), SUPPORTED_PROTOCOL.into());
+        status.insert("sidecar_missing".to_string(), sidecar_missing.into());
+        status.insert("clipboard_sync_enabled".to_string(), clipboard_sync_enabled.into());
+        status.insert("bind_interface".to_string(), bind_interface.into());
+        status.insert("idle_disconnect_in_secs".to_string(), idle_in.into());
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+async fn get_peers(app: AppHandle) -> Result<Vec<Peer>, RpcError> {
+    usable_peers(recovery::call(&app, "get_peers", serde_json::json!({})).await?)
+}
+
+/// Ask the sidecar for discovered peers, dropping any with an unusable port.
+async fn fetch_peers(state: &SharedState) -> Result<Vec<Peer>, RpcError> {
+    // An empty list just means discovery hasn't found anyone yet
+    usable_peers(call_sidecar(state, "get_peers", serde_json::json!({})).await?)
+}
+
+fn usable_peers(peers: serde_json::Value) -> Result<Vec<Peer>, RpcError> {
+    let peers: Vec<Peer> = serde_json::from_value(peers)?;
+    Ok(peers
+        .into_iter()
+        .filter(|peer| {
+            if peer.port == 0 {
+                warn!("Ignoring peer {} with invalid port 0", peer.id);
+            }
+            peer.port != 0
+        })
+        .collect())
+}
+
+/// Forward any method to the sidecar and return its raw result, for
+/// prototyping sidecar methods before they get a command of their own.
+///
+/// Nothing is validated, so release builds refuse it unless
+/// `settings.allow_raw_rpc` is on.
+#[tauri::command]
+async fn raw_rpc(
+    state: tauri::State<'_, SharedState>,
+    method: String,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, RpcError> {
+    if !cfg!(debug_assertions) && !state.lock().await.settings.allow_raw_rpc {
+        return Err(RpcError::InvalidInput(Text::new("input.raw_rpc_disabled")));
+    }
+    if method.is_empty() {
+        return Err(RpcError::InvalidInput(Text::new("input.method_empty")));
+    }
+    // The sidecar reads `params` as an object
+    let params = if params.is_null() { serde_json::json!({}) } else { params };
+    call_sidecar(&state, &method, params).await
+}
+
+/// Status, peers and settings in one round-trip, for the main view.
+///
+/// `status` and `peers` are each `{ "result": ... }` or `{ "error": ... }`
+/// (an `RpcError`), so one failing doesn't hide the other.
+#[tauri::command]
+async fn get_dashboard(state: tauri::State<'_, SharedState>) -> Result<serde_json::Value, RpcError> {
+    let mut results = batch_rpc(&state, vec![
+        ("get_status".to_string(), serde_json::json!({})),
+        ("get_peers".to_string(), serde_json::json!({})),
+    ])
+    .await
+    .into_iter();
+    let status = results.next().unwrap_or(Err(RpcError::SidecarNotRunning));
+    let peers = results
+        .next()
+        .unwrap_or(Err(RpcError::SidecarNotRunning))
+        .and_then(usable_peers)
+        .and_then(|peers| Ok(serde_json::to_value(peers)?));
+
+    let item = |result: RpcResult| match result {
+        Ok(result) => serde_json::json!({ "result": result }),
+        Err(error) => serde_json::json!({ "error": error }),
+    };
+    Ok(serde_json::json!({
+        "status": item(status),
+        "peers": item(peers),
+        "settings": state.lock().await.settings
     }))
 }
 
+/// How long a connection may take end to end. Pairing waits on the user
+/// reading the passphrase off the other device and typing it in.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Connect to a peer and wait for the handshake to finish.
+///
+/// Resolves with the session id and peer info once the sidecar reports
+/// `connected`, or with the sidecar's reason if pairing fails.
+///
+/// For a peer from the discovered list prefer `connect_by_id`, which
+/// doesn't depend on an address that may have changed.
 #[tauri::command]
-async fn get_peers() -> Result<serde_json::Value, String> {
-    // Return empty peer list for now
-    Ok(serde_json::json!([]))
+async fn connect_to_peer(
+    app: AppHandle,
+    host: String,
+    port: u16,
+    trace_id: Option<String>,
+) -> Result<serde_json::Value, RpcError> {
+    ipc::traced(trace_id, connect(&app, host, port, None)).await
+}
+
+/// `token` is a pairing token from the peer's QR code, which lets it accept
+/// us without a passphrase.
+///
+/// An attempt that can't reach the peer is retried as `connect_retry`
+/// allows, announcing each retry as `sidecar:reconnecting`; disconnecting
+/// stops the retries.
+async fn connect(app: &AppHandle, host: String, port: u16, token: Option<String>) -> RpcResult {
+    if port == 0 {
+        return Err(RpcError::InvalidInput(Text::new("input.port_zero")));
+    }
+    let host = address::normalize_host(&host)?;
+
+    let cancel = Arc::new(Notify::new());
+    let retry = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        if connections::is_connected_to(&state, None, &host) {
+            return Err(RpcError::InvalidInput(Text::new("input.already_connected").with("host", host)));
+        }
+        state.connect_retry_cancel = Some(cancel.clone());
+        state.settings.connect_retry.clone()
+    };
+
+    let mut attempt = 0;
+    let result = loop {
+        match connect_once(app, &host, port, token.clone()).await {
+            Err(error) if error.is_retryable_connect() && attempt < retry.attempts => {
+                attempt += 1;
+                let delay = retry.delay(attempt);
+                warn!("Connecting to {}:{} failed ({}); retry {} in {:?}", host, port, error, attempt, delay);
+                let text = error.text();
+                emit_event(app, "sidecar:reconnecting", serde_json::json!({
+                    "attempt": attempt,
+                    "max_attempts": retry.attempts,
+                    "delay_ms": delay.as_millis() as u64,
+                    "error": error.to_string(),
+                    "message_code": text.code,
+                    "params": text.params
+                }))
+                .await;
+                set_connection_state(app, ConnectionState::Connecting).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = cancel.notified() => break Err(RpcError::Cancelled),
+                }
+            }
+            Err(RpcError::Transport(error)) if attempt > 0 => {
+                break Err(RpcError::Transport(format!("{} (gave up after {} retries)", error, attempt)));
+            }
+            Err(RpcError::Remote { code, message }) if attempt > 0 => {
+                break Err(RpcError::Remote {
+                    code,
+                    message: format!("{} (gave up after {} retries)", message, attempt),
+                });
+            }
+            result => break result,
+        }
+    };
+
+    {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        if state.connect_retry_cancel.as_ref().is_some_and(|c| Arc::ptr_eq(c, &cancel)) {
+            state.connect_retry_cancel = None;
+        }
+    }
+    result
+}
+
+/// A single connection attempt.
+async fn connect_once(app: &AppHandle, host: &str, port: u16, token: Option<String>) -> RpcResult {
+    info!("Connecting to {}:{}", host, port);
+    let state = app.state::<SharedState>().inner().clone();
+    // Register before sending: a failure can be reported before the response
+    let rx = wait_for_handshake(app, "connect").await;
+    let peer_id = {
+        let mut state = state.lock().await;
+        state.connect_target = Some((host.to_string(), port));
+        state
+            .settings
+            .last_peer
+            .as_ref()
+            .filter(|peer| peer.ip == host)
+            .and_then(|peer| peer.id.clone())
+    };
+
+    let mut params = serde_json::json!({"host": host, "port": port});
+    // A stored secret lets the sidecar skip the passphrase exchange
+    if let Some(secret) = peer_id.as_deref().and_then(keychain::load_pairing_secret) {
+        params["pairing_secret"] = secret.into();
+    }
+    if let Some(token) = token {
+        params["pairing_token"] = token.into();
+    }
+    set_connection_state(app, ConnectionState::Connecting).await;
+    match call_sidecar(&state, "connect", params).await {
+        Ok(result) => {
+            let session_id = result.get("session_id").and_then(|id| id.as_str());
+            state.lock().await.handshake_session = session_id.map(String::from);
+        }
+        Err(e) => {
+            set_connection_state(app, ConnectionState::Disconnected).await;
+            return Err(e);
+        }
+    }
+
+    await_handshake(rx, HANDSHAKE_TIMEOUT).await
+}
+
+/// Register for the outcome of the current connection handshake.
+///
+/// The wait gets a request id of its own, announced to the frontend as
+/// `sidecar:request_started` so it can be passed to `cancel_request`.
+async fn wait_for_handshake(app: &AppHandle, method: &str) -> oneshot::Receiver<RpcResult> {
+    let (tx, rx) = oneshot::channel();
+    let id = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        let id = state.sidecar.client.next_id();
+        state.handshake_waiters.retain(|_, tx| !tx.is_closed());
+        state.handshake_waiters.insert(id, tx);
+        id
+    };
+    emit_event(app, "sidecar:request_started", serde_json::json!({
+        "id": id,
+        "method": method
+    }))
+    .await;
+    rx
+}
+
+async fn await_handshake(rx: oneshot::Receiver<RpcResult>, timeout: Duration) -> RpcResult {
+    match tokio::time::timeout(timeout, rx).await {
+        Ok(result) => result
+            .map_err(|_| RpcError::Transport("handshake abandoned".to_string()))?,
+        Err(_) => Err(RpcError::Timeout),
+    }
+}
+
+/// Update the cached connection state, announcing it if it changed.
+///
+/// `Disconnected` stays `Connected` while other connections remain.
+async fn set_connection_state(app: &AppHandle, connection_state: ConnectionState) {
+    let (previous, connection_state, label) = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        let connection_state = match connection_state {
+            ConnectionState::Disconnected if !state.connections.is_empty() => ConnectionState::Connected,
+            connection_state => connection_state,
+        };
+        if connection_state == ConnectionState::Disconnected {
+            idle::stop(&mut state);
+        }
+        let previous = std::mem::replace(&mut state.connection_state, connection_state);
+        (previous, connection_state, connections::label(&state))
+    };
+    if previous != connection_state {
+        tray::update(app, connection_state, label.as_deref());
+        emit_event(app, "sidecar:connection_state", serde_json::json!({
+            "state": connection_state
+        })).await;
+    }
 }
 
 #[tauri::command]
-async fn connect_to_peer(host: String, port: u16) -> Result<serde_json::Value, String> {
-    println!("Connecting to {}:{}", host, port);
-    Ok(serde_json::json!({"status": "connecting"}))
+async fn get_connection_state(
+    state: tauri::State<'_, SharedState>,
+) -> Result<ConnectionState, RpcError> {
+    Ok(state.lock().await.connection_state)
 }
 
+/// Record the peer from a `connected` event as the last peer.
+///
+/// Runs as its own task: it queries the sidecar for the peer's discovery
+/// id, and the stdout reader that delivered the event can't wait on a
+/// response it would itself have to route.
+fn remember_peer(app: &AppHandle, event: Connected) {
+    let Connected { session_id, peer_name: name, peer_ip, peer_fingerprint: fingerprint, pairing_secret: secret } =
+        event;
+    let Some(ip) = peer_ip else {
+        return;
+    };
+    let app = app.clone();
+    let state = app.state::<SharedState>().inner().clone();
+
+    tauri::async_runtime::spawn(async move {
+        let port = match &state.lock().await.connect_target {
+            Some((host, port)) if *host == ip => *port,
+            _ => DEFAULT_PORT,
+        };
+        let id = match fetch_peers(&state).await {
+            Ok(peers) => peers.into_iter().find(|peer| peer.ip == ip).map(|peer| peer.id),
+            Err(e) => {
+                warn!("Could not resolve peer id for {}: {}", ip, e);
+                None
+            }
+        };
+        // Secrets are keyed by peer id; without one there's nothing to file it under
+        if let (Some(id), Some(secret)) = (&id, &secret) {
+            keychain::store_pairing_secret(id, secret);
+        }
+        if let (Some(id), Some(fingerprint)) = (&id, &fingerprint) {
+            trust::check(&app, id, fingerprint).await;
+        }
+
+        let mut state = state.lock().await;
+        // Discovery may only have caught up with the peer since `connected`
+        if let Some(connection) = session_id.and_then(|session_id| state.connections.get_mut(&session_id)) {
+            if connection.peer_id.is_none() {
+                connection.peer_id = id.clone();
+            }
+        }
+        state.settings.last_peer = Some(LastPeer {
+            id,
+            name,
+            ip,
+            port,
+            // `connected` is only sent once the passphrase exchange succeeded
+            paired: true,
+            connected_at: unix_millis(),
+            fingerprint,
+        });
+        onboarding::record_pairing(&app, &mut state);
+        state.save_settings();
+    });
+}
+
+/// Reconnect to the last peer if auto-reconnect is on and we aren't
+/// already connected to it.
+///
+/// Discovery is consulted first so a peer whose address changed (e.g. a
+/// new DHCP lease) is still found by id; the stored address is the
+/// fallback when discovery hasn't seen it.
+///
+/// At `launch` it first waits `auto_reconnect_delay_ms`, then up to
+/// `network_wait_timeout_secs` for a network, giving up quietly if none
+/// comes; the network monitor reconnects once one does.
+fn spawn_auto_reconnect(app: &AppHandle, launch: bool) {
+    let app = app.clone();
+    let state = app.state::<SharedState>().inner().clone();
+
+    tauri::async_runtime::spawn(async move {
+        let is_due = |state: &AppState| {
+            if !state.settings.auto_reconnect {
+                return None;
+            }
+            state
+                .settings
+                .last_peer
+                .clone()
+                .filter(|last_peer| !connections::is_connected_to(state, last_peer.id.as_deref(), &last_peer.ip))
+        };
+        let (delay, network_timeout) = {
+            let state = state.lock().await;
+            if is_due(&state).is_none() {
+                return;
+            }
+            (
+                Duration::from_millis(state.settings.auto_reconnect_delay_ms),
+                Duration::from_secs(state.settings.network_wait_timeout_secs),
+            )
+        };
+        if launch {
+            // On a cold boot the network is often still coming up
+            tokio::time::sleep(delay).await;
+            if !network::wait_until_online(&app, network_timeout).await {
+                info!("No network; leaving auto-reconnect to the network monitor");
+                return;
+            }
+        }
+        // The wait may have given the user time to connect themselves
+        let Some(last_peer) = is_due(&*state.lock().await) else {
+            return;
+        };
+
+        let discovered = match &last_peer.id {
+            Some(id) => fetch_peers(&state)
+                .await
+                .ok()
+                .and_then(|peers| peers.into_iter().find(|peer| peer.id == *id)),
+            None => None,
+        };
+        let (host, port) = match discovered {
+            Some(peer) => (peer.ip, peer.port),
+            None => (last_peer.ip, last_peer.port),
+        };
+
+        info!("Auto-reconnecting to {} at {}:{}", last_peer.name, host, port);
+        if let Err(e) = connect(&app, host, port, None).await {
+            warn!("Auto-reconnect failed: {}", e);
+        }
+    });
+}
+
+/// Passphrases are single dictionary words; anything longer is a typo.
+const MAX_PASSPHRASE_LEN: usize = 64;
+
+/// Submit the passphrase shown on the peer and wait for its verdict.
+///
+/// Resolves with the established session, like `connect_to_peer`.
 #[tauri::command]
-async fn submit_passphrase(passphrase: String) -> Result<serde_json::Value, String> {
-    println!("Submitting passphrase: {}", passphrase);
-    Ok(serde_json::json!({"status": "submitted"}))
+async fn submit_passphrase(
+    app: AppHandle,
+    passphrase: String,
+    trace_id: Option<String>,
+) -> Result<serde_json::Value, RpcError> {
+    ipc::traced(trace_id, async move {
+        let passphrase = passphrase.trim();
+        if passphrase.is_empty() {
+            return Err(RpcError::InvalidInput(Text::new("input.passphrase_empty")));
+        }
+        if passphrase.len() > MAX_PASSPHRASE_LEN {
+            return Err(RpcError::InvalidInput(
+                Text::new("input.passphrase_too_long").with("max_chars", MAX_PASSPHRASE_LEN),
+            ));
+        }
+
+        // Never log the passphrase itself
+        info!("Submitting passphrase ({} chars)", passphrase.chars().count());
+        let state = app.state::<SharedState>().inner().clone();
+        let rx = wait_for_handshake(&app, "submit_passphrase").await;
+        call_sidecar(&state, "submit_passphrase", serde_json::json!({"passphrase": passphrase}))
+            .await?;
+        await_handshake(rx, rpc_timeout("submit_passphrase")).await
+    })
+    .await
 }
 
+/// How long to wait for the sidecar to acknowledge a disconnect before
+/// tearing down local state anyway.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Disconnect from the peer in session `session_id`, or from every peer
+/// (abandoning any handshake) without one, and clear their session state.
+///
+/// Safe to call when already disconnected. Local state is cleared even if
+/// the sidecar doesn't acknowledge, so the UI can't get stuck.
+///
+/// `forget` also drops the stored pairing secret of the peers
+/// disconnected, and the last peer if it was one of them, so the next
+/// connection has to pair from scratch.
 #[tauri::command]
-async fn disconnect_peer() -> Result<serde_json::Value, String> {
-    Ok(serde_json::json!({"status": "disconnected"}))
+async fn disconnect_peer(
+    app: AppHandle,
+    session_id: Option<String>,
+    forget: Option<bool>,
+    trace_id: Option<String>,
+) -> Result<serde_json::Value, RpcError> {
+    ipc::traced(trace_id, async move {
+        disconnect(&app, session_id, forget.unwrap_or(false)).await;
+        Ok(serde_json::json!({"status": "disconnected"}))
+    })
+    .await
+}
+
+async fn disconnect(app: &AppHandle, session_id: Option<String>, forget: bool) {
+    let state = app.state::<SharedState>().inner().clone();
+    let ack = call_sidecar_with_timeout(
+        &state,
+        "disconnect",
+        serde_json::json!({ "session_id": session_id }),
+        DISCONNECT_TIMEOUT,
+    )
+    .await;
+    if let Err(e) = ack {
+        warn!("Sidecar did not acknowledge disconnect: {}", e);
+    }
+
+    let (disconnected, handshake, none_left) = {
+        let mut state = state.lock().await;
+        let disconnected: Vec<PeerConnection> = match &session_id {
+            Some(session_id) => state.connections.remove(session_id).into_iter().collect(),
+            None => state.connections.drain().map(|(_, connection)| connection).collect(),
+        };
+        let handshake = session_id.is_none() || state.handshake_session == session_id;
+        (disconnected, handshake, state.connections.is_empty())
+    };
+    if handshake {
+        cancel_handshake(app).await;
+    }
+    if none_left {
+        transfers::cancel_all(app, &state).await;
+    } else {
+        for connection in &disconnected {
+            transfers::cancel_session(app, &state, &connection.session_id).await;
+        }
+    }
+    set_connection_state(app, ConnectionState::Disconnected).await;
+
+    if forget {
+        let ids = {
+            let mut state = state.lock().await;
+            let mut ids: Vec<String> =
+                disconnected.into_iter().filter_map(|connection| connection.peer_id).collect();
+            let last_peer_id = state.settings.last_peer.as_ref().map(|peer| peer.id.clone());
+            // Without a session every peer goes, the last one included
+            let last_peer_gone = last_peer_id.as_ref().is_some_and(|id| {
+                session_id.is_none() || id.as_ref().is_some_and(|id| ids.contains(id))
+            });
+            if last_peer_gone {
+                state.settings.last_peer = None;
+                state.save_settings();
+                ids.extend(last_peer_id.flatten());
+            }
+            ids.sort();
+            ids.dedup();
+            ids
+        };
+        for id in ids {
+            // Already logged; the settings are cleared either way
+            let _ = keychain::clear_pairing_secret(&id);
+        }
+    }
+}
+
+/// Give up on the connection handshake in progress, here and in any retries.
+async fn cancel_handshake(app: &AppHandle) {
+    let state = app.state::<SharedState>();
+    let mut state = state.lock().await;
+    state.connect_target = None;
+    if let Some(cancel) = state.connect_retry_cancel.take() {
+        cancel.notify_one();
+    }
+    state.resolve_handshake(|| Err(RpcError::NotConnected));
 }
 
 #[tauri::command]
-async fn send_notification_to_peer(title: String, body: String) -> Result<serde_json::Value, String> {
-    println!("Sending notification: {} - {}", title, body);
-    Ok(serde_json::json!({"status": "sent"}))
+async fn restart_sidecar(app: AppHandle) -> Result<(), RpcError> {
+    restart(&app).await
 }
 
-fn start_sidecar(app: &AppHandle) -> Result<(), String> {
-    let sidecar_command = app
-        .shell()
-        .sidecar("deck-link-sidecar")
-        .map_err(|e| e.to_string())?
-        .args(["run", "--ipc"]);
+/// Stop the sidecar, abandoning everything in flight, and start a new one.
+async fn restart(app: &AppHandle) -> Result<(), RpcError> {
+    emit_event(app, "sidecar:restarting", serde_json::Value::Null).await;
 
-    let (mut rx, _child) = sidecar_command.spawn().map_err(|e| e.to_string())?;
+    stop_sidecar(app).await;
+    {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        state.sidecar.client.fail_all(|| RpcError::Cancelled);
+        state.resolve_handshake(|| Err(RpcError::Cancelled));
+        state.event_log.clear();
+        state.sidecar.restart_attempts = 0;
+        state.connections.clear();
+    }
+    set_connection_state(app, ConnectionState::Disconnected).await;
 
-    // Spawn a task to read stdout and emit events
-    let app_handle = app.clone();
+    // `sidecar:ready` follows once the new process finishes its handshake
+    start_sidecar(app).await.map_err(RpcError::Transport)
+}
+
+/// Try launching the sidecar again after `sidecar:missing`, e.g. once the
+/// install has been repaired.
+#[tauri::command]
+async fn retry_start_sidecar(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+) -> Result<(), RpcError> {
+    state.lock().await.sidecar.restart_attempts = 0;
+    start_sidecar(&app).await.map_err(RpcError::Transport)
+}
+
+/// Called by the frontend once its event listeners are registered.
+#[tauri::command]
+async fn frontend_ready(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+) -> Result<(), RpcError> {
+    // Flush under the lock so no new event can overtake the buffered ones
+    let mut state = state.lock().await;
+    state.frontend_ready = true;
+    for (event, payload) in state.event_buffer.drain(..) {
+        let _ = app.emit(&event, payload);
+    }
+    Ok(())
+}
+
+/// Change the log level (`error`, `warn`, `info`, `debug`, `trace`) and
+/// remember it for the next launch.
+#[tauri::command]
+async fn set_log_level(
+    handle: tauri::State<'_, LogLevelHandle>,
+    state: tauri::State<'_, SharedState>,
+    level: String,
+) -> Result<(), RpcError> {
+    apply_log_level(&handle, &level)?;
+    let mut state = state.lock().await;
+    state.settings.log_level = level;
+    state.save_settings();
+    Ok(())
+}
+
+/// Requests the sidecar hasn't answered yet, oldest first, for spotting
+/// one that never will be. `get_status` reports how many there are.
+#[tauri::command]
+async fn get_pending_requests(
+    state: tauri::State<'_, SharedState>,
+) -> Result<Vec<ipc::PendingRequest>, RpcError> {
+    Ok(state.lock().await.sidecar.client.pending())
+}
+
+/// Recently emitted events, oldest first, so a debug console opened late
+/// still has some history.
+#[tauri::command]
+async fn get_recent_events(state: tauri::State<'_, SharedState>) -> Result<Vec<LoggedEvent>, RpcError> {
+    Ok(state.lock().await.event_log.iter().cloned().collect())
+}
+
+#[tauri::command]
+async fn clear_event_log(state: tauri::State<'_, SharedState>) -> Result<(), RpcError> {
+    state.lock().await.event_log.clear();
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_log_path(log: tauri::State<'_, SidecarLog>) -> Result<Option<String>, RpcError> {
+    let log = log.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(log.as_ref().map(|log| log.path().to_string_lossy().to_string()))
+}
+
+/// Longest tail `read_log_tail` returns.
+const MAX_LOG_TAIL: usize = 10_000;
+
+/// The last `lines` lines of the sidecar log, at most `MAX_LOG_TAIL`, with
+/// secrets redacted; `sidecar:log` carries the lines that follow. Empty
+/// when there is no log file.
+#[tauri::command]
+async fn read_log_tail(log: tauri::State<'_, SidecarLog>, lines: usize) -> Result<Vec<String>, RpcError> {
+    let path = {
+        let log = log.lock().unwrap_or_else(|e| e.into_inner());
+        match log.as_ref() {
+            Some(log) => log.path().to_path_buf(),
+            None => return Ok(Vec::new()),
+        }
+    };
+    let lines = lines.min(MAX_LOG_TAIL);
+    let tail = tokio::task::spawn_blocking(move || logs::tail(&path, lines))
+        .await
+        .map_err(|e| RpcError::Transport(format!("cannot read log: {}", e)))?
+        .map_err(|e| RpcError::Transport(format!("cannot read log: {}", e)))?;
+    // Lines are redacted as they are written; this catches older ones
+    Ok(tail.iter().map(|line| redact_line(line)).collect())
+}
+
+#[tauri::command]
+async fn get_sidecar_pid(state: tauri::State<'_, SharedState>) -> Result<Option<u32>, RpcError> {
+    // Restarts swap the child under the same lock, so this never sees a stale pid
+    Ok(state.lock().await.sidecar.client.transport().and_then(|child| child.pid()))
+}
+
+/// Refuse clipboard text that couldn't be passed on whole.
+fn validate_clipboard_text(text: &str) -> Result<(), RpcError> {
+    if text.len() > MAX_CLIPBOARD_BYTES {
+        return Err(RpcError::InvalidInput(
+            Text::new("input.clipboard_text_too_large").with("max_bytes", MAX_CLIPBOARD_BYTES),
+        ));
+    }
+    if text.contains('\0') {
+        return Err(RpcError::InvalidInput(Text::new("input.clipboard_binary")));
+    }
+    // Escaping can make it several times bigger, up to six bytes for a
+    // control character, and the escaped text has to fit in one line of
+    // sidecar output
+    let escaped = serde_json::to_string(text)?.len();
+    let max_escaped = ipc::MAX_LINE_LEN - CLIPBOARD_LINE_OVERHEAD;
+    if escaped > max_escaped {
+        return Err(RpcError::InvalidInput(
+            Text::new("input.clipboard_text_escapes_too_large")
+                .with("escaped_bytes", escaped)
+                .with("max_bytes", max_escaped),
+        ));
+    }
+    Ok(())
+}
+
+/// Share `text` with the peer, or without it whatever is on the local
+/// clipboard, preferring an image over text.
+#[tauri::command]
+async fn send_clipboard(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    text: Option<String>,
+    trace_id: Option<String>,
+) -> Result<(), RpcError> {
+    ipc::traced(trace_id, async move {
+        if !state.lock().await.settings.clipboard_sync_enabled {
+            debug!("Clipboard sync is paused; not sending");
+            return Ok(());
+        }
+        let text = match text {
+            Some(text) => text,
+            None => {
+                if let Some(pixels) = clipboard_image::read_local(&app) {
+                    return clipboard_image::send(&state, pixels).await;
+                }
+                app.clipboard()
+                    .read_text()
+                    .map_err(|e| RpcError::InvalidInput(Text::detail("input.clipboard_unreadable", e)))?
+            }
+        };
+        validate_clipboard_text(&text)?;
+
+        // Re-sending text we just received keeps its origin, so it stops there
+        let origin = {
+            let state = state.lock().await;
+            match &state.last_remote_clipboard {
+                Some(update) if update.text == text => update.origin.clone(),
+                _ => state.origin_id.clone(),
+            }
+        };
+
+        let update = ClipboardUpdate { text, origin };
+        call_sidecar(&state, "set_clipboard", serde_json::to_value(update)?).await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Send whatever is on the local clipboard to the peer once, image or
+/// text, as a deliberate action: unlike `send_clipboard` it goes out while
+/// clipboard sync is paused, and as copied here even if it came from the
+/// peer. Announced as `sidecar:clipboard_pushed`.
+#[tauri::command]
+async fn push_clipboard(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    trace_id: Option<String>,
+) -> Result<(), RpcError> {
+    ipc::traced(trace_id, async move {
+        let origin = {
+            let state = state.lock().await;
+            if state.connections.is_empty() {
+                return Err(RpcError::NotConnected);
+            }
+            state.origin_id.clone()
+        };
+
+        let kind = if let Some(pixels) = clipboard_image::read_local(&app) {
+            clipboard_image::send_as(&state, pixels, origin).await?;
+            "image"
+        } else {
+            let text = app.clipboard().read_text().map_err(|e| {
+                RpcError::InvalidInput(Text::detail("input.clipboard_unsendable", e))
+            })?;
+            if text.is_empty() {
+                return Err(RpcError::InvalidInput(Text::new("input.clipboard_empty")));
+            }
+            validate_clipboard_text(&text)?;
+            let update = ClipboardUpdate { text, origin };
+            call_sidecar(&state, "set_clipboard", serde_json::to_value(update)?).await?;
+            "text"
+        };
+        info!("Pushed clipboard {} to peer", kind);
+        emit_event(&app, "sidecar:clipboard_pushed", serde_json::json!({ "kind": kind })).await;
+        Ok(())
+    })
+    .await
+}
+
+/// Write a peer's clipboard update to the local clipboard.
+///
+/// Returns false for updates that should go no further: invalid ones and
+/// our own updates echoed back to us.
+async fn apply_remote_clipboard(app: &AppHandle, update: ClipboardUpdate) -> bool {
+    if let Err(e) = validate_clipboard_text(&update.text) {
+        warn!("Ignoring clipboard update: {}", e);
+        return false;
+    }
+
+    {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        if !state.settings.clipboard_sync_enabled || update.origin == state.origin_id {
+            return false;
+        }
+        state.last_remote_clipboard = Some(update.clone());
+    }
+
+    if let Err(e) = app.clipboard().write_text(update.text) {
+        error!("Failed to write clipboard: {}", e);
+    }
+    true
+}
+
+/// Pause or resume clipboard sharing in both directions.
+#[tauri::command]
+async fn set_clipboard_sync_enabled(app: AppHandle, enabled: bool) -> Result<(), RpcError> {
+    set_clipboard_sync(&app, Some(enabled)).await;
+    Ok(())
+}
+
+/// Persist the clipboard sync preference; `None` toggles it.
+async fn set_clipboard_sync(app: &AppHandle, enabled: Option<bool>) {
+    let enabled = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        let current = state.settings.clipboard_sync_enabled;
+        let enabled = enabled.unwrap_or(!current);
+        if enabled == current {
+            return;
+        }
+        state.settings.clipboard_sync_enabled = enabled;
+        state.save_settings();
+        enabled
+    };
+    clipboard_sync_changed(app, enabled).await;
+}
+
+/// Tell the tray and the frontend that clipboard sync is now `enabled`,
+/// however the setting was changed.
+async fn clipboard_sync_changed(app: &AppHandle, enabled: bool) {
+    info!("Clipboard sync {}", if enabled { "resumed" } else { "paused" });
+    tray::show_clipboard_sync(app, enabled);
+    emit_event(app, "sidecar:clipboard_sync_changed", serde_json::json!({ "enabled": enabled })).await;
+}
+
+/// Forward a notification to the peer in session `session_id`, which may
+/// be left out while only one is connected. With `local`, it is also shown
+/// on this device once the peer has it.
+#[tauri::command]
+async fn send_notification_to_peer(
+    app: AppHandle,
+    title: String,
+    body: String,
+    priority: Option<String>,
+    local: Option<bool>,
+    session_id: Option<String>,
+    trace_id: Option<String>,
+) -> Result<serde_json::Value, RpcError> {
+    ipc::traced(trace_id, async move {
+        notifications::validate(&title, &body)?;
+        let state = app.state::<SharedState>().inner().clone();
+        {
+            let state = state.lock().await;
+            let connected = match &session_id {
+                Some(session_id) => state.connections.contains_key(session_id),
+                None => !state.connections.is_empty(),
+            };
+            if !connected {
+                return Err(RpcError::NotConnected);
+            }
+        }
+
+        // Contents may be private; only their size is logged
+        debug!("Sending notification ({} + {} bytes)", title.len(), body.len());
+        let params = notifications::request(&title, &body, priority.as_deref(), session_id.as_deref());
+        let result = call_sidecar(&state, "send_notification", params).await?;
+
+        if local.unwrap_or(false) {
+            notifications::show_local(&app, &title, &body).await;
+        }
+        Ok(result)
+    })
+    .await
+}
+
+/// Give up restarting after this many consecutive unexpected terminations.
+const MAX_RESTART_ATTEMPTS: u32 = 5;
+
+/// Upper bound on the delay between restart attempts.
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Delay before restart attempt `attempt` (1-based): 1s, 2s, 4s, ... capped.
+fn restart_backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1 << attempt.saturating_sub(1).min(5)).min(MAX_RESTART_BACKOFF)
+}
+
+/// Restart the sidecar after it terminated unexpectedly.
+///
+/// Backs off exponentially and emits `sidecar:fatal` once it runs out of
+/// attempts, so the UI can show a permanent error.
+fn schedule_restart(app: &AppHandle) {
+    let app = app.clone();
     tauri::async_runtime::spawn(async move {
-        use tauri_plugin_shell::process::CommandEvent;
-
-        while let Some(event) = rx.recv().await {
-            match event {
-                CommandEvent::Stdout(line) => {
-                    let line_str = String::from_utf8_lossy(&line);
-                    if let Ok(response) = serde_json::from_str::<RpcResponse>(&line_str) {
-                        if let Some(event_name) = response.event {
-                            // Emit event to frontend
-                            let _ = app_handle.emit(&format!("sidecar:{}", event_name), response.data);
-                        } else if let Some(result) = response.result {
-                            // Emit result
-                            let _ = app_handle.emit("sidecar:result", serde_json::json!({
-                                "id": response.id,
-                                "result": result
-                            }));
-                        } else if let Some(error) = response.error {
-                            let _ = app_handle.emit("sidecar:error", serde_json::json!({
-                                "id": response.id,
-                                "error": error
-                            }));
+        let state = app.state::<SharedState>().inner().clone();
+        let attempt = {
+            let mut state = state.lock().await;
+            state.sidecar.restart_attempts += 1;
+            state.sidecar.restart_attempts
+        };
+
+        if attempt > MAX_RESTART_ATTEMPTS {
+            error!("Sidecar failed {} times in a row, giving up", MAX_RESTART_ATTEMPTS);
+            emit_event(&app, "sidecar:fatal", serde_json::json!({
+                "attempts": MAX_RESTART_ATTEMPTS
+            })).await;
+            return;
+        }
+
+        let delay = restart_backoff(attempt);
+        warn!("Restarting sidecar in {:?} (attempt {})", delay, attempt);
+        tokio::time::sleep(delay).await;
+
+        if let Err(e) = start_sidecar(&app).await {
+            error!("Failed to restart sidecar: {}", e);
+            schedule_restart(&app);
+        }
+    });
+}
+
+/// How often the health check pings the sidecar.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long a ping may take before it counts as missed.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Missed pings in a row after which the sidecar is considered wedged.
+const MAX_MISSED_PINGS: u32 = 2;
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Ping the sidecar periodically to catch a process that is alive but stuck.
+///
+/// A wedged sidecar is killed, which sends it through the normal
+/// unexpected-termination restart path.
+fn spawn_health_check(app: &AppHandle) -> tauri::async_runtime::JoinHandle<()> {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<SharedState>().inner().clone();
+        let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        // The first tick fires immediately; give the sidecar time to boot
+        interval.tick().await;
+
+        let mut missed = 0;
+        loop {
+            interval.tick().await;
+            let ping = serde_json::json!({});
+            match call_sidecar_with_timeout(&state, "ping", ping, HEALTH_CHECK_TIMEOUT).await {
+                Ok(pong) => {
+                    missed = 0;
+                    state.lock().await.last_pong_at = Some(unix_millis());
+                    latency::record(&app, Some(&pong)).await;
+                }
+                Err(e) => {
+                    missed += 1;
+                    warn!("Sidecar ping failed ({} in a row): {}", missed, e);
+                    latency::record(&app, None).await;
+                    if missed >= MAX_MISSED_PINGS {
+                        emit_event(&app, "sidecar:unhealthy", serde_json::json!({
+                            "missed_pings": missed
+                        })).await;
+                        if let Some(child) = state.lock().await.sidecar.client.detach() {
+                            let _ = child.kill();
                         }
+                        return;
                     }
                 }
-                CommandEvent::Stderr(line) => {
-                    let line_str = String::from_utf8_lossy(&line);
-                    eprintln!("Sidecar stderr: {}", line_str);
+            }
+        }
+    })
+}
+
+/// Sidecar protocol this build speaks. Sidecars with a different major
+/// version are refused.
+const SUPPORTED_PROTOCOL: &str = "1.0";
+
+/// The major part of a `major.minor` protocol version.
+fn protocol_major(version: &str) -> &str {
+    version.split('.').next().unwrap_or(version)
+}
+
+/// How long a freshly spawned sidecar gets to answer its first ping.
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Wait for a new sidecar to answer a ping, then mark it ready.
+///
+/// A sidecar that never answers is killed and goes through the restart path.
+fn spawn_ready_handshake(app: &AppHandle, generation: u64) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<SharedState>().inner().clone();
+        // Not `send_rpc`: the sidecar isn't ready until this is answered
+        let ping = state.lock().await.sidecar.client.write("ping", serde_json::json!({}));
+        let pong = match ping {
+            // Requests queue in the pipe until the sidecar starts reading
+            Ok((id, rx)) => sidecar::response(&state, id, rx, READY_TIMEOUT).await,
+            Err(e) => Err(e),
+        };
+
+        match pong {
+            Ok(pong) => mark_ready(&app, generation, &pong).await,
+            Err(e) => {
+                let mut state = state.lock().await;
+                if state.sidecar.generation == generation && !state.sidecar.is_ready() {
+                    error!("Sidecar never became ready: {}", e);
+                    if let Some(child) = state.sidecar.client.detach() {
+                        let _ = child.kill();
+                    }
                 }
-                CommandEvent::Error(error) => {
-                    eprintln!("Sidecar error: {}", error);
-                    let _ = app_handle.emit("sidecar:error", serde_json::json!({
-                        "error": error
-                    }));
+            }
+        }
+    });
+}
+
+/// Start accepting commands for sidecar `generation` and tell the frontend.
+///
+/// Reached either by the handshake ping or by a `ready` event from the
+/// sidecar, whichever comes first. A sidecar speaking an incompatible
+/// protocol is stopped instead, with `sidecar:incompatible`.
+async fn mark_ready(app: &AppHandle, generation: u64, handshake: &serde_json::Value) {
+    let state = app.state::<SharedState>().inner().clone();
+    let version = |key: &str| handshake.get(key).and_then(|v| v.as_str()).map(String::from);
+    let protocol_version = version("protocol_version");
+    let compatible = protocol_version
+        .as_deref()
+        .is_some_and(|v| protocol_major(v) == protocol_major(SUPPORTED_PROTOCOL));
+    {
+        let mut state = state.lock().await;
+        if state.sidecar.generation != generation || state.sidecar.is_ready() {
+            return;
+        }
+        state.protocol_version = protocol_version.clone();
+        state.sidecar_version = version("version");
+        if !compatible {
+            // Its exit mustn't go through the restart path
+            state.sidecar.stopping = true;
+        } else {
+            state.sidecar.mark_ready();
+            state.rpc_timeouts = 0;
+            state.health_check = Some(spawn_health_check(app));
+        }
+    }
+
+    if !compatible {
+        error!(
+            "Sidecar speaks protocol {:?}, this build supports {}",
+            protocol_version, SUPPORTED_PROTOCOL
+        );
+        emit_event(app, "sidecar:incompatible", serde_json::json!({
+            "protocol_version": protocol_version,
+            "supported": SUPPORTED_PROTOCOL,
+            "message": Text::new("error.incompatible_sidecar").english(),
+            "message_code": "error.incompatible_sidecar",
+            "params": {}
+        }))
+        .await;
+        // From its own task: the stdout reader may be the one calling us,
+        // and it has to see the exit
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move { stop_sidecar(&app).await });
+        return;
+    }
+
+    emit_event(app, "sidecar:ready", serde_json::json!({
+        "protocol_version": protocol_version
+    })).await;
+
+    set_connection_state(app, ConnectionState::Discovering).await;
+    peers::spawn_refresh(app);
+    // An explicit pairing request takes precedence over the last peer
+    if !deep_link::spawn_pending(app).await {
+        // Only the first sidecar of the run is started at launch
+        spawn_auto_reconnect(app, generation == 1);
+    }
+}
+
+/// Act on an event pushed by the sidecar, then forward it to the frontend
+/// as `sidecar:<event>`.
+async fn handle_event(app: &AppHandle, generation: u64, name: &str, data: serde_json::Value) {
+    let parsed = SidecarEvent::parse(name, &data);
+    // Whatever happens to it next, the data is on its way to the frontend
+    let data = events::for_frontend(data);
+    let event = match parsed {
+        Ok(Some(event)) => event,
+        Ok(None) => {
+            // A newer sidecar than this build; passed on so it isn't lost
+            warn!("Unknown sidecar event {:?}", name);
+            emit_event(app, "sidecar:unknown", serde_json::json!({ "event": name, "data": data })).await;
+            return;
+        }
+        Err(e) => {
+            warn!("Ignoring malformed sidecar event {:?}: {}", name, e);
+            return;
+        }
+    };
+    let request = matches!(event, SidecarEvent::TransferRequest(_) | SidecarEvent::Notification(_));
+    if request && !policy::admit(app, name, &data).await {
+        return;
+    }
+    let forward = match event {
+        SidecarEvent::Ready(ref info) => {
+            // mark_ready emits its own `sidecar:ready`
+            mark_ready(app, generation, info).await;
+            false
+        }
+        SidecarEvent::ClipboardUpdated(update) => apply_remote_clipboard(app, update).await,
+        SidecarEvent::ClipboardImage(image) => clipboard_image::apply_remote(app, image).await,
+        SidecarEvent::TransferRequest(request) => transfers::handle_request(app, request).await,
+        SidecarEvent::TransferChunk(chunk) => transfers::handle_chunk(app, chunk).await,
+        SidecarEvent::TransferFinish(transfer) => transfers::handle_end(app, transfer, true).await,
+        SidecarEvent::TransferCancel(transfer) => transfers::handle_end(app, transfer, false).await,
+        SidecarEvent::PeerDiscovered(peer) => peers::handle_discovered(app, peer).await,
+        SidecarEvent::PeerLost(lost) => peers::handle_lost(app, lost).await,
+        SidecarEvent::DiscoveryStatus(status) => discovery::handle_event(app, status).await,
+        SidecarEvent::Stats(stats) => bandwidth::handle_event(app, stats).await,
+        SidecarEvent::PairingRequest(request) => pairing::handle_request(app, request).await,
+        SidecarEvent::PairingRequestExpired(request) => pairing::handle_expired(app, request).await,
+        SidecarEvent::PassphraseRequired(handshake) | SidecarEvent::ChallengeGenerated(handshake) => {
+            app.state::<SharedState>().lock().await.handshake_session = handshake.session_id;
+            set_connection_state(app, ConnectionState::Pairing).await;
+            true
+        }
+        SidecarEvent::Connected(connected) => {
+            let fingerprint = connected.peer_fingerprint.as_deref();
+            let session = serde_json::json!({
+                "session_id": connected.session_id,
+                "peer": {
+                    "name": connected.peer_name,
+                    "ip": connected.peer_ip,
+                    "fingerprint": fingerprint,
+                    "fingerprint_display": fingerprint.map(trust::display)
+                },
+            });
+            {
+                let mut state = app.state::<SharedState>().inner().lock().await;
+                match PeerConnection::from_event(&state, &connected) {
+                    Some(connection) => {
+                        state.connections.insert(connection.session_id.clone(), connection);
+                    }
+                    None => warn!("Connected event without a session id"),
                 }
-                CommandEvent::Terminated(payload) => {
-                    eprintln!("Sidecar terminated: {:?}", payload);
-                    let _ = app_handle.emit("sidecar:terminated", serde_json::json!({
-                        "code": payload.code
+                state.resolve_handshake(|| Ok(session.clone()));
+            }
+            remember_peer(app, connected);
+            idle::start(app).await;
+            set_connection_state(app, ConnectionState::Connected).await;
+            true
+        }
+        SidecarEvent::AuthFailed(ref failure) | SidecarEvent::ConnectionError(ref failure) => {
+            let reason = failure
+                .reason
+                .clone()
+                .or_else(|| failure.error.clone())
+                .unwrap_or_else(|| "connection failed".to_string());
+            // Unknown codes become `Unknown` rather than failing to parse
+            let class = failure
+                .code
+                .as_ref()
+                .map(|code| serde_json::from_value::<ConnectError>(code.clone()).unwrap_or(ConnectError::Unknown));
+            let unreachable = matches!(event, SidecarEvent::ConnectionError(_));
+            app.state::<SharedState>().lock().await.resolve_handshake(|| {
+                if failure.rate_limited {
+                    Err(RpcError::RateLimited(reason.clone()))
+                } else if let Some(class) = class {
+                    Err(class.into_rpc(reason.clone()))
+                } else if unreachable {
+                    // An older sidecar: couldn't reach the peer, as opposed
+                    // to it turning us down
+                    Err(RpcError::Transport(reason.clone()))
+                } else {
+                    Err(RpcError::Remote { code: 0, message: reason.clone() })
+                }
+            });
+            set_connection_state(app, ConnectionState::Disconnected).await;
+            true
+        }
+        SidecarEvent::Disconnected(handshake) => {
+            let state = app.state::<SharedState>().inner().clone();
+            let ended = {
+                let mut state = state.lock().await;
+                let ended = handshake.session_id.and_then(|id| state.connections.remove(&id));
+                if ended.is_none() {
+                    // The handshake fell through
+                    state.resolve_handshake(|| Err(RpcError::Remote {
+                        code: 0,
+                        message: "peer disconnected".to_string(),
                     }));
                 }
-                _ => {}
+                ended
+            };
+            if let Some(connection) = ended {
+                transfers::cancel_session(app, &state, &connection.session_id).await;
             }
+            set_connection_state(app, ConnectionState::Disconnected).await;
+            true
         }
-    });
+        SidecarEvent::Notification(notification) => {
+            notifications::show_peer_notification(app, notification, &data).await;
+            true
+        }
+        SidecarEvent::StateChanged | SidecarEvent::ServerStarted | SidecarEvent::ServerStopped => true,
+    };
+
+    if forward {
+        emit_event(app, &format!("sidecar:{}", name), data).await;
+    }
+}
+
+/// Pass on what sidecar `generation` wrote that nobody was waiting for,
+/// and act on its exit.
+async fn handle_output(app: &AppHandle, generation: u64, output: sidecar::Output) {
+    let message = match output {
+        sidecar::Output::Message(message) => message,
+        sidecar::Output::Stderr(line) => {
+            let line_str = redact_line(&String::from_utf8_lossy(&line));
+            debug!("Sidecar stderr: {}", line_str);
+
+            let timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+            if let Some(log) = app.state::<SidecarLog>().lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+                if let Err(e) = log.write_line(&timestamp, &line_str) {
+                    error!("Failed to write sidecar log: {}", e);
+                }
+            }
+            emit_event(app, "sidecar:log", serde_json::json!({
+                "timestamp": timestamp,
+                "line": line_str.trim_end()
+            })).await;
+            return;
+        }
+        sidecar::Output::Error(error) => {
+            warn!("Sidecar error: {}", error);
+            emit_event(app, "sidecar:error", serde_json::json!({
+                "error": error
+            })).await;
+            return;
+        }
+        sidecar::Output::Terminated { code, restart } => {
+            emit_event(app, "sidecar:terminated", serde_json::json!({
+                "code": code
+            })).await;
+            set_connection_state(app, ConnectionState::Disconnected).await;
+            peers::clear(app).await;
+            if restart {
+                schedule_restart(app);
+            }
+            return;
+        }
+    };
+
+    // Whatever the sidecar did on behalf of a traced request stays traced
+    match message {
+        Message::Event { name, data, trace_id } => {
+            ipc::traced(trace_id, handle_event(app, generation, &name, data)).await;
+        }
+        Message::Reply { id, result: Ok(result), trace_id } => {
+            ipc::traced(trace_id, emit_event(app, "sidecar:result", serde_json::json!({
+                "id": id,
+                "result": result
+            }))).await;
+        }
+        Message::Reply { id, result: Err(error), trace_id } => {
+            ipc::traced(trace_id, emit_event(app, "sidecar:error", serde_json::json!({
+                "id": id,
+                "error": error
+            }))).await;
+        }
+    }
+}
+
+/// Name the sidecar binary is bundled under (`externalBin`).
+const SIDECAR_NAME: &str = "deck-link-sidecar";
+
+/// Where the shell plugin looks for the sidecar: next to our executable.
+fn sidecar_path() -> Option<PathBuf> {
+    let dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+    Some(dir.join(format!("{}{}", SIDECAR_NAME, std::env::consts::EXE_SUFFIX)))
+}
+
+/// Spawn the sidecar, unless one is already running.
+///
+/// If it can't be launched at all, `sidecar:missing` carries the path we
+/// expected it at and the OS error; `retry_start_sidecar` tries again.
+async fn start_sidecar(app: &AppHandle) -> Result<(), String> {
+    let state = app.state::<SharedState>().inner().clone();
+
+    // Spawn under the lock so concurrent starts can't both launch a process
+    let spawned = {
+        let mut state = state.lock().await;
+        if state.sidecar.client.is_attached() {
+            return Ok(());
+        }
+
+        let options = &state.settings.sidecar;
+        let mut args = options.args();
+        args.extend(["--mode".to_string(), state.settings.device_type.as_str().to_string()]);
+        if let Some(name) = &state.settings.device_name {
+            args.extend(["--name".to_string(), name.clone()]);
+        }
+        args.extend(interfaces::sidecar_args(&state));
+        // Keep the identity key with our other config; settings can override
+        let mut env = std::collections::BTreeMap::new();
+        if let Some(dirs) = app.try_state::<DataDirs>() {
+            let key = dirs.config.join("identity.pem").to_string_lossy().to_string();
+            env.insert("DECK_LINK_IDENTITY".to_string(), key);
+        }
+        env.extend(options.env.clone());
+        let launched = if mock::enabled() {
+            warn!("Using the mock sidecar");
+            let (rx, child) = mock::spawn();
+            Ok((rx, SidecarChild::Mock(child)))
+        } else {
+            app.shell()
+                .sidecar(SIDECAR_NAME)
+                .and_then(|command| command.args(args).envs(env).spawn())
+                .map(|(rx, child)| (rx, SidecarChild::Process(child)))
+        };
+        match launched {
+            Ok((rx, child)) => {
+                // Keep the child so commands can write requests to its stdin
+                state.sidecar_missing = None;
+                Ok((rx, state.sidecar.attach(child)))
+            }
+            Err(e) => {
+                // Not bundled, not executable or built for another arch
+                let path = sidecar_path();
+                error!("Cannot launch sidecar at {:?}: {}", path, e);
+                let message = Text::detail("error.sidecar_missing", &e);
+                let missing = serde_json::json!({
+                    "path": path.map(|path| path.to_string_lossy().to_string()),
+                    "error": e.to_string(),
+                    "message_code": message.code,
+                    "params": message.params
+                });
+                state.sidecar_missing = Some(missing.clone());
+                Err((missing, e.to_string()))
+            }
+        }
+    };
+    let (rx, generation) = match spawned {
+        Ok(spawned) => spawned,
+        Err((missing, error)) => {
+            emit_event(app, "sidecar:missing", missing).await;
+            return Err(error);
+        }
+    };
+    info!(generation, "Sidecar started");
+    spawn_ready_handshake(app, generation);
+
+    // Spawn a task to read stdout, route responses and emit events
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(
+        sidecar::read_events(state, generation, rx, move |output| {
+            let app = app_handle.clone();
+            async move { handle_output(&app, generation, output).await }
+        })
+        .instrument(info_span!("sidecar", generation)),
+    );
 
     Ok(())
 }
 
+/// Bring the main window to the foreground.
+fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Find a pairing target in launch arguments: `--pair <host>[:<port>]` or
+/// `--pair=<host>[:<port>]`. `deck-link://` links are handled by `deep_link`.
+fn parse_pair_arg(args: &[String]) -> Option<PairTarget> {
+    let target = args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == "--pair" {
+            args.get(i + 1).map(String::as_str)
+        } else {
+            arg.strip_prefix("--pair=")
+        }
+    })?;
+
+    // A bare IPv6 address has colons but no port
+    let (host, port) = if target.parse::<std::net::IpAddr>().is_ok() {
+        (target.to_string(), DEFAULT_PORT)
+    } else {
+        match target.rsplit_once(':') {
+            Some((host, port)) => {
+                (host.trim_matches(|c| c == '[' || c == ']').to_string(), port.parse().ok()?)
+            }
+            None => (target.to_string(), DEFAULT_PORT),
+        }
+    };
+    Some(PairTarget { host: Some(host), port, peer_id: None, token: None })
+}
+
+/// A second launch hands its arguments to us and exits; show ourselves
+/// and pick up any pairing target it was given.
+fn handle_second_instance(app: &AppHandle, args: Vec<String>) {
+    show_main_window(app);
+
+    if let Some(target) = parse_pair_arg(&args) {
+        deep_link::pair_with(app, target);
+    }
+}
+
 fn main() {
+    if mock::stdio() {
+        return mock::serve_stdio();
+    }
     tauri::Builder::default()
+        // Must be registered first. The lock is OS-owned (a named mutex,
+        // D-Bus name or socket), so a crashed instance never leaves it held.
+        .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+            handle_second_instance(app, args)
+        }))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_process::init())
-        .manage(Arc::new(Mutex::new(AppState { sidecar_running: false })))
+        .plugin(tauri_plugin_updater::Builder::new().build())
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .manage(Arc::new(Mutex::new(AppState {
+            sidecar: Sidecar::new(),
+            pending_quit: None,
+            rpc_timeouts: 0,
+            last_recovery: None,
+            last_activity: None,
+            idle_timer: None,
+            health_check: None,
+            last_pong_at: None,
+            latency: latency::Latency::default(),
+            protocol_version: None,
+            sidecar_version: None,
+            sidecar_missing: None,
+            frontend_ready: false,
+            event_buffer: VecDeque::new(),
+            event_log: VecDeque::new(),
+            origin_id: uuid::Uuid::new_v4().to_string(),
+            last_remote_clipboard: None,
+            last_remote_image: None,
+            transfers: HashMap::new(),
+            batches: HashMap::new(),
+            incoming_transfers: HashMap::new(),
+            transfer_list: TransferList::default(),
+            local_ip: None,
+            local_device: None,
+            peers: HashMap::new(),
+            discovery: None,
+            peers_seen: HashMap::new(),
+            peers_emitted_at: None,
+            peers_emit_scheduled: false,
+            rescan_waiters: None,
+            connection_state: ConnectionState::Disconnected,
+            connections: HashMap::new(),
+            handshake_session: None,
+            last_notification: None,
+            settings: Settings::default(),
+            settings_path: None,
+            connect_target: None,
+            connect_retry_cancel: None,
+            handshake_waiters: HashMap::new(),
+            pairing_prompt: None,
+            pending_pair: None,
+            window_save_scheduled: false,
+        })))
         .setup(|app| {
+            let data_dir_warning = match data_dir::resolve(app.handle()) {
+                Ok((dirs, warning)) => {
+                    app.manage(dirs);
+                    warning
+                }
+                Err(e) => Some(format!("App dirs unavailable: {}", e)),
+            };
+            app.manage(init_logging(app.handle()));
+            if let Some(warning) = data_dir_warning {
+                warn!("{}", warning);
+            }
+            let sidecar_log = open_log(app.handle(), "sidecar.log")
+                .map_err(|e| warn!("Sidecar log file unavailable: {}", e))
+                .ok();
+            app.manage(SidecarLog::new(sidecar_log));
+            load_settings(app.handle());
+            onboarding::announce(app.handle());
+            let state = app.state::<SharedState>();
+            let log_level = tauri::async_runtime::block_on(state.lock()).settings.log_level.clone();
+            if let Err(e) = apply_log_level(&app.state::<LogLevelHandle>(), &log_level) {
+                warn!("Ignoring log level setting: {}", e);
+            }
+            window::restore(app.handle());
+            history::init(app.handle());
+            network::spawn_monitor(app.handle());
+            resume::spawn_monitor(app.handle());
+            peers::spawn_expiry(app.handle());
+            updates::spawn_checker(app.handle());
+            deep_link::init(app.handle());
+            if let Some(target) = parse_pair_arg(&std::env::args().collect::<Vec<_>>()) {
+                deep_link::pair_with(app.handle(), target);
+            }
+            if let Err(e) = tray::create(app.handle()) {
+                error!("Failed to create tray icon: {}", e);
+            }
+
             // Start the Python sidecar
-            if let Err(e) = start_sidecar(app.handle()) {
-                eprintln!("Failed to start sidecar: {}", e);
+            if let Err(e) = tauri::async_runtime::block_on(start_sidecar(app.handle())) {
+                error!("Failed to start sidecar: {}", e);
             }
             Ok(())
         })
+        .on_window_event(|window, event| {
+            window::track(window, event);
+            file_drop::handle(window, event);
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                let state = window.state::<SharedState>();
+                let run_in_background =
+                    tauri::async_runtime::block_on(state.lock()).settings.run_in_background;
+                // Keep the sidecar linked; the tray's Quit is the real exit
+                if run_in_background {
+                    api.prevent_close();
+                    let _ = window.hide();
+                } else if !tauri::async_runtime::block_on(quit::allowed(window.app_handle())) {
+                    api.prevent_close();
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             get_status,
+            get_version,
+            get_connection_state,
+            cancel_request,
+            get_dashboard,
+            device::get_local_device,
+            latency::get_latency,
+            bandwidth::get_bandwidth_stats,
+            connections::get_connections,
+            trust::trust_fingerprint,
+            device::get_device_name,
+            device::set_device_name,
+            device::set_device_type,
+            interfaces::list_interfaces,
+            interfaces::set_bind_interface,
+            raw_rpc,
             get_peers,
             connect_to_peer,
+            probe::probe_peer,
             submit_passphrase,
             disconnect_peer,
             send_notification_to_peer,
+            restart_sidecar,
+            retry_start_sidecar,
+            get_log_path,
+            storage::verify_storage,
+            storage::repair_storage,
+            read_log_tail,
+            get_recent_events,
+            get_pending_requests,
+            clear_event_log,
+            diagnostics::export_diagnostics,
+            onboarding::get_onboarding_status,
+            onboarding::set_onboarding_completed,
+            set_log_level,
+            get_sidecar_pid,
+            frontend_ready,
+            send_clipboard,
+            push_clipboard,
+            set_clipboard_sync_enabled,
+            history::get_transfer_history,
+            history::clear_transfer_history,
+            forget::forget_peer,
+            forget::forget_all_peers,
+            data_dir::get_data_dir,
+            data_dir::set_data_dir,
+            updates::check_for_update,
+            updates::install_update,
+            policy::block_peer,
+            policy::unblock_peer,
+            policy::set_peer_policy,
+            transfers::send_file,
+            transfers::send_files,
+            transfers::set_transfer_rate_limit,
+            transfers::cancel_transfer,
+            transfers::cancel_all_transfers,
+            transfer_list::list_transfers,
+            quit::confirm_quit,
+            quit::cancel_quit,
+            transfers::accept_transfer,
+            transfers::reject_transfer,
+            notifications::set_notifications_enabled,
+            notifications::broadcast_notification,
+            notifications::show_local_notification,
+            peers::rescan_peers,
+            peers::connect_by_id,
+            pairing::accept_pairing,
+            pairing::decline_pairing,
+            discovery::get_discovery_status,
+            settings::get_settings,
+            settings::set_settings,
+            settings::update_settings,
+            window::reset_window_position,
+            deep_link::get_pairing_payload,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                // Tell the peer we're going and don't leave an orphaned
+                // Python process behind
+                let state = app.state::<SharedState>().inner().clone();
+                tauri::async_runtime::block_on(async {
+                    // Flush a window geometry save that may still be debouncing
+                    state.lock().await.save_settings();
+                    stop_sidecar(app).await;
+                });
+            }
+        });
 }
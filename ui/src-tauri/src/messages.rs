@@ -0,0 +1,163 @@
+//! User-facing text as stable codes the frontend can localize.
+//!
+//! Errors and events that carry text for the user also carry a
+//! `message_code` such as `error.timeout` and the `params` to fill into it,
+//! and the frontend looks the code up in its own translations. Nothing is
+//! translated here: `ENGLISH` only renders the text for logs and for the
+//! `message`, `error` and `reason` fields older frontends read. Codes are
+//! part of the frontend contract; once shipped, a code keeps its meaning
+//! and its params.
+
+use std::fmt;
+
+/// English for every code, with `{name}` standing for a param.
+const ENGLISH: &[(&str, &str)] = &[
+    ("error.sidecar_not_running", "sidecar not running"),
+    ("error.not_connected", "not connected to a peer"),
+    ("error.timeout", "request timed out"),
+    ("error.transport", "transport error: {detail}"),
+    ("error.remote", "{detail}"),
+    ("error.rate_limited", "{detail}"),
+    ("error.cancelled", "request cancelled"),
+    ("error.overloaded", "too many pending requests"),
+    ("error.busy", "busy with another peer"),
+    ("error.invalid_payload", "invalid payload: {detail}"),
+    ("error.connect.peer_offline", "{detail}"),
+    ("error.connect.refused", "{detail}"),
+    ("error.connect.timeout", "{detail}"),
+    ("error.connect.wrong_passphrase", "{detail}"),
+    ("error.connect.incompatible_version", "{detail}"),
+    ("error.connect.tls_error", "{detail}"),
+    ("error.sidecar_missing", "cannot launch the helper: {detail}"),
+    (
+        "error.incompatible_sidecar",
+        "The Deck-Link helper doesn't match this version of the app. Please update or reinstall Deck-Link.",
+    ),
+    ("input.invalid_host", "invalid host \"{host}\""),
+    ("input.port_zero", "port must not be 0"),
+    ("input.already_connected", "already connected to {host}"),
+    ("input.passphrase_empty", "passphrase is empty"),
+    ("input.passphrase_too_long", "passphrase exceeds {max_chars} characters"),
+    ("input.no_pending_request", "no pending request {id}"),
+    ("input.no_pairing_request", "no pairing request {id}"),
+    ("input.raw_rpc_disabled", "raw_rpc is disabled"),
+    ("input.method_empty", "method is empty"),
+    ("input.unknown_interface", "unknown network interface \"{interface}\""),
+    ("input.unknown_log_level", "unknown log level \"{level}\""),
+    ("input.log_level_unchangeable", "cannot change log level: {detail}"),
+    ("input.clipboard_text_too_large", "clipboard text exceeds {max_bytes} bytes"),
+    ("input.clipboard_text_escapes_too_large", "clipboard text takes {escaped_bytes} bytes escaped, over {max_bytes}"),
+    ("input.clipboard_binary", "binary clipboard content is not supported"),
+    ("input.clipboard_unreadable", "cannot read clipboard: {detail}"),
+    ("input.clipboard_unsendable", "clipboard holds nothing that can be sent: {detail}"),
+    ("input.clipboard_empty", "clipboard is empty"),
+    ("input.image_unencodable", "cannot encode clipboard image: {detail}"),
+    ("input.image_too_large", "clipboard image exceeds {max_bytes} bytes"),
+    ("input.data_dir_not_absolute", "data dir must be an absolute path"),
+    ("input.data_dir_unwritable", "cannot write to {path}: {detail}"),
+    ("input.unknown_device_type", "unknown device type \"{device_type}\""),
+    ("input.device_name_empty", "device name is empty"),
+    ("input.device_name_too_long", "device name exceeds {max_chars} characters"),
+    ("input.destination_empty", "destination path is empty"),
+    ("input.peer_id_empty", "peer id is empty"),
+    ("input.peer_not_found", "peer {peer_id} is not on this network"),
+    ("input.unknown_peer_policy", "unknown peer policy \"{policy}\"; expected block or allow"),
+    ("input.not_a_fingerprint", "not a fingerprint"),
+    ("input.notification_title_too_long", "notification title exceeds {max_bytes} bytes"),
+    ("input.notification_body_too_long", "notification body exceeds {max_bytes} bytes"),
+    ("input.sidecar_port_range", "sidecar port {port} is outside 1024-65535"),
+    ("input.sidecar_env_name", "invalid sidecar environment variable name"),
+    ("input.connect_retry_delays", "connect retry delays must satisfy 0 < initial_delay_ms <= max_delay_ms"),
+    ("input.update_endpoint_not_https", "update endpoint \"{endpoint}\" is not an https URL"),
+    ("input.invalid_update_endpoint", "invalid update endpoint: {detail}"),
+    ("input.update_interval", "check_interval_hours must be at least 1"),
+    ("input.no_update", "no update available"),
+    ("input.settings_not_object", "settings patch must be an object"),
+    ("input.invalid_settings", "invalid settings: {detail}"),
+    ("input.max_pending_requests", "max_pending_requests must be at least 1"),
+    ("input.peer_ttl_too_low", "peer_ttl_secs must be at least {min_secs}"),
+    ("input.rate_limit_too_low", "transfer rate limit must be at least 1 kbps"),
+    ("input.no_files", "no files to send"),
+    ("input.file_unreadable", "cannot read {path}: {detail}"),
+    ("input.not_a_file", "{path} is not a file"),
+    ("input.file_uncreatable", "cannot create {path}: {detail}"),
+    ("input.no_active_transfer", "no active transfer {id}"),
+    ("input.no_pending_transfer", "no pending transfer {id}"),
+    ("input.save_path_parent_dir", "invalid save path: must not contain '..'"),
+    ("input.save_path_no_file_name", "invalid save path: missing file name"),
+    ("input.save_path_no_downloads", "invalid save path: downloads directory unavailable: {detail}"),
+    ("input.save_path_no_directory", "invalid save path: directory does not exist"),
+    ("input.save_path_outside_downloads", "invalid save path: must be inside {dir}"),
+    ("link.not_pairing", "not a pairing link"),
+    ("link.invalid_port", "invalid port \"{port}\""),
+    ("link.invalid_peer_id", "invalid peer id"),
+    ("link.no_target", "link has neither host nor peer_id"),
+    ("link.invalid_token", "invalid token"),
+    ("link.peer_not_found", "peer from link not found on this network"),
+    ("storage.partly_forgotten", "partly forgotten; failed {failed}"),
+    ("storage.backup_failed", "cannot back up settings: {detail}"),
+    ("transfer.cancelled", "cancelled"),
+    ("transfer.cancelled_by_peer", "cancelled by peer"),
+    ("transfer.disconnected", "disconnected"),
+    ("transfer.incomplete", "incomplete transfer"),
+    ("transfer.truncated", "file was truncated during transfer"),
+    ("transfer.deleted", "file was deleted during transfer"),
+    ("transfer.modified", "file was modified during transfer"),
+    ("transfer.invalid_chunk", "invalid chunk data: {detail}"),
+    ("transfer.unknown", "unknown transfer"),
+    ("transfer.not_accepted", "transfer was not accepted"),
+    ("transfer.out_of_order", "chunk out of order"),
+    ("transfer.oversized", "peer sent more data than announced"),
+    ("transfer.failed", "{detail}"),
+    ("files.not_connected", "connect to a device before sending files"),
+    ("connection.sidecar_unresponsive", "sidecar not responding"),
+    ("connection.peer_unresponsive", "peer not answering pings"),
+    ("connection.high_latency", "high latency"),
+];
+
+/// A piece of user-facing text: its code and the params to fill in.
+#[derive(Debug, Clone)]
+pub struct Text {
+    pub code: &'static str,
+    pub params: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Text {
+    pub fn new(code: &'static str) -> Self {
+        Self { code, params: serde_json::Map::new() }
+    }
+
+    pub fn with(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.params.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// The code with `detail` as its only param, for text that comes from
+    /// elsewhere (the OS, the sidecar) already in English.
+    pub fn detail(code: &'static str, detail: impl fmt::Display) -> Self {
+        Self::new(code).with("detail", detail.to_string())
+    }
+
+    /// The English text; a code missing from `ENGLISH` renders as itself.
+    pub fn english(&self) -> String {
+        let template = ENGLISH
+            .iter()
+            .find(|(code, _)| *code == self.code)
+            .map_or(self.code, |(_, english)| english);
+        let mut text = template.to_string();
+        for (name, value) in &self.params {
+            let value = match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            text = text.replace(&format!("{{{}}}", name), &value);
+        }
+        text
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.english())
+    }
+}
@@ -0,0 +1,401 @@
+//! An in-process stand-in for the Python sidecar, for working on the
+//! frontend without Python: launch with `PATCH_MOCK=1`.
+//!
+//! It reads the same JSON-RPC lines the real sidecar would get on stdin
+//! and answers on the same event channel a spawned process reports
+//! through, so nothing above `SidecarChild` can tell the two apart.
+//! Answers are canned; a couple of fake peers come and go on a timer, and
+//! the one connected to offers a small file.
+//!
+//! With `PATCH_MOCK=stdio` the app runs nothing but the mock, on its own
+//! stdin and stdout, so the tests in `tests/` can drive it as a process the
+//! way we drive the sidecar.
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use base64::Engine;
+use tauri::async_runtime::{channel, Receiver, Sender};
+use tauri_plugin_shell::process::{CommandEvent, TerminatedPayload};
+use tokio::sync::mpsc;
+
+use crate::{DEFAULT_PORT, SUPPORTED_PROTOCOL};
+
+/// Set to `1` to use the mock instead of spawning the sidecar.
+const ENV_VAR: &str = "PATCH_MOCK";
+
+/// How often a fake peer is announced (or withdrawn).
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a mock connection takes to be "accepted".
+const CONNECT_DELAY: Duration = Duration::from_millis(500);
+
+/// How often traffic on the mock connection is reported, like the sidecar does.
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The file the fake peer offers once connected.
+const MOCK_TRANSFER_ID: &str = "mock-transfer";
+const MOCK_TRANSFER: &[u8] = b"Sent by the mock sidecar.\n";
+
+pub fn enabled() -> bool {
+    std::env::var(ENV_VAR).is_ok_and(|value| value == "1")
+}
+
+/// Whether to serve the mock on stdio instead of starting the app.
+pub fn stdio() -> bool {
+    std::env::var(ENV_VAR).is_ok_and(|value| value == "stdio")
+}
+
+/// Write end of the mock's "stdin".
+pub struct MockChild {
+    stdin: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl MockChild {
+    pub fn write(&mut self, buf: &[u8]) -> Result<(), String> {
+        self.stdin
+            .send(buf.to_vec())
+            .map_err(|_| "mock sidecar has exited".to_string())
+    }
+
+    /// Closing stdin ends the mock, which then reports itself terminated.
+    pub fn kill(self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Start the mock, returning what spawning the real sidecar would.
+pub fn spawn() -> (Receiver<CommandEvent>, MockChild) {
+    let (events, rx) = channel(64);
+    let (stdin, requests) = mpsc::unbounded_channel();
+    tauri::async_runtime::spawn(run(events, requests));
+    (rx, MockChild { stdin })
+}
+
+/// Run the mock on this process's stdin and stdout until stdin closes or
+/// it is asked to shut down.
+pub fn serve_stdio() {
+    tauri::async_runtime::block_on(async {
+        let (mut rx, mut child) = spawn();
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin().lock();
+            let mut buf = [0; 8192];
+            // Dropping `child` at EOF ends the mock
+            while let Ok(n @ 1..) = stdin.read(&mut buf) {
+                if child.write(&buf[..n]).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut stdout = std::io::stdout().lock();
+        while let Some(event) = rx.recv().await {
+            match event {
+                CommandEvent::Stdout(line) if stdout.write_all(&line).and_then(|_| stdout.flush()).is_ok() => {}
+                // Or nobody is reading any more
+                CommandEvent::Stdout(_) | CommandEvent::Terminated(_) => break,
+                _ => {}
+            }
+        }
+    });
+}
+
+fn fake_peers() -> [serde_json::Value; 2] {
+    [
+        serde_json::json!({
+            "id": "mock-deck._decklink._tcp.local.",
+            "name": "Steam Deck (mock)",
+            "device_type": "deck",
+            "ip": "192.0.2.10",
+            "port": DEFAULT_PORT
+        }),
+        serde_json::json!({
+            "id": "mock-laptop._decklink._tcp.local.",
+            "name": "Laptop (mock)",
+            "device_type": "laptop",
+            "ip": "192.0.2.11",
+            "port": DEFAULT_PORT
+        }),
+    ]
+}
+
+#[derive(Default)]
+struct Mock {
+    /// Which fake peers are currently "discovered".
+    visible: [bool; 2],
+    /// Name and IP of the peer we're "connected" to.
+    connected: Option<(String, String)>,
+    /// Bytes "sent" and "received" on the connection.
+    traffic: (u64, u64),
+    /// The fake peer's file is waiting to be accepted or rejected.
+    offered: bool,
+}
+
+async fn run(events: Sender<CommandEvent>, mut requests: mpsc::UnboundedReceiver<Vec<u8>>) {
+    let mut mock = Mock::default();
+    let mut discovery = tokio::time::interval(DISCOVERY_INTERVAL);
+    let mut stats = tokio::time::interval(STATS_INTERVAL);
+    let mut tick = 0;
+    let mut buffer = Vec::new();
+
+    loop {
+        tokio::select! {
+            request = requests.recv() => {
+                let Some(bytes) = request else {
+                    break;
+                };
+                buffer.extend_from_slice(&bytes);
+                while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    if !mock.handle(&events, &line).await {
+                        let _ = events.send(terminated()).await;
+                        return;
+                    }
+                }
+            }
+            _ = discovery.tick() => {
+                // Peer 0 stays; peer 1 comes and goes
+                let index = if tick == 0 { 0 } else { 1 };
+                tick += 1;
+                mock.toggle_peer(&events, index).await;
+                // Like the sidecar's heartbeat, so peers don't expire
+                mock.announce_visible(&events).await;
+            }
+            _ = stats.tick() => mock.report_traffic(&events).await,
+        }
+    }
+    let _ = events.send(terminated()).await;
+}
+
+fn terminated() -> CommandEvent {
+    CommandEvent::Terminated(TerminatedPayload { code: Some(0), signal: None })
+}
+
+async fn send_line(events: &Sender<CommandEvent>, message: serde_json::Value) {
+    let mut line = message.to_string().into_bytes();
+    line.push(b'\n');
+    let _ = events.send(CommandEvent::Stdout(line)).await;
+}
+
+async fn emit(events: &Sender<CommandEvent>, event: &str, data: serde_json::Value) {
+    send_line(events, serde_json::json!({ "event": event, "data": data })).await;
+}
+
+impl Mock {
+    /// Answer one request line. Returns false once asked to shut down.
+    async fn handle(&mut self, events: &Sender<CommandEvent>, line: &[u8]) -> bool {
+        let Ok(request) = serde_json::from_slice::<serde_json::Value>(line) else {
+            return true;
+        };
+        let id = request.get("id").cloned().unwrap_or_default();
+        let method = request.get("method").and_then(|m| m.as_str()).unwrap_or_default();
+        let params = request.get("params").cloned().unwrap_or_default();
+
+        let result = match method {
+            "ping" => Ok(serde_json::json!({
+                "pong": true,
+                "protocol_version": SUPPORTED_PROTOCOL,
+                "version": "mock",
+                "peer_rtt_ms": self.connected.as_ref().map(|_| 12.0)
+            })),
+            "get_status" => Ok(self.status()),
+            "get_peers" => Ok(self.peers()),
+            "get_discovery_status" => Ok(serde_json::json!({
+                "active": true,
+                "last_scan_at": null,
+                "interfaces": ["192.0.2.1"],
+                "error": null
+            })),
+            "connect" => {
+                let host = params.get("host").and_then(|h| h.as_str()).unwrap_or_default();
+                let name = fake_peers()
+                    .into_iter()
+                    .find(|peer| peer["ip"] == host)
+                    .map(|peer| peer["name"].as_str().unwrap_or_default().to_string())
+                    .unwrap_or_else(|| format!("Mock peer at {}", host));
+                self.connected = Some((name, host.to_string()));
+                self.traffic = (0, 0);
+                Ok(serde_json::json!({ "status": "connecting", "session_id": "mock-session" }))
+            }
+            "disconnect" => Ok(serde_json::json!({ "status": "disconnected" })),
+            "verify_connections" => Ok(serde_json::json!({
+                "alive": self.connected.iter().map(|_| "mock-session").collect::<Vec<_>>()
+            })),
+            "rescan" => Ok(serde_json::json!({ "status": "scanning" })),
+            "probe" => {
+                let host = params.get("host").and_then(|h| h.as_str()).unwrap_or_default();
+                let known = fake_peers().iter().any(|peer| peer["ip"] == host);
+                Ok(serde_json::json!({
+                    "reachable": known,
+                    "rtt_ms": known.then_some(4.0),
+                    "error": (!known).then_some("timed out")
+                }))
+            }
+            "create_pairing_token" => Ok(serde_json::json!({
+                "token": "mock-pairing-token-000",
+                "peer_id": "mock-self._decklink._tcp.local.",
+                "local_info": self.local_info()
+            })),
+            "shutdown" => Ok(serde_json::json!({ "status": "shutting_down" })),
+            "set_clipboard" | "set_clipboard_image" | "transfer_start" | "transfer_chunk"
+            | "transfer_finish" if self.connected.is_none() => Err("Not connected".to_string()),
+            "set_clipboard" | "set_clipboard_image" => Ok(serde_json::json!({ "status": "sent" })),
+            // The fake peer accepts whatever it is offered straight away
+            "transfer_start" => Ok(serde_json::json!({ "status": "offered" })),
+            "transfer_chunk" => Ok(serde_json::json!({ "status": "sent" })),
+            "transfer_finish" => Ok(serde_json::json!({ "status": "finished" })),
+            "transfer_cancel" => Ok(serde_json::json!({ "status": "cancelled" })),
+            "accept_transfer" | "reject_transfer" if !self.offered || params["id"] != MOCK_TRANSFER_ID => {
+                Err(format!("No pending transfer {}", params["id"]))
+            }
+            "accept_transfer" => Ok(serde_json::json!({ "status": "accepted" })),
+            "reject_transfer" => Ok(serde_json::json!({ "status": "rejected" })),
+            "cancel" => Ok(serde_json::json!({ "status": "finished", "id": params.get("id") })),
+            "revoke_pairing_secrets" => Ok(serde_json::json!({ "revoked": 0 })),
+            // Echoed, so tests can see what the peer would have been sent
+            "send_notification" => Ok(serde_json::json!({ "status": "sent", "delivered": params })),
+            "submit_passphrase" | "set_device_name" | "set_device_type"
+            | "set_transfer_rate_limit" | "accept_pairing" | "reject_pairing" | "set_bind_interface" => {
+                Ok(serde_json::json!({ "status": "ok" }))
+            }
+            _ => Err(format!("Unknown method: {}", method)),
+        };
+
+        match result {
+            Ok(result) => send_line(events, serde_json::json!({ "result": result, "id": id })).await,
+            Err(error) => send_line(events, serde_json::json!({ "error": error, "id": id })).await,
+        }
+
+        // Follow-up events, as the real sidecar sends them after answering
+        match method {
+            "connect" => {
+                tokio::time::sleep(CONNECT_DELAY).await;
+                if let Some((name, ip)) = &self.connected {
+                    emit(events, "connected", serde_json::json!({
+                        "session_id": "mock-session",
+                        "peer_name": name,
+                        "peer_ip": ip
+                    }))
+                    .await;
+                    // The fake peer offers a file, to try the receiving side on
+                    self.offered = true;
+                    emit(events, "transfer_request", serde_json::json!({
+                        "id": MOCK_TRANSFER_ID,
+                        "filename": "hello-from-mock.txt",
+                        "size": MOCK_TRANSFER.len(),
+                        "session_id": "mock-session",
+                        "peer_name": name
+                    }))
+                    .await;
+                }
+            }
+            "accept_transfer" | "reject_transfer" if self.offered => {
+                self.offered = false;
+                if method == "accept_transfer" {
+                    emit(events, "transfer_chunk", serde_json::json!({
+                        "id": MOCK_TRANSFER_ID,
+                        "offset": 0,
+                        "data": base64::engine::general_purpose::STANDARD.encode(MOCK_TRANSFER),
+                        "session_id": "mock-session"
+                    }))
+                    .await;
+                    emit(events, "transfer_finish", serde_json::json!({
+                        "id": MOCK_TRANSFER_ID,
+                        "session_id": "mock-session"
+                    }))
+                    .await;
+                }
+            }
+            "disconnect" if self.connected.take().is_some() => {
+                self.offered = false;
+                emit(events, "disconnected", serde_json::json!({ "session_id": "mock-session" })).await;
+            }
+            "rescan" => self.announce_visible(events).await,
+            "shutdown" => return false,
+            _ => {}
+        }
+        true
+    }
+
+    async fn announce_visible(&self, events: &Sender<CommandEvent>) {
+        for index in 0..self.visible.len() {
+            if self.visible[index] {
+                emit(events, "peer_discovered", fake_peers()[index].clone()).await;
+            }
+        }
+    }
+
+    async fn report_traffic(&mut self, events: &Sender<CommandEvent>) {
+        if self.connected.is_none() {
+            return;
+        }
+        self.traffic.0 += 2_000;
+        self.traffic.1 += 50_000;
+        emit(events, "stats", serde_json::json!({
+            "session_id": "mock-session",
+            "bytes_sent": self.traffic.0,
+            "bytes_received": self.traffic.1
+        }))
+        .await;
+    }
+
+    async fn toggle_peer(&mut self, events: &Sender<CommandEvent>, index: usize) {
+        let peer = fake_peers()[index].clone();
+        self.visible[index] = !self.visible[index];
+        if self.visible[index] {
+            emit(events, "peer_discovered", peer).await;
+        } else {
+            emit(events, "peer_lost", serde_json::json!({ "id": peer["id"] })).await;
+        }
+    }
+
+    fn peers(&self) -> serde_json::Value {
+        let peers = fake_peers();
+        let visible = peers.iter().zip(self.visible).filter(|(_, visible)| *visible);
+        serde_json::Value::Array(visible.map(|(peer, _)| peer.clone()).collect())
+    }
+
+    fn local_info(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": "This device (mock)",
+            "type": "laptop",
+            "ip": "192.0.2.1",
+            "addresses": [
+                { "ip": "192.0.2.1", "family": 4, "primary": true, "link_local": false },
+                { "ip": "2001:db8::1", "family": 6, "primary": false, "link_local": false }
+            ],
+            "port": DEFAULT_PORT
+        })
+    }
+
+    fn status(&self) -> serde_json::Value {
+        let (state, peer) = match &self.connected {
+            Some((name, ip)) => (
+                "connected",
+                serde_json::json!({ "name": name, "ip": ip, "port": DEFAULT_PORT }),
+            ),
+            None => ("disconnected", serde_json::Value::Null),
+        };
+        let connections: Vec<serde_json::Value> = self
+            .connected
+            .iter()
+            .map(|(name, ip)| serde_json::json!({
+                "session_id": "mock-session",
+                "name": name,
+                "ip": ip,
+                "port": DEFAULT_PORT,
+                "fingerprint": null
+            }))
+            .collect();
+        serde_json::json!({
+            "state": state,
+            "device_name": "This device (mock)",
+            "device_type": "laptop",
+            "port": DEFAULT_PORT,
+            "fingerprint": null,
+            "connections": connections,
+            "peer": peer,
+            "session_id": self.connected.as_ref().map(|_| "mock-session"),
+            "local_info": self.local_info()
+        })
+    }
+}
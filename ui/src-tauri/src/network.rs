@@ -0,0 +1,104 @@
+//! Noticing when the local network changes (new Wi-Fi, Ethernet plugged in).
+//!
+//! There is no portable change notification, so the local IP is polled.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+
+use crate::{device, emit_event, peers, spawn_auto_reconnect, SharedState};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often `wait_until_online` looks again.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A local address other than loopback, IPv4 or IPv6.
+fn usable_ip() -> Option<IpAddr> {
+    local_ip_address::local_ip()
+        .or_else(|_| local_ip_address::local_ipv6())
+        .ok()
+        .filter(|ip| !ip.is_loopback())
+}
+
+/// Wait up to `timeout` for a usable local address, announcing
+/// `sidecar:awaiting_network` if there isn't one yet. Returns whether one
+/// turned up.
+pub async fn wait_until_online(app: &AppHandle, timeout: Duration) -> bool {
+    if usable_ip().is_some() {
+        return true;
+    }
+    if timeout.is_zero() {
+        return false;
+    }
+    info!("No network yet; waiting up to {:?}", timeout);
+    emit_event(app, "sidecar:awaiting_network", serde_json::json!({
+        "timeout_ms": timeout.as_millis() as u64
+    }))
+    .await;
+    let deadline = tokio::time::Instant::now() + timeout;
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+        if usable_ip().is_some() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Start the monitor. Call once from setup.
+///
+/// A change only counts once it has been seen on two polls in a row, so an
+/// interface flapping while it comes up doesn't trigger a burst of rescans.
+/// Losing the network entirely is reported but doesn't rescan; getting it
+/// back does, and reconnects to the last peer as auto-reconnect would.
+pub fn spawn_monitor(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<SharedState>().inner().clone();
+        {
+            let mut state = state.lock().await;
+            state.local_ip = local_ip_address::local_ip().ok();
+            device::invalidate(&mut state);
+        }
+
+        let mut candidate: Option<Option<IpAddr>> = None;
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let current = local_ip_address::local_ip().ok();
+            let previous = state.lock().await.local_ip;
+            if current == previous {
+                candidate = None;
+                continue;
+            }
+            if candidate != Some(current) {
+                candidate = Some(current);
+                continue;
+            }
+            candidate = None;
+
+            let ready = {
+                let mut state = state.lock().await;
+                state.local_ip = current;
+                device::invalidate(&mut state);
+                state.sidecar.is_ready()
+            };
+            info!("Local IP changed from {:?} to {:?}", previous, current);
+            emit_event(&app, "sidecar:network_changed", serde_json::json!({
+                "old_ip": previous,
+                "new_ip": current
+            }))
+            .await;
+
+            if ready && current.is_some() {
+                if let Err(e) = peers::rescan_peers(app.clone(), None).await {
+                    warn!("Rescan after network change failed: {}", e);
+                }
+                spawn_auto_reconnect(&app, false);
+            }
+        }
+    });
+}
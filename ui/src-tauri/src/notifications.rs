@@ -0,0 +1,357 @@
+//! Native OS notifications, for notifications forwarded by the peer and
+//! for ones we send ourselves.
+//!
+//! The peer's notifications are held back while the OS is in do-not-disturb
+//! (Focus on macOS, Focus assist or presentation mode on Windows) unless
+//! `notifications_ignore_dnd` is set. Elsewhere DND can't be queried and
+//! they are always shown.
+//!
+//! Ours go to one peer (`send_notification_to_peer`) or to every
+//! connected peer the policy honours (`broadcast_notification`).
+//!
+//! Some Linux desktops have no notification daemon, so showing one can
+//! fail. That is logged and announced as `sidecar:notification_failed`,
+//! never returned as an error; the peer's notifications reach the in-app
+//! list as `sidecar:notification` either way. `native_notifications` off
+//! leaves the in-app list as the only place they appear.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+use tracing::{debug, error, info, warn};
+
+use crate::messages::Text;
+use crate::{call_sidecar, connections, emit_event, ipc, policy, RpcError, SharedState};
+
+/// Identical notifications arriving within this window are shown once.
+const DEBOUNCE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Longest title we send or show, in bytes.
+const MAX_TITLE_LEN: usize = 256;
+
+/// Longest body we send or show, in bytes.
+const MAX_BODY_LEN: usize = 4096;
+
+#[derive(serde::Deserialize)]
+pub struct PeerNotification {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+/// The last notification shown, for debouncing duplicates.
+pub struct LastNotification {
+    title: String,
+    body: String,
+    shown_at: Instant,
+}
+
+/// Show a `notification` event from the sidecar as a native notification.
+pub async fn show_peer_notification(app: &AppHandle, notification: PeerNotification, data: &serde_json::Value) {
+    let (peer_name, ignore_dnd) = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        if !state.settings.notifications_enabled {
+            return;
+        }
+
+        let duplicate = state.last_notification.as_ref().is_some_and(|last| {
+            last.title == notification.title
+                && last.body == notification.body
+                && last.shown_at.elapsed() < DEBOUNCE_WINDOW
+        });
+        if duplicate {
+            return;
+        }
+        state.last_notification = Some(LastNotification {
+            title: notification.title.clone(),
+            body: notification.body.clone(),
+            shown_at: Instant::now(),
+        });
+        let peer_name = connections::for_event(&state, data).map(|connection| connection.name.clone());
+        (peer_name, state.settings.notifications_ignore_dnd)
+    };
+
+    // Still forwarded as `sidecar:notification`, so it reaches the event log
+    if !ignore_dnd && do_not_disturb() == Some(true) {
+        debug!("Do not disturb is on; not showing notification");
+        emit_event(app, "sidecar:notification_suppressed", serde_json::json!({
+            "reason": "do_not_disturb",
+            "peer_name": peer_name,
+            "title": notification.title
+        }))
+        .await;
+        return;
+    }
+
+    let title = match peer_name {
+        Some(peer_name) => format!("{}: {}", peer_name, notification.title),
+        None => notification.title,
+    };
+    show(app, "peer", &title, &notification.body).await;
+}
+
+/// Show a native notification unless `native_notifications` is off.
+/// `origin` says whose it is, `"peer"` or `"local"`, for
+/// `sidecar:notification_failed`. Returns whether it was shown.
+async fn show(app: &AppHandle, origin: &str, title: &str, body: &str) -> bool {
+    let state = app.state::<SharedState>();
+    if !state.lock().await.settings.native_notifications {
+        return false;
+    }
+    let Err(e) = app.notification().builder().title(title).body(body).show() else {
+        return true;
+    };
+    warn!("Cannot show notification: {}", e);
+    emit_event(app, "sidecar:notification_failed", serde_json::json!({
+        "origin": origin,
+        "title": title,
+        "error": e.to_string()
+    }))
+    .await;
+    false
+}
+
+/// Whether the OS is holding back notifications; `None` if we can't tell.
+#[cfg(target_os = "macos")]
+fn do_not_disturb() -> Option<bool> {
+    // Focus modes turned on by hand are recorded as assertions here; the
+    // file may be unreadable without Full Disk Access
+    let home = std::env::var_os("HOME")?;
+    let path = std::path::Path::new(&home).join("Library/DoNotDisturb/DB/Assertions.json");
+    let assertions: serde_json::Value = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+    let active = assertions
+        .get("data")?
+        .as_array()?
+        .iter()
+        .filter_map(|entry| entry.get("storeAssertionRecords")?.as_array())
+        .any(|records| !records.is_empty());
+    Some(active)
+}
+
+#[cfg(windows)]
+fn do_not_disturb() -> Option<bool> {
+    // QUERY_USER_NOTIFICATION_STATE values that mean "not now"
+    const QUNS_BUSY: i32 = 2;
+    const QUNS_RUNNING_D3D_FULL_SCREEN: i32 = 3;
+    const QUNS_PRESENTATION_MODE: i32 = 4;
+    const QUNS_QUIET_TIME: i32 = 6;
+
+    #[link(name = "shell32")]
+    extern "system" {
+        fn SHQueryUserNotificationState(state: *mut i32) -> i32;
+    }
+
+    let mut state = 0;
+    // SAFETY: the only argument is a valid pointer to an i32 we own
+    let hresult = unsafe { SHQueryUserNotificationState(&mut state) };
+    if hresult < 0 {
+        return None;
+    }
+    Some(matches!(
+        state,
+        QUNS_BUSY | QUNS_RUNNING_D3D_FULL_SCREEN | QUNS_PRESENTATION_MODE | QUNS_QUIET_TIME
+    ))
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn do_not_disturb() -> Option<bool> {
+    None
+}
+
+/// Check an outgoing notification against the size limits.
+pub fn validate(title: &str, body: &str) -> Result<(), RpcError> {
+    if title.len() > MAX_TITLE_LEN {
+        return Err(RpcError::InvalidInput(
+            Text::new("input.notification_title_too_long").with("max_bytes", MAX_TITLE_LEN),
+        ));
+    }
+    if body.len() > MAX_BODY_LEN {
+        return Err(RpcError::InvalidInput(
+            Text::new("input.notification_body_too_long").with("max_bytes", MAX_BODY_LEN),
+        ));
+    }
+    Ok(())
+}
+
+/// The `send_notification` request for the peer in session `session_id`.
+pub fn request(title: &str, body: &str, priority: Option<&str>, session_id: Option<&str>) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "body": body,
+        "priority": priority,
+        "session_id": session_id
+    })
+}
+
+/// Show a notification of ours on this device. Returns whether it was
+/// shown; see `show`.
+pub async fn show_local(app: &AppHandle, title: &str, body: &str) -> bool {
+    show(app, "local", title, body).await
+}
+
+/// Show a notification on this device only, without the sidecar or a
+/// peer; mostly for checking notifications work at all. Whether they do
+/// shows as `sidecar:notification_failed`, not as an error.
+#[tauri::command]
+pub async fn show_local_notification(
+    app: AppHandle,
+    title: String,
+    body: String,
+) -> Result<(), RpcError> {
+    validate(&title, &body)?;
+    // Contents may be private; only their size is logged
+    debug!("Showing local notification ({} + {} bytes)", title.len(), body.len());
+    show_local(&app, &title, &body).await;
+    Ok(())
+}
+
+/// One peer's part in a broadcast.
+#[derive(Debug, Clone, Serialize)]
+pub struct BroadcastTarget {
+    pub session_id: String,
+    pub name: String,
+    /// Why it wasn't delivered, for failed sends.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BroadcastReport {
+    pub delivered: Vec<BroadcastTarget>,
+    pub failed: Vec<BroadcastTarget>,
+    /// Peers the policy doesn't honour, which weren't sent anything.
+    pub skipped: Vec<BroadcastTarget>,
+}
+
+/// Send a notification to every connected peer at once, except those the
+/// peer policy blocks, and report who got it. Only fails outright, with
+/// `not_connected`, when nobody is connected.
+///
+/// The outcome is also announced as `sidecar:notification_broadcast`.
+#[tauri::command]
+pub async fn broadcast_notification(
+    app: AppHandle,
+    title: String,
+    body: String,
+    trace_id: Option<String>,
+) -> Result<BroadcastReport, RpcError> {
+    ipc::traced(trace_id, async move {
+        validate(&title, &body)?;
+        let state = app.state::<SharedState>().inner().clone();
+        let mut report = BroadcastReport::default();
+        let targets: Vec<BroadcastTarget> = {
+            let state = state.lock().await;
+            if state.connections.is_empty() {
+                return Err(RpcError::NotConnected);
+            }
+            let mut targets = Vec::new();
+            for connection in state.connections.values() {
+                let target = BroadcastTarget {
+                    session_id: connection.session_id.clone(),
+                    name: connection.name.clone(),
+                    error: None,
+                };
+                if policy::honours(&state.settings, connection.peer_id.as_deref()) {
+                    targets.push(target);
+                } else {
+                    report.skipped.push(target);
+                }
+            }
+            targets
+        };
+
+        // Contents may be private; only their size is logged
+        debug!(
+            "Broadcasting notification ({} + {} bytes) to {} peers",
+            title.len(),
+            body.len(),
+            targets.len()
+        );
+        let sends: Vec<_> = targets
+            .into_iter()
+            .map(|target| {
+                let state = state.clone();
+                let params = request(&title, &body, None, Some(&target.session_id));
+                tauri::async_runtime::spawn(ipc::traced(ipc::trace_id(), async move {
+                    let result = call_sidecar(&state, "send_notification", params).await;
+                    (target, result)
+                }))
+            })
+            .collect();
+        for send in sends {
+            match send.await {
+                Ok((target, Ok(_))) => report.delivered.push(target),
+                Ok((mut target, Err(e))) => {
+                    warn!("Broadcast to {} failed: {}", target.name, e);
+                    target.error = Some(e.to_string());
+                    report.failed.push(target);
+                }
+                Err(e) => error!("Broadcast send panicked: {}", e),
+            }
+        }
+
+        info!(
+            "Broadcast notification: {} delivered, {} failed, {} skipped",
+            report.delivered.len(),
+            report.failed.len(),
+            report.skipped.len()
+        );
+        emit_event(&app, "sidecar:notification_broadcast", serde_json::to_value(&report)?).await;
+        Ok(report)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_notifications_enabled(
+    state: tauri::State<'_, SharedState>,
+    enabled: bool,
+) -> Result<(), RpcError> {
+    let mut state = state.lock().await;
+    state.settings.notifications_enabled = enabled;
+    state.save_settings();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_enforces_size_limits() {
+        assert!(validate(&"t".repeat(MAX_TITLE_LEN), &"b".repeat(MAX_BODY_LEN)).is_ok());
+        let Err(RpcError::InvalidInput(text)) = validate(&"t".repeat(MAX_TITLE_LEN + 1), "") else {
+            panic!("long title accepted");
+        };
+        assert_eq!(text.code, "input.notification_title_too_long");
+        let Err(RpcError::InvalidInput(text)) = validate("", &"b".repeat(MAX_BODY_LEN + 1)) else {
+            panic!("long body accepted");
+        };
+        assert_eq!(text.code, "input.notification_body_too_long");
+    }
+
+    #[test]
+    fn validate_counts_bytes_not_chars() {
+        assert!(validate(&"é".repeat(MAX_TITLE_LEN / 2 + 1), "").is_err());
+    }
+
+    #[tokio::test]
+    async fn notification_reaches_the_sidecar_intact() {
+        let (state, _outputs) = crate::sidecar::testing::start_mock();
+        let title = "Build \"finished\"";
+        let body = "All tests passed ✓\n\tSee the log in C:\\builds";
+        let params = request(title, body, Some("high"), Some("session-1"));
+
+        let timeout = crate::rpc_timeout("send_notification");
+        let result = crate::sidecar::call(&state, "send_notification", params, timeout).await.unwrap();
+        let delivered = &result["delivered"];
+        assert_eq!(delivered["title"], title);
+        assert_eq!(delivered["body"], body);
+        assert_eq!(delivered["priority"], "high");
+        assert_eq!(delivered["session_id"], "session-1");
+    }
+}
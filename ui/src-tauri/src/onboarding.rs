@@ -0,0 +1,86 @@
+//! First-run setup.
+//!
+//! Two persisted flags drive it: `onboarding_completed`, which the setup
+//! wizard sets once the user is through it, and `has_paired`, set the first
+//! time pairing succeeds so the "pair your first device" hint can go away.
+//! While onboarding is incomplete, startup announces `app:first_run`; a
+//! change to either flag is announced as `app:onboarding`.
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+
+use crate::{emit_event, AppState, RpcError, SharedState};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct OnboardingStatus {
+    pub completed: bool,
+    pub has_paired: bool,
+}
+
+impl OnboardingStatus {
+    fn of(state: &AppState) -> Self {
+        Self {
+            completed: state.settings.onboarding_completed,
+            has_paired: state.settings.has_paired,
+        }
+    }
+}
+
+/// Announce `app:first_run` if onboarding hasn't been completed. Call once
+/// from setup, after the settings are loaded.
+pub fn announce(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let status = {
+            let state = app.state::<SharedState>();
+            let state = state.lock().await;
+            OnboardingStatus::of(&state)
+        };
+        if !status.completed {
+            info!("First run; onboarding not completed");
+            emit(&app, "app:first_run", status).await;
+        }
+    });
+}
+
+/// Note that pairing succeeded, the first time it does. The caller saves
+/// the settings.
+pub fn record_pairing(app: &AppHandle, state: &mut AppState) {
+    if state.settings.has_paired {
+        return;
+    }
+    state.settings.has_paired = true;
+    let status = OnboardingStatus::of(state);
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move { emit(&app, "app:onboarding", status).await });
+}
+
+async fn emit(app: &AppHandle, event: &str, status: OnboardingStatus) {
+    match serde_json::to_value(status) {
+        Ok(status) => emit_event(app, event, status).await,
+        Err(e) => warn!("Failed to serialize onboarding status: {}", e),
+    }
+}
+
+#[tauri::command]
+pub async fn get_onboarding_status(state: tauri::State<'_, SharedState>) -> Result<OnboardingStatus, RpcError> {
+    Ok(OnboardingStatus::of(&*state.lock().await))
+}
+
+/// Mark first-run setup finished, or with `false`, due again.
+#[tauri::command]
+pub async fn set_onboarding_completed(app: AppHandle, completed: bool) -> Result<OnboardingStatus, RpcError> {
+    let status = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        if state.settings.onboarding_completed == completed {
+            return Ok(OnboardingStatus::of(&state));
+        }
+        state.settings.onboarding_completed = completed;
+        state.save_settings();
+        OnboardingStatus::of(&state)
+    };
+    emit(&app, "app:onboarding", status).await;
+    Ok(status)
+}
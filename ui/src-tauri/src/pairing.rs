@@ -0,0 +1,132 @@
+//! Deciding on pairing requests from other devices.
+//!
+//! The sidecar holds each incoming request (`pairing_request`) until told
+//! to `accept_pairing` or `reject_pairing`. We pair with one peer at a
+//! time: a request arriving mid-pairing or with another prompt open is
+//! refused as busy straight away. Otherwise the UI is asked via
+//! `sidecar:pairing_request`, and a prompt nobody answers within
+//! `PROMPT_TIMEOUT` is declined.
+
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+
+use crate::messages::Text;
+use crate::{call_sidecar, emit_event, ConnectionState, RpcError, SharedState};
+
+/// How long the user gets to answer a pairing prompt.
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(serde::Deserialize)]
+pub struct PairingRequest {
+    pub id: String,
+    #[serde(default)]
+    pub peer_name: Option<String>,
+}
+
+/// Handle `pairing_request_expired`. Returns whether the event should be
+/// forwarded, which it is.
+pub async fn handle_expired(app: &AppHandle, request: PairingRequest) -> bool {
+    let state = app.state::<SharedState>();
+    let mut state = state.lock().await;
+    if state.pairing_prompt.as_ref() == Some(&request.id) {
+        state.pairing_prompt = None;
+    }
+    true
+}
+
+/// Handle `pairing_request`. Returns whether the event should be
+/// forwarded, which it only is when the user is to be asked.
+pub async fn handle_request(app: &AppHandle, request: PairingRequest) -> bool {
+    let state = app.state::<SharedState>().inner().clone();
+
+    let busy = {
+        let mut state = state.lock().await;
+        // Connected peers don't get in the way; another handshake does
+        let busy = state.pairing_prompt.is_some()
+            || matches!(state.connection_state, ConnectionState::Connecting | ConnectionState::Pairing);
+        if !busy {
+            state.pairing_prompt = Some(request.id.clone());
+        }
+        busy
+    };
+    if busy {
+        info!("Refusing pairing request from {:?}: busy", request.peer_name);
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move { refuse(&app, &request, "busy").await });
+        return false;
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(PROMPT_TIMEOUT).await;
+        let expired = {
+            let state = app.state::<SharedState>();
+            let mut state = state.lock().await;
+            let expired = state.pairing_prompt.as_ref() == Some(&request.id);
+            if expired {
+                state.pairing_prompt = None;
+            }
+            expired
+        };
+        if expired {
+            refuse(&app, &request, "timeout").await;
+        }
+    });
+    true
+}
+
+/// Tell the sidecar to turn the request down and the UI that it was.
+async fn refuse(app: &AppHandle, request: &PairingRequest, reason: &str) {
+    let state = app.state::<SharedState>().inner().clone();
+    let params = serde_json::json!({ "id": request.id, "reason": reason });
+    if let Err(e) = call_sidecar(&state, "reject_pairing", params).await {
+        warn!("Could not refuse pairing request {}: {}", request.id, e);
+    }
+    emit_event(app, "sidecar:pairing_request_rejected", serde_json::json!({
+        "id": request.id,
+        "peer_name": request.peer_name,
+        "reason": reason
+    }))
+    .await;
+}
+
+/// Take the prompt for `id` if it is the open one.
+async fn take_prompt(state: &SharedState, id: &str) -> Result<(), RpcError> {
+    let mut state = state.lock().await;
+    if state.pairing_prompt.as_deref() != Some(id) {
+        return Err(RpcError::InvalidInput(Text::new("input.no_pairing_request").with("id", id)));
+    }
+    state.pairing_prompt = None;
+    Ok(())
+}
+
+/// Let the device behind request `id` go on to the passphrase exchange.
+///
+/// Fails with `busy`, refusing the request, if we started connecting to
+/// another peer while the prompt was open.
+#[tauri::command]
+pub async fn accept_pairing(app: AppHandle, id: String) -> Result<(), RpcError> {
+    let state = app.state::<SharedState>().inner().clone();
+    take_prompt(&state, &id).await?;
+    let connection_state = state.lock().await.connection_state;
+    if matches!(connection_state, ConnectionState::Connecting | ConnectionState::Pairing) {
+        let params = serde_json::json!({ "id": id, "reason": "busy" });
+        if let Err(e) = call_sidecar(&state, "reject_pairing", params).await {
+            warn!("Could not refuse pairing request {}: {}", id, e);
+        }
+        return Err(RpcError::Busy);
+    }
+    call_sidecar(&state, "accept_pairing", serde_json::json!({ "id": id })).await?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn decline_pairing(app: AppHandle, id: String) -> Result<(), RpcError> {
+    let state = app.state::<SharedState>().inner().clone();
+    take_prompt(&state, &id).await?;
+    let params = serde_json::json!({ "id": id, "reason": "declined" });
+    call_sidecar(&state, "reject_pairing", params).await?;
+    Ok(())
+}
@@ -0,0 +1,294 @@
+//! The canonical list of discovered peers.
+//!
+//! The sidecar reports discovery as deltas (`peer_discovered`,
+//! `peer_lost`); they are folded into `AppState::peers` here and the
+//! frontend only ever sees whole lists, as `sidecar:peers_changed`.
+//!
+//! A scan can report dozens of changes a second, so the list is emitted
+//! at most once per `EMIT_INTERVAL`: the first change after a quiet spell
+//! goes out straight away, later ones are folded into one trailing emit.
+//!
+//! A peer that goes to sleep never sends `peer_lost`. The sidecar re-reports
+//! every peer that still answers every 20 s, and peers not heard from
+//! within `settings.peer_ttl_secs` are dropped — except the connected
+//! one, whose live connection says it's still there.
+
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::messages::Text;
+use crate::{call_sidecar, connect, emit_event, fetch_peers, ipc, Peer, RpcError, SharedState};
+
+/// How long a rescan listens for answers before reporting what it found.
+const RESCAN_WINDOW: Duration = Duration::from_secs(3);
+
+/// Shortest gap between two `sidecar:peers_changed`.
+const EMIT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Lowest `peer_ttl_secs` accepted; below this the sidecar's heartbeats
+/// couldn't keep peers alive.
+pub const MIN_TTL_SECS: u64 = 30;
+
+/// How often peers are checked for expiry.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(serde::Deserialize)]
+pub struct PeerLost {
+    pub id: String,
+}
+
+/// Apply a `peer_discovered` event. Returns whether the raw event should
+/// still be forwarded, which it never is: `sidecar:peers_changed`
+/// replaces it.
+pub async fn handle_discovered(app: &AppHandle, peer: Peer) -> bool {
+    let changed = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        state.peers_seen.insert(peer.id.clone(), Instant::now());
+        upsert(&mut state.peers, peer)
+    };
+    if changed {
+        peers_changed(app).await;
+    }
+    false
+}
+
+/// Apply a `peer_lost` event; like `handle_discovered`, never forwarded.
+pub async fn handle_lost(app: &AppHandle, PeerLost { id }: PeerLost) -> bool {
+    let removed = app.state::<SharedState>().lock().await.peers.remove(&id).is_some();
+    if removed {
+        peers_changed(app).await;
+    }
+    false
+}
+
+/// Replace the list with a fresh snapshot from the sidecar.
+///
+/// Runs as its own task since it makes an RPC; used when the sidecar
+/// becomes ready, as discovery may have found peers before it could
+/// send us events.
+pub fn spawn_refresh(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = refresh(&app).await {
+            warn!("Could not refresh peer list: {}", e);
+        }
+    });
+}
+
+async fn refresh(app: &AppHandle) -> Result<Vec<Peer>, RpcError> {
+    let state = app.state::<SharedState>().inner().clone();
+    let peers = fetch_peers(&state).await?;
+    {
+        let mut state = state.lock().await;
+        state.peers.clear();
+        let now = Instant::now();
+        for peer in peers {
+            state.peers_seen.insert(peer.id.clone(), now);
+            upsert(&mut state.peers, peer);
+        }
+    }
+    peers_changed(app).await;
+    Ok(current(&state).await)
+}
+
+/// Make the sidecar forget its discovery cache and browse again, then
+/// return whatever answered within `RESCAN_WINDOW`.
+///
+/// Calls made while a scan is running share its result rather than
+/// starting another.
+#[tauri::command]
+pub async fn rescan_peers(app: AppHandle, trace_id: Option<String>) -> Result<Vec<Peer>, RpcError> {
+    ipc::traced(trace_id, async move {
+        let state = app.state::<SharedState>().inner().clone();
+        {
+            let mut state = state.lock().await;
+            if let Some(waiters) = state.rescan_waiters.as_mut() {
+                let (tx, rx) = oneshot::channel();
+                waiters.push(tx);
+                drop(state);
+                return rx
+                    .await
+                    .map_err(|_| RpcError::Transport("rescan abandoned".to_string()));
+            }
+            state.rescan_waiters = Some(Vec::new());
+        }
+
+        emit_event(&app, "sidecar:discovery_started", serde_json::Value::Null).await;
+        let result = match call_sidecar(&state, "rescan", serde_json::json!({})).await {
+            Ok(_) => {
+                tokio::time::sleep(RESCAN_WINDOW).await;
+                refresh(&app).await
+            }
+            Err(e) => Err(e),
+        };
+
+        let waiters = state.lock().await.rescan_waiters.take().unwrap_or_default();
+        let peers = match &result {
+            Ok(peers) => peers.clone(),
+            Err(_) => current(&state).await,
+        };
+        for tx in waiters {
+            let _ = tx.send(peers.clone());
+        }
+        emit_event(&app, "sidecar:discovery_finished", serde_json::json!({
+            "count": peers.len()
+        }))
+        .await;
+        result
+    })
+    .await
+}
+
+/// Connect to a discovered peer at whatever address it has now, and wait
+/// for the handshake like `connect_to_peer`. The way the UI should
+/// connect to a peer from the list.
+///
+/// A peer missing from the list is looked for again with a fresh
+/// snapshot from the sidecar before giving up.
+#[tauri::command]
+pub async fn connect_by_id(
+    app: AppHandle,
+    peer_id: String,
+    trace_id: Option<String>,
+) -> Result<serde_json::Value, RpcError> {
+    ipc::traced(trace_id, async move {
+        let address = |peers: &std::collections::HashMap<String, Peer>| {
+            peers.get(&peer_id).map(|peer| (peer.ip.clone(), peer.port))
+        };
+        let state = app.state::<SharedState>().inner().clone();
+        let known = address(&state.lock().await.peers);
+        let (host, port) = match known {
+            Some(known) => known,
+            None => {
+                refresh(&app).await?;
+                address(&state.lock().await.peers).ok_or_else(|| {
+                    RpcError::InvalidInput(Text::new("input.peer_not_found").with("peer_id", peer_id.as_str()))
+                })?
+            }
+        };
+        connect(&app, host, port, None).await
+    })
+    .await
+}
+
+/// Start dropping peers that have gone quiet. Call once from setup.
+pub fn spawn_expiry(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<SharedState>().inner().clone();
+        let mut interval = tokio::time::interval(EXPIRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            let expired = {
+                let mut state = state.lock().await;
+                let state = &mut *state;
+                let ttl = Duration::from_secs(state.settings.peer_ttl_secs);
+                let seen = &mut state.peers_seen;
+                seen.retain(|id, _| state.peers.contains_key(id));
+                let stale: Vec<String> = state
+                    .peers
+                    .keys()
+                    .filter(|id| !state.connections.values().any(|connection| connection.peer_id.as_ref() == Some(*id)))
+                    .filter(|id| seen.get(*id).is_none_or(|at| at.elapsed() >= ttl))
+                    .cloned()
+                    .collect();
+                for id in &stale {
+                    state.peers.remove(id);
+                    seen.remove(id);
+                }
+                stale
+            };
+            if !expired.is_empty() {
+                info!("Dropping peers not seen recently: {:?}", expired);
+                peers_changed(&app).await;
+            }
+        }
+    });
+}
+
+/// Forget every peer, e.g. because the sidecar is gone.
+pub async fn clear(app: &AppHandle) {
+    let was_empty = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        std::mem::take(&mut state.peers).is_empty()
+    };
+    if !was_empty {
+        peers_changed(app).await;
+    }
+}
+
+/// Insert or update a peer, returning whether anything changed.
+///
+/// A peer advertising on several interfaces shows up once per address
+/// under the same id; the most recent usable address wins, and an update
+/// without one keeps the address we already had.
+fn upsert(peers: &mut std::collections::HashMap<String, Peer>, mut peer: Peer) -> bool {
+    if let Some(existing) = peers.get(&peer.id) {
+        if peer.ip.is_empty() || peer.port == 0 {
+            peer.ip = existing.ip.clone();
+            peer.port = existing.port;
+        }
+        if *existing == peer {
+            return false;
+        }
+    } else if peer.ip.is_empty() || peer.port == 0 {
+        warn!("Ignoring peer {} without a usable address", peer.id);
+        return false;
+    }
+    peers.insert(peer.id.clone(), peer);
+    true
+}
+
+/// The current list, in display order.
+async fn current(state: &SharedState) -> Vec<Peer> {
+    let mut peers: Vec<Peer> = state.lock().await.peers.values().cloned().collect();
+    peers.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    peers
+}
+
+/// Emit the list now, or once `EMIT_INTERVAL` has passed since the last
+/// emit; whichever it is, it carries the list as it is by then.
+async fn peers_changed(app: &AppHandle) {
+    let state = app.state::<SharedState>().inner().clone();
+    let wait = {
+        let mut state = state.lock().await;
+        if state.peers_emit_scheduled {
+            return;
+        }
+        let wait = state
+            .peers_emitted_at
+            .map(|at| EMIT_INTERVAL.saturating_sub(at.elapsed()))
+            .unwrap_or_default();
+        if wait.is_zero() {
+            state.peers_emitted_at = Some(Instant::now());
+        } else {
+            state.peers_emit_scheduled = true;
+        }
+        wait
+    };
+
+    if wait.is_zero() {
+        emit_peers(app).await;
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(wait).await;
+        {
+            let mut state = state.lock().await;
+            state.peers_emit_scheduled = false;
+            state.peers_emitted_at = Some(Instant::now());
+        }
+        emit_peers(&app).await;
+    });
+}
+
+async fn emit_peers(app: &AppHandle) {
+    let peers = current(app.state::<SharedState>().inner()).await;
+    emit_event(app, "sidecar:peers_changed", serde_json::json!(peers)).await;
+}
@@ -0,0 +1,109 @@
+//! Which peers may send us files and notifications.
+//!
+//! In `block` mode (the default) every peer is honoured except those in
+//! `blocked_peers`; in `allow` mode only those in `allowed_peers` are.
+//! Peers are identified by discovery id, so a peer we can't match to one
+//! counts as unlisted.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+
+use crate::messages::Text;
+use crate::settings::Settings;
+use crate::{call_sidecar, connections, emit_event, RpcError, SharedState};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerPolicy {
+    #[default]
+    Block,
+    Allow,
+}
+
+impl PeerPolicy {
+    pub fn parse(value: &str) -> Result<Self, RpcError> {
+        match value {
+            "block" => Ok(PeerPolicy::Block),
+            "allow" => Ok(PeerPolicy::Allow),
+            _ => Err(RpcError::InvalidInput(Text::new("input.unknown_peer_policy").with("policy", value))),
+        }
+    }
+}
+
+/// Whether the policy lets the peer with discovery id `peer_id` through.
+pub fn honours(settings: &Settings, peer_id: Option<&str>) -> bool {
+    match (settings.peer_policy, peer_id) {
+        (PeerPolicy::Block, Some(id)) => !settings.blocked_peers.contains(id),
+        (PeerPolicy::Block, None) => true,
+        (PeerPolicy::Allow, Some(id)) => settings.allowed_peers.contains(id),
+        (PeerPolicy::Allow, None) => false,
+    }
+}
+
+/// Check an incoming request against the policy before anything acts on
+/// it. Returns whether it may go ahead; refused transfers are rejected
+/// with the sidecar.
+pub async fn admit(app: &AppHandle, event: &str, data: &serde_json::Value) -> bool {
+    let (peer_id, peer_name) = {
+        let state = app.state::<SharedState>();
+        let state = state.lock().await;
+        let connection = connections::for_event(&state, data);
+        let peer_id = connection.and_then(|connection| connection.peer_id.as_deref());
+        if honours(&state.settings, peer_id) {
+            return true;
+        }
+        (peer_id.map(String::from), connection.map(|connection| connection.name.clone()))
+    };
+
+    info!("Blocked {} from {:?} ({:?})", event, peer_name, peer_id);
+    if event == "transfer_request" {
+        if let Some(id) = data.get("id").and_then(|id| id.as_str()).map(String::from) {
+            // The stdout reader that delivered this can't wait on the response
+            let state = app.state::<SharedState>().inner().clone();
+            tauri::async_runtime::spawn(async move {
+                let params = serde_json::json!({ "id": id });
+                if let Err(e) = call_sidecar(&state, "reject_transfer", params).await {
+                    warn!("Could not reject blocked transfer {}: {}", id, e);
+                }
+            });
+        }
+    }
+    emit_event(app, "sidecar:request_blocked", serde_json::json!({
+        "kind": event,
+        "peer_id": peer_id,
+        "peer_name": peer_name
+    }))
+    .await;
+    false
+}
+
+/// Stop honouring `id`, whichever mode is active.
+#[tauri::command]
+pub async fn block_peer(state: tauri::State<'_, SharedState>, id: String) -> Result<(), RpcError> {
+    let mut state = state.lock().await;
+    state.settings.allowed_peers.remove(&id);
+    state.settings.blocked_peers.insert(id);
+    state.save_settings();
+    Ok(())
+}
+
+/// Take `id` off `blocked_peers`. It is honoured again in `block` mode; in
+/// `allow` mode it still has to be in `allowed_peers`.
+#[tauri::command]
+pub async fn unblock_peer(state: tauri::State<'_, SharedState>, id: String) -> Result<(), RpcError> {
+    let mut state = state.lock().await;
+    state.settings.blocked_peers.remove(&id);
+    state.save_settings();
+    Ok(())
+}
+
+/// Switch between `"block"` and `"allow"` modes.
+#[tauri::command]
+pub async fn set_peer_policy(state: tauri::State<'_, SharedState>, mode: String) -> Result<(), RpcError> {
+    let policy = PeerPolicy::parse(&mode)?;
+    let mut state = state.lock().await;
+    state.settings.peer_policy = policy;
+    state.save_settings();
+    Ok(())
+}
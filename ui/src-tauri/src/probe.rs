@@ -0,0 +1,41 @@
+//! Checking a device is reachable before pairing with it.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::messages::Text;
+use crate::{address, call_sidecar_with_timeout, RpcError, SharedState};
+
+/// The sidecar gives the handshake 2.5s; this leaves room for the reply.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub reachable: bool,
+    /// Time to complete the WebSocket handshake, when it completed.
+    pub rtt_ms: Option<f64>,
+    /// Why the peer couldn't be reached.
+    pub error: Option<String>,
+}
+
+/// See whether a Deck-Link instance answers at `host:port`, for the
+/// manual-add-peer flow and for troubleshooting firewalls.
+///
+/// The sidecar only does the WebSocket handshake and hangs up, so no
+/// session is started on either side. An unreachable peer resolves with
+/// `reachable: false`; errors are for bad input and sidecar trouble.
+#[tauri::command]
+pub async fn probe_peer(
+    state: tauri::State<'_, SharedState>,
+    host: String,
+    port: u16,
+) -> Result<ProbeResult, RpcError> {
+    if port == 0 {
+        return Err(RpcError::InvalidInput(Text::new("input.port_zero")));
+    }
+    let host = address::normalize_host(&host)?;
+    let params = serde_json::json!({ "host": host, "port": port });
+    let result = call_sidecar_with_timeout(state.inner(), "probe", params, PROBE_TIMEOUT).await?;
+    Ok(serde_json::from_value(result)?)
+}
@@ -0,0 +1,119 @@
+//! Asking before quitting in the middle of a transfer.
+//!
+//! Quitting stops the sidecar, and with it every transfer. So when the tray's
+//! Quit or closing the window would quit while files are moving, the quit
+//! is held and `app:confirm_quit` asks the frontend; it goes ahead once
+//! `confirm_quit` says how, and can still be called off with `cancel_quit`
+//! while it waits. `confirm_quit_during_transfers` turns the question off.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::{emit_event, show_main_window, transfers, RpcError, SharedState};
+
+/// How long cancelled outgoing transfers get to tell the peer.
+const CANCEL_GRACE: Duration = Duration::from_secs(5);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Whether quitting may go ahead now; if not, the frontend has been asked.
+pub async fn allowed(app: &AppHandle) -> bool {
+    let in_flight = {
+        let state = app.state::<SharedState>();
+        let state = state.lock().await;
+        if state.pending_quit.is_some() || !state.settings.confirm_quit_during_transfers {
+            return true;
+        }
+        transfers::in_flight(&state)
+    };
+    if in_flight == 0 {
+        return true;
+    }
+    show_main_window(app);
+    emit_event(app, "app:confirm_quit", serde_json::json!({ "transfers": in_flight })).await;
+    false
+}
+
+/// The tray's Quit.
+pub fn request(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if allowed(&app).await {
+            // Exiting through the event loop runs the graceful sidecar shutdown
+            app.exit(0);
+        }
+    });
+}
+
+/// Go ahead with a quit held by `app:confirm_quit`.
+///
+/// With `force`, transfers are cancelled as `cancel_all_transfers` would,
+/// so the peer hears of it and partial downloads are deleted, before the
+/// app exits. Without it, the app exits once the transfers have finished
+/// by themselves. Either way `app:quit_pending` reports the transfers
+/// still running while it waits, and `cancel_quit` can call it off.
+#[tauri::command]
+pub async fn confirm_quit(app: AppHandle, state: tauri::State<'_, SharedState>, force: bool) -> Result<(), RpcError> {
+    let state = state.inner().clone();
+    let abandon = Arc::new(Notify::new());
+    state.lock().await.pending_quit = Some(abandon.clone());
+    tauri::async_runtime::spawn(async move {
+        let finished = if force {
+            let cancelled = transfers::abort_all(&app, &state, "transfer.cancelled", true).await;
+            info!("Quitting, cancelled {} transfers", cancelled);
+            tokio::select! {
+                drained = tokio::time::timeout(CANCEL_GRACE, drain(&app, &state)) => {
+                    if drained.is_err() {
+                        warn!("Transfers still running after cancelling; quitting anyway");
+                    }
+                    true
+                }
+                _ = abandon.notified() => false,
+            }
+        } else {
+            tokio::select! {
+                _ = drain(&app, &state) => true,
+                _ = abandon.notified() => false,
+            }
+        };
+        if !finished {
+            info!("Quit abandoned");
+            emit_event(&app, "app:quit_cancelled", serde_json::Value::Null).await;
+            return;
+        }
+        app.exit(0);
+    });
+    Ok(())
+}
+
+/// Call off a confirmed quit that is still waiting for transfers; the
+/// next quit asks again. Returns whether there was one.
+#[tauri::command]
+pub async fn cancel_quit(state: tauri::State<'_, SharedState>) -> Result<bool, RpcError> {
+    let Some(abandon) = state.lock().await.pending_quit.take() else {
+        return Ok(false);
+    };
+    abandon.notify_one();
+    Ok(true)
+}
+
+/// Wait for every transfer to end, with `app:quit_pending` each time the
+/// number still running changes.
+async fn drain(app: &AppHandle, state: &SharedState) {
+    let mut reported = None;
+    loop {
+        let in_flight = transfers::in_flight(&*state.lock().await);
+        if in_flight == 0 {
+            return;
+        }
+        if reported != Some(in_flight) {
+            reported = Some(in_flight);
+            emit_event(app, "app:quit_pending", serde_json::json!({ "transfers": in_flight })).await;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,120 @@
+//! Replacing a sidecar that still answers pings but nothing else.
+//!
+//! The health check only catches a sidecar stuck altogether. One wedged in
+//! a way that leaves pings working times out on everything else instead,
+//! so `record` counts response timeouts in a row, and once there are
+//! `TIMEOUT_THRESHOLD` of them `call` restarts the sidecar and sends the
+//! request that timed out once more, to the new process. When that
+//! answers, `sidecar:auto_recovered` says so.
+//!
+//! Only requests the sidecar answers by itself count, and `counts` lists
+//! them. Pings, requests that wait on the peer or the user, and any method
+//! added later until it is listed neither add to the count nor reset it,
+//! so a slow peer can't get the sidecar restarted; and a recovery is
+//! tried at most once per `COOLDOWN`, so neither can anything else.
+
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+
+use crate::{call_sidecar, emit_event, restart, AppState, RpcError, RpcResult, SharedState};
+
+/// Timeouts in a row after which the sidecar is restarted.
+const TIMEOUT_THRESHOLD: u32 = 3;
+
+/// Least time between two recoveries.
+const COOLDOWN: Duration = Duration::from_secs(300);
+
+/// How long the new sidecar gets to finish its ready handshake.
+const READY_TIMEOUT: Duration = Duration::from_secs(15);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Whether `method`'s outcome says anything about the sidecar itself: it
+/// is one the sidecar answers without waiting on a peer or the user.
+fn counts(method: &str) -> bool {
+    matches!(
+        method,
+        "get_status"
+            | "get_peers"
+            | "get_discovery_status"
+            | "set_device_name"
+            | "set_device_type"
+            | "set_transfer_rate_limit"
+            | "create_pairing_token"
+            | "accept_pairing"
+            | "reject_pairing"
+            | "revoke_pairing_secrets"
+            | "rescan"
+            | "cancel"
+    )
+}
+
+/// Count `result` of a request for `method` towards recovery.
+pub fn record(state: &mut AppState, method: &str, result: &RpcResult) {
+    if !counts(method) {
+        return;
+    }
+    match result {
+        Err(RpcError::Timeout) => state.rpc_timeouts += 1,
+        // Anything the sidecar itself answered
+        Ok(_) | Err(RpcError::Remote { .. } | RpcError::RateLimited(_)) => state.rpc_timeouts = 0,
+        Err(_) => {}
+    }
+}
+
+/// Whether enough timeouts have piled up to recover now. Resets the count
+/// if so, so one recovery is tried for them, not one per caller.
+fn due(state: &mut AppState) -> Option<u32> {
+    if state.rpc_timeouts < TIMEOUT_THRESHOLD || state.last_recovery.is_some_and(|at| at.elapsed() < COOLDOWN) {
+        return None;
+    }
+    state.last_recovery = Some(Instant::now());
+    Some(std::mem::take(&mut state.rpc_timeouts))
+}
+
+/// Wait for the new sidecar to be ready. Returns whether it is.
+async fn wait_until_ready(state: &SharedState) -> bool {
+    tokio::time::timeout(READY_TIMEOUT, async {
+        while !state.lock().await.sidecar.is_ready() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+/// Like `call_sidecar`, but a timeout that makes `TIMEOUT_THRESHOLD`
+/// restarts the sidecar and sends the request again. Only for requests
+/// that are safe to send twice.
+pub async fn call(app: &AppHandle, method: &str, params: serde_json::Value) -> RpcResult {
+    let state = app.state::<SharedState>().inner().clone();
+    let result = call_sidecar(&state, method, params.clone()).await;
+    if !matches!(result, Err(RpcError::Timeout)) {
+        return result;
+    }
+    let Some(timeouts) = due(&mut *state.lock().await) else {
+        return result;
+    };
+
+    warn!("{} sidecar requests in a row timed out; restarting it", timeouts);
+    if let Err(e) = restart(app).await {
+        warn!("Cannot restart unresponsive sidecar: {}", e);
+        return result;
+    }
+    if !wait_until_ready(&state).await {
+        warn!("Restarted sidecar did not become ready");
+        return result;
+    }
+    let retried = call_sidecar(&state, method, params).await;
+    if retried.is_ok() {
+        info!("Sidecar recovered after {} timeouts", timeouts);
+        emit_event(app, "sidecar:auto_recovered", serde_json::json!({
+            "method": method,
+            "timeouts": timeouts
+        }))
+        .await;
+    }
+    retried
+}
@@ -0,0 +1,74 @@
+//! Noticing the machine waking from sleep.
+//!
+//! Connections don't survive a suspend, but nothing says so until the
+//! sidecar's pings time out. There is no portable power-event notification,
+//! so sleep is spotted the way it shows up everywhere: a timer that should
+//! have fired seconds ago fires minutes late, or the wall clock has moved on
+//! far more than the monotonic one. On resume every connection is verified
+//! straight away, and the last peer is reconnected as auto-reconnect would.
+//! A sleep too short to spot is left to the ping timeouts as before.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+
+use crate::{call_sidecar_with_timeout, emit_event, spawn_auto_reconnect, SharedState};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A check this much later than due counts as a sleep.
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// How long verifying the connections may take; the sidecar gives each
+/// peer a few seconds to answer.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start watching for resume. Call once from setup.
+pub fn spawn_monitor(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut last = (Instant::now(), SystemTime::now());
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            let now = (Instant::now(), SystemTime::now());
+            let monotonic = now.0.duration_since(last.0);
+            // A wall clock set backwards isn't a sleep
+            let wall = now.1.duration_since(last.1).unwrap_or_default();
+            last = now;
+
+            let late = monotonic.max(wall).saturating_sub(CHECK_INTERVAL);
+            if late >= SLEEP_THRESHOLD {
+                resumed(&app, late).await;
+            }
+        }
+    });
+}
+
+async fn resumed(app: &AppHandle, slept: Duration) {
+    info!("Resumed after about {}s asleep", slept.as_secs());
+    emit_event(app, "sidecar:resumed", serde_json::json!({
+        "slept_ms": slept.as_millis() as u64
+    }))
+    .await;
+
+    let state = app.state::<SharedState>().inner().clone();
+    let connected = {
+        let state = state.lock().await;
+        if !state.sidecar.is_ready() {
+            // The sidecar is on its way up; readiness reconnects by itself
+            return;
+        }
+        !state.connections.is_empty()
+    };
+    if connected {
+        // Dead sessions come back as `disconnected` events, and with them
+        // the usual connection-state changes
+        let verify = serde_json::json!({});
+        if let Err(e) = call_sidecar_with_timeout(&state, "verify_connections", verify, VERIFY_TIMEOUT).await {
+            warn!("Verifying connections after resume failed: {}", e);
+            return;
+        }
+    }
+    spawn_auto_reconnect(app, false);
+}
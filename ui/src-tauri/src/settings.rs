@@ -0,0 +1,685 @@
+//! User settings persisted as JSON in the app config directory.
+//!
+//! The file records the `version` of the schema it was written with, and
+//! files from older builds are migrated forward as they are loaded. A field
+//! with a value we can't read falls back to its default on its own; a file
+//! that can't be read as settings at all is moved aside and replaced by the
+//! defaults, so a bad file never blocks startup.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tracing::{error, info, warn};
+
+use crate::device::DeviceType;
+use crate::messages::Text;
+use crate::policy::PeerPolicy;
+use crate::window::WindowGeometry;
+use crate::logs::LogLevelHandle;
+use crate::{
+    apply_log_level, clipboard_sync_changed, device, interfaces, peers, restart, RpcError, SharedState, DEFAULT_PORT,
+};
+
+/// Schema version this build writes; see `migrate`.
+pub const CURRENT_VERSION: u64 = 2;
+
+/// The peer we last connected to successfully.
+///
+/// Only non-secret metadata lives here; the pairing secret is kept in the
+/// keychain (see `keychain`), keyed by `id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastPeer {
+    /// Discovery id, if the peer was discovered rather than dialed by address.
+    pub id: Option<String>,
+    pub name: String,
+    pub ip: String,
+    pub port: u16,
+    /// Whether the peer had completed passphrase pairing.
+    #[serde(default)]
+    pub paired: bool,
+    /// Unix time (ms) the connection was established.
+    pub connected_at: u64,
+    /// Identity fingerprint the peer presented, if it sent one.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+}
+
+/// How the sidecar process is launched. Changing any of it restarts the
+/// sidecar.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SidecarSettings {
+    /// Port to listen on (`--port`), 1024–65535; `DEFAULT_PORT` when unset.
+    pub port: Option<u16>,
+    /// Debug logging in the sidecar (`--verbose`).
+    pub verbose: bool,
+    /// Passed through after our own arguments.
+    pub extra_args: Vec<String>,
+    /// Set in the sidecar's environment on top of ours.
+    pub env: BTreeMap<String, String>,
+}
+
+impl SidecarSettings {
+    /// The port the sidecar listens on.
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_PORT)
+    }
+
+    /// Arguments for `deck-link-sidecar`.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec!["run".to_string(), "--ipc".to_string()];
+        if let Some(port) = self.port {
+            args.extend(["--port".to_string(), port.to_string()]);
+        }
+        if self.verbose {
+            args.push("--verbose".to_string());
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+
+    fn validate(&self) -> Result<(), RpcError> {
+        if let Some(port) = self.port {
+            if port < 1024 {
+                return Err(RpcError::InvalidInput(Text::new("input.sidecar_port_range").with("port", port)));
+            }
+        }
+        if self.env.keys().any(|key| key.is_empty() || key.contains('=') || key.contains('\0')) {
+            return Err(RpcError::InvalidInput(Text::new("input.sidecar_env_name")));
+        }
+        Ok(())
+    }
+}
+
+/// Retrying a connection attempt that couldn't reach the peer. Failed
+/// pairing is never retried.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectRetry {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub attempts: u32,
+    /// Delay before the first retry, doubling with each one after.
+    pub initial_delay_ms: u64,
+    /// Upper bound on the delay, before jitter.
+    pub max_delay_ms: u64,
+}
+
+impl Default for ConnectRetry {
+    fn default() -> Self {
+        Self { attempts: 3, initial_delay_ms: 1000, max_delay_ms: 15_000 }
+    }
+}
+
+impl ConnectRetry {
+    /// Delay before retry `attempt` (1-based), plus up to half again as
+    /// jitter so two devices retrying each other drift apart.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self
+            .initial_delay_ms
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(self.max_delay_ms);
+        let jitter = (uuid::Uuid::new_v4().as_u128() as u64) % (base / 2 + 1);
+        Duration::from_millis(base + jitter)
+    }
+
+    fn validate(&self) -> Result<(), RpcError> {
+        if self.initial_delay_ms == 0 || self.max_delay_ms < self.initial_delay_ms {
+            return Err(RpcError::InvalidInput(Text::new("input.connect_retry_delays")));
+        }
+        Ok(())
+    }
+}
+
+/// Checking for new releases; see `updates`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateSettings {
+    /// Check automatically; `check_for_update` works either way.
+    pub enabled: bool,
+    /// Overrides the endpoint in `tauri.conf.json`.
+    pub endpoint: Option<String>,
+    pub check_interval_hours: u64,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        Self { enabled: true, endpoint: None, check_interval_hours: 24 }
+    }
+}
+
+impl UpdateSettings {
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval_hours.max(1) * 60 * 60)
+    }
+
+    fn validate(&self) -> Result<(), RpcError> {
+        if let Some(endpoint) = &self.endpoint {
+            match url::Url::parse(endpoint) {
+                Ok(url) if url.scheme() == "https" => {}
+                _ => {
+                    return Err(RpcError::InvalidInput(
+                        Text::new("input.update_endpoint_not_https").with("endpoint", endpoint.as_str()),
+                    ))
+                }
+            }
+        }
+        if self.check_interval_hours == 0 {
+            return Err(RpcError::InvalidInput(Text::new("input.update_interval")));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Schema version the settings were written with.
+    pub version: u64,
+    /// Reconnect to `last_peer` whenever the sidecar becomes ready, and
+    /// after the machine wakes from sleep.
+    pub auto_reconnect: bool,
+    /// Wait this long after launch before auto-reconnecting.
+    pub auto_reconnect_delay_ms: u64,
+    /// How long auto-reconnect at launch waits for a network; 0 doesn't
+    /// wait. A network that comes up later reconnects then instead.
+    pub network_wait_timeout_secs: u64,
+    pub connect_retry: ConnectRetry,
+    /// Disconnect after this many minutes without activity; 0 never does.
+    /// See `idle`.
+    pub idle_disconnect_minutes: u64,
+    /// Whether notifications from the peer are shown natively.
+    pub notifications_enabled: bool,
+    /// Show them even while the OS is in do-not-disturb / Focus mode.
+    pub notifications_ignore_dnd: bool,
+    /// Show notifications, the peer's and ours, as native popups; while
+    /// off they only appear in the app. See `notifications`.
+    pub native_notifications: bool,
+    /// Share the clipboard with the peer; while off, nothing is sent and
+    /// the peer's updates are dropped.
+    pub clipboard_sync_enabled: bool,
+    /// Closing the window hides it to the tray instead of quitting.
+    pub run_in_background: bool,
+    /// Ask before quitting while files are being transferred; see `quit`.
+    pub confirm_quit_during_transfers: bool,
+    /// Name shown to peers instead of the hostname.
+    pub device_name: Option<String>,
+    pub device_type: DeviceType,
+    /// Network interface discovery and connections are bound to; `None`
+    /// for all of them. See `interfaces`.
+    pub bind_interface: Option<String>,
+    /// `tracing` level filter, e.g. `"info"` or `"debug"`.
+    pub log_level: String,
+    /// Most sidecar requests allowed in flight at once; beyond it commands
+    /// fail with `overloaded` rather than queue.
+    pub max_pending_requests: usize,
+    /// Allow `raw_rpc` in release builds; debug builds always allow it.
+    pub allow_raw_rpc: bool,
+    /// Cap on transfer speed in kilobits per second; `None` is unlimited.
+    pub transfer_rate_limit_kbps: Option<u32>,
+    /// Most transfers kept in the history; older ones are pruned.
+    pub transfer_history_limit: usize,
+    /// Main window geometry from the last run.
+    pub window: Option<WindowGeometry>,
+    pub last_peer: Option<LastPeer>,
+    /// Accepted identity fingerprints, by peer id; see `trust`.
+    pub trusted_fingerprints: BTreeMap<String, String>,
+    /// Discovered peers not heard from for this long are dropped from the
+    /// list; see `peers`.
+    pub peer_ttl_secs: u64,
+    /// Whose files and notifications we accept; see `policy`.
+    pub peer_policy: PeerPolicy,
+    pub blocked_peers: BTreeSet<String>,
+    pub allowed_peers: BTreeSet<String>,
+    pub sidecar: SidecarSettings,
+    pub updates: UpdateSettings,
+    /// The user has finished (or skipped) first-run setup; see `onboarding`.
+    pub onboarding_completed: bool,
+    /// Pairing with a peer has succeeded at least once.
+    pub has_paired: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            auto_reconnect: false,
+            auto_reconnect_delay_ms: 0,
+            network_wait_timeout_secs: 30,
+            connect_retry: ConnectRetry::default(),
+            idle_disconnect_minutes: 0,
+            notifications_enabled: true,
+            notifications_ignore_dnd: false,
+            native_notifications: true,
+            clipboard_sync_enabled: true,
+            run_in_background: false,
+            confirm_quit_during_transfers: true,
+            device_name: None,
+            device_type: DeviceType::default(),
+            bind_interface: None,
+            log_level: "info".to_string(),
+            max_pending_requests: 256,
+            allow_raw_rpc: false,
+            transfer_rate_limit_kbps: None,
+            transfer_history_limit: 1000,
+            window: None,
+            last_peer: None,
+            trusted_fingerprints: BTreeMap::new(),
+            peer_ttl_secs: 60,
+            peer_policy: PeerPolicy::default(),
+            blocked_peers: BTreeSet::new(),
+            allowed_peers: BTreeSet::new(),
+            sidecar: SidecarSettings::default(),
+            updates: UpdateSettings::default(),
+            onboarding_completed: false,
+            has_paired: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Read settings from `path`, migrating them if an older build wrote
+    /// them. A missing file is created with the defaults, and a corrupt one
+    /// is backed up and reset; an unreadable one yields the defaults for
+    /// this run and is left alone. Fields with invalid values are reset to
+    /// their defaults, keeping a copy of the file as it was.
+    pub fn load(path: &Path) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::reset(path),
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        let parsed = serde_json::from_str(&contents).map_err(|e| e.to_string()).and_then(migrate);
+        match parsed {
+            Ok((value, migrated)) => {
+                let (settings, invalid) = salvage(value);
+                if !invalid.is_empty() {
+                    let backup = backup_path(path);
+                    warn!(
+                        "Settings file {} has invalid {}; using their defaults and copying it to {}",
+                        path.display(),
+                        invalid.join(", "),
+                        backup.display()
+                    );
+                    if let Err(e) = fs::copy(path, &backup) {
+                        error!("Failed to back up {}: {}", path.display(), e);
+                    }
+                }
+                if migrated {
+                    if let Err(e) = settings.save(path) {
+                        error!("Failed to save migrated settings to {}: {}", path.display(), e);
+                    }
+                }
+                settings
+            }
+            Err(e) => {
+                let backup = backup_path(path);
+                warn!("Settings file {} is corrupt ({}); moving it to {}", path.display(), e, backup.display());
+                if let Err(e) = fs::rename(path, &backup) {
+                    error!("Failed to back up {}: {}", path.display(), e);
+                }
+                Self::reset(path)
+            }
+        }
+    }
+
+    /// Write the defaults to `path` and return them.
+    fn reset(path: &Path) -> Self {
+        let settings = Self::default();
+        if let Err(e) = settings.save(path) {
+            error!("Failed to save default settings to {}: {}", path.display(), e);
+        }
+        settings
+    }
+
+    /// Write settings to `path`, going through a temporary file so a crash
+    /// mid-write can't leave a truncated file behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// Where a copy of the settings file at `path` is kept before a bad value
+/// in it is given up.
+fn backup_path(path: &Path) -> std::path::PathBuf {
+    path.with_extension(format!("json.corrupt-{}", chrono::Utc::now().timestamp()))
+}
+
+/// Read a migrated settings file, giving up only the fields whose values
+/// we can't read. Returns the settings and the names of those fields.
+fn salvage(value: serde_json::Value) -> (Settings, Vec<String>) {
+    if let Ok(settings) = serde_json::from_value(value.clone()) {
+        return (settings, Vec::new());
+    }
+    let serde_json::Value::Object(fields) = value else {
+        return (Settings::default(), Vec::new());
+    };
+    // Take the fields one at a time onto the defaults, so each is judged
+    // on its own
+    let mut kept = serde_json::to_value(Settings::default()).unwrap_or_default();
+    let mut invalid = Vec::new();
+    for (key, field) in fields {
+        let mut candidate = kept.clone();
+        candidate[key.as_str()] = field;
+        if serde_json::from_value::<Settings>(candidate.clone()).is_ok() {
+            kept = candidate;
+        } else {
+            invalid.push(key);
+        }
+    }
+    (serde_json::from_value(kept).unwrap_or_default(), invalid)
+}
+
+/// Bring a settings file from an older build up to `CURRENT_VERSION`.
+/// Returns it and whether anything changed.
+///
+/// Each step takes the file one version further. Version 0 is every file
+/// from before settings were versioned; its fields all carry over as they
+/// are. Version 2 added the onboarding flags, which for an install that
+/// has already connected somewhere are worked out from what it remembers.
+/// A file from a newer build is loaded as it is, dropping fields we don't
+/// know.
+fn migrate(mut value: serde_json::Value) -> Result<(serde_json::Value, bool), String> {
+    let Some(fields) = value.as_object_mut() else {
+        return Err("settings are not a JSON object".to_string());
+    };
+    let from = match fields.get("version") {
+        None => 0,
+        Some(version) => version.as_u64().ok_or_else(|| format!("invalid version {}", version))?,
+    };
+    if from > CURRENT_VERSION {
+        warn!("Settings are from a newer version ({}); loading what we understand", from);
+        return Ok((value, false));
+    }
+    for version in from..CURRENT_VERSION {
+        match version {
+            0 => {}
+            1 => {
+                let last_peer = fields.get("last_peer").filter(|peer| !peer.is_null());
+                let paired = last_peer.and_then(|peer| peer.get("paired")).and_then(|p| p.as_bool()) == Some(true)
+                    || fields
+                        .get("trusted_fingerprints")
+                        .and_then(|trusted| trusted.as_object())
+                        .is_some_and(|trusted| !trusted.is_empty());
+                let used = paired || last_peer.is_some();
+                fields.insert("has_paired".to_string(), paired.into());
+                fields.insert("onboarding_completed".to_string(), used.into());
+            }
+            _ => unreachable!("no migration from settings version {}", version),
+        }
+    }
+    if from < CURRENT_VERSION {
+        info!("Migrated settings from version {} to {}", from, CURRENT_VERSION);
+        fields.insert("version".to_string(), CURRENT_VERSION.into());
+    }
+    Ok((value, from < CURRENT_VERSION))
+}
+
+/// Apply `patch` to `target` as a JSON merge patch (RFC 7396): objects
+/// are merged key by key, `null` removes a key, anything else replaces it.
+fn merge_patch(target: &mut serde_json::Value, patch: serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let serde_json::Value::Object(fields) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                fields.remove(&key);
+            } else {
+                merge_patch(fields.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_settings(state: tauri::State<'_, SharedState>) -> Result<Settings, RpcError> {
+    Ok(state.lock().await.settings.clone())
+}
+
+/// Replace the settings. A change to `sidecar` restarts the sidecar so it
+/// takes effect; one to `log_level` applies straight away. What pairing has
+/// recorded (`trusted_fingerprints`, `last_peer` and `has_paired`) is kept
+/// as it is, whatever `settings` says.
+#[tauri::command]
+pub async fn set_settings(app: AppHandle, settings: Settings) -> Result<(), RpcError> {
+    apply(&app, settings).await
+}
+
+/// Change only the settings in `patch`, a JSON merge patch against the
+/// current settings: nested objects are merged, and `null` puts a field
+/// back to its default. Validated and applied like `set_settings`;
+/// resolves with the settings now in effect.
+#[tauri::command]
+pub async fn update_settings(app: AppHandle, patch: serde_json::Value) -> Result<Settings, RpcError> {
+    if !patch.is_object() {
+        return Err(RpcError::InvalidInput(Text::new("input.settings_not_object")));
+    }
+    let state = app.state::<SharedState>().inner().clone();
+    let mut value = serde_json::to_value(&state.lock().await.settings)?;
+    merge_patch(&mut value, patch);
+    let settings: Settings = serde_json::from_value(value)
+        .map_err(|e| RpcError::InvalidInput(Text::detail("input.invalid_settings", e)))?;
+    apply(&app, settings).await?;
+    let settings = state.lock().await.settings.clone();
+    Ok(settings)
+}
+
+/// Validate `settings` and make them the current ones, keeping what only
+/// pairing may change.
+async fn apply(app: &AppHandle, mut settings: Settings) -> Result<(), RpcError> {
+    // Whatever the caller sent, they are now in this build's schema
+    settings.version = CURRENT_VERSION;
+    if settings.log_level.parse::<tracing_subscriber::filter::LevelFilter>().is_err() {
+        let level = settings.log_level.as_str();
+        return Err(RpcError::InvalidInput(Text::new("input.unknown_log_level").with("level", level)));
+    }
+    if let Some(name) = &settings.device_name {
+        settings.device_name = Some(device::sanitize_name(name)?);
+    }
+    if settings.max_pending_requests == 0 {
+        return Err(RpcError::InvalidInput(Text::new("input.max_pending_requests")));
+    }
+    if settings.transfer_rate_limit_kbps == Some(0) {
+        return Err(RpcError::InvalidInput(Text::new("input.rate_limit_too_low")));
+    }
+    if settings.peer_ttl_secs < peers::MIN_TTL_SECS {
+        return Err(RpcError::InvalidInput(Text::new("input.peer_ttl_too_low").with("min_secs", peers::MIN_TTL_SECS)));
+    }
+    settings.connect_retry.validate()?;
+    settings.sidecar.validate()?;
+    settings.updates.validate()?;
+    let state = app.state::<SharedState>();
+    let (restart_needed, renamed, retyped, rebound, relevelled, clipboard_sync) = {
+        let mut state = state.lock().await;
+        settings.trusted_fingerprints = state.settings.trusted_fingerprints.clone();
+        settings.last_peer = state.settings.last_peer.clone();
+        settings.has_paired = state.settings.has_paired;
+        // One that was set and has since gone away can stay as it is
+        let rebound = state.settings.bind_interface != settings.bind_interface;
+        if let Some(name) = settings.bind_interface.as_deref().filter(|_| rebound) {
+            interfaces::validate(&state, name)?;
+        }
+        let previous = std::mem::replace(&mut state.settings, settings);
+        state.save_settings();
+        device::invalidate(&mut state);
+        (
+            previous.sidecar != state.settings.sidecar,
+            previous.device_name != state.settings.device_name,
+            previous.device_type != state.settings.device_type,
+            rebound,
+            previous.log_level != state.settings.log_level,
+            (previous.clipboard_sync_enabled != state.settings.clipboard_sync_enabled)
+                .then_some(state.settings.clipboard_sync_enabled),
+        )
+    };
+    if let Some(enabled) = clipboard_sync {
+        clipboard_sync_changed(app, enabled).await;
+    }
+    if relevelled {
+        let level = state.lock().await.settings.log_level.clone();
+        apply_log_level(&app.state::<LogLevelHandle>(), &level)?;
+    }
+    if restart_needed {
+        restart(app).await?;
+    } else {
+        if renamed {
+            device::push_identity(&state).await?;
+        }
+        if retyped {
+            device::push_device_type(&state).await?;
+        }
+        if rebound {
+            interfaces::push(&state).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A settings file in a fresh directory, removed afterwards.
+    struct File {
+        dir: std::path::PathBuf,
+        path: std::path::PathBuf,
+    }
+
+    impl File {
+        fn new(contents: serde_json::Value) -> Self {
+            let dir = std::env::temp_dir().join(format!("deck-link-test-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("settings.json");
+            fs::write(&path, contents.to_string()).unwrap();
+            File { dir, path }
+        }
+
+        fn backups(&self) -> usize {
+            fs::read_dir(&self.dir)
+                .unwrap()
+                .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().contains(".corrupt-"))
+                .count()
+        }
+    }
+
+    impl Drop for File {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn version_1_works_out_the_onboarding_flags() {
+        let peer = json!({ "name": "Deck", "ip": "192.0.2.10", "port": 52525, "paired": true, "connected_at": 1 });
+        let (value, migrated) = migrate(json!({ "version": 1, "auto_reconnect": true, "last_peer": peer })).unwrap();
+        assert!(migrated);
+        assert_eq!(value["version"], CURRENT_VERSION);
+        assert_eq!(value["has_paired"], true);
+        assert_eq!(value["onboarding_completed"], true);
+        assert_eq!(value["auto_reconnect"], true);
+
+        let (fresh, _) = migrate(json!({ "last_peer": null })).unwrap();
+        assert_eq!(fresh["has_paired"], false);
+        assert_eq!(fresh["onboarding_completed"], false);
+
+        let trusted = json!({ "version": 1, "trusted_fingerprints": { "deck": "ab:cd" } });
+        let (value, _) = migrate(trusted).unwrap();
+        assert_eq!(value["has_paired"], true);
+    }
+
+    #[test]
+    fn newer_version_is_loaded_as_it_is() {
+        let file = json!({ "version": CURRENT_VERSION + 1, "auto_reconnect": true, "from_the_future": 1 });
+        let (value, migrated) = migrate(file.clone()).unwrap();
+        assert!(!migrated);
+        assert_eq!(value, file);
+
+        let file = File::new(file);
+        let settings = Settings::load(&file.path);
+        assert!(settings.auto_reconnect);
+        assert_eq!(file.backups(), 0);
+    }
+
+    #[test]
+    fn current_version_is_left_alone() {
+        let file = json!({ "version": CURRENT_VERSION, "log_level": "debug" });
+        assert_eq!(migrate(file.clone()).unwrap(), (file, false));
+        assert!(migrate(json!({ "version": "two" })).is_err());
+        assert!(migrate(json!([])).is_err());
+    }
+
+    #[test]
+    fn merge_patch_null_removes_a_field() {
+        let mut target = json!({ "device_name": "Laptop", "log_level": "debug" });
+        merge_patch(&mut target, json!({ "device_name": null, "missing": null }));
+        assert_eq!(target, json!({ "log_level": "debug" }));
+    }
+
+    #[test]
+    fn merge_patch_merges_nested_objects() {
+        let mut target = json!({
+            "sidecar": { "port": 5000, "verbose": false, "env": { "A": "1" } },
+            "log_level": "info",
+        });
+        let patch = json!({ "sidecar": { "verbose": true, "env": { "B": "2" } }, "extra_args": ["-x"] });
+        merge_patch(&mut target, patch);
+        assert_eq!(
+            target,
+            json!({
+                "sidecar": { "port": 5000, "verbose": true, "env": { "A": "1", "B": "2" } },
+                "log_level": "info",
+                "extra_args": ["-x"],
+            })
+        );
+
+        merge_patch(&mut target, json!({ "sidecar": "replaced", "log_level": { "nested": true } }));
+        assert_eq!(target["sidecar"], "replaced");
+        assert_eq!(target["log_level"], json!({ "nested": true }));
+    }
+
+    #[test]
+    fn invalid_value_resets_only_its_field() {
+        let file = File::new(json!({
+            "version": CURRENT_VERSION,
+            "device_type": "toaster",
+            "device_name": "Workstation",
+            "auto_reconnect": true,
+            "peer_ttl_secs": "soon",
+        }));
+        let settings = Settings::load(&file.path);
+        assert_eq!(settings.device_type, DeviceType::default());
+        assert_eq!(settings.peer_ttl_secs, Settings::default().peer_ttl_secs);
+        assert_eq!(settings.device_name.as_deref(), Some("Workstation"));
+        assert!(settings.auto_reconnect);
+        assert_eq!(file.backups(), 1);
+    }
+
+    #[test]
+    fn unparseable_file_is_reset() {
+        let file = File::new(json!(null));
+        fs::write(&file.path, "{ not json").unwrap();
+        let settings = Settings::load(&file.path);
+        assert!(!settings.auto_reconnect);
+        assert_eq!(file.backups(), 1);
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&file.path).unwrap()).unwrap();
+        assert_eq!(saved["version"], CURRENT_VERSION);
+    }
+}
@@ -0,0 +1,619 @@
+//! The sidecar process as seen through its pipes: attaching a freshly
+//! spawned one, reading what it writes, calling it and stopping it.
+//!
+//! Nothing here knows about the window, the tray or the connection state,
+//! so it runs the same against the real sidecar, the mock or any child
+//! process. `AppState` holds the `Sidecar` and is its `Host`; `main.rs`
+//! turns what `read_events` reports into frontend events and restarts.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tauri::async_runtime::Receiver;
+use tauri_plugin_shell::process::CommandEvent;
+use tokio::sync::{oneshot, Mutex};
+use tracing::{debug, error, info_span, warn, Instrument};
+
+use crate::ipc::{self, LineFramer, Message, SidecarClient, Transport};
+use crate::{RpcError, RpcResult};
+
+/// A sidecar that stayed up at least this long counts as healthy, which
+/// resets the restart attempt counter.
+const HEALTHY_UPTIME: Duration = Duration::from_secs(60);
+
+/// How long the sidecar gets to exit after SIGTERM before it is killed.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(3);
+
+/// How long the `shutdown` request may take to disconnect the peer and
+/// exit cleanly before we fall back to signals.
+const SHUTDOWN_RPC_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What requests are written to: the sidecar's stdin, and the process
+/// behind it.
+pub trait Child: Transport {
+    /// `None` when there is no process of its own, like the mock.
+    fn pid(&self) -> Option<u32>;
+
+    fn kill(self) -> Result<(), String>;
+}
+
+/// Whatever holds the `Sidecar`, behind the lock the functions here take.
+pub trait Host: Send + 'static {
+    type Child: Child + Send;
+
+    fn sidecar(&mut self) -> &mut Sidecar<Self::Child>;
+
+    /// Requests that may be outstanding at once; see `SidecarClient::send`.
+    fn max_pending_requests(&self) -> usize;
+
+    /// Drop whatever belonged to the process that just exited. Called under
+    /// the same lock that records the exit.
+    fn exited(&mut self) {}
+}
+
+/// Where the current sidecar process is in its life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// No process, or one that has stopped answering: its stdout closed.
+    Stopped,
+    /// Spawned, but the ready handshake isn't done; requests are refused.
+    Starting,
+    Ready,
+}
+
+pub struct Sidecar<C> {
+    pub phase: Phase,
+    /// Requests to the running sidecar, written to its stdin.
+    pub client: SidecarClient<C>,
+    /// Bumped on every spawn so output from a previous process is ignored.
+    pub generation: u64,
+    /// Generation of the most recent process that has exited.
+    pub exited_generation: u64,
+    /// Set while we are deliberately stopping the sidecar, so its
+    /// termination isn't mistaken for a crash.
+    pub stopping: bool,
+    /// When the current sidecar process was spawned.
+    pub started_at: Option<Instant>,
+    /// Consecutive unexpected terminations since the sidecar was last healthy.
+    pub restart_attempts: u32,
+}
+
+impl<C: Child> Sidecar<C> {
+    pub fn new() -> Self {
+        Sidecar {
+            phase: Phase::Stopped,
+            client: SidecarClient::new(),
+            generation: 0,
+            exited_generation: 0,
+            stopping: false,
+            started_at: None,
+            restart_attempts: 0,
+        }
+    }
+
+    /// Take over a freshly spawned process, returning its generation.
+    pub fn attach(&mut self, child: C) -> u64 {
+        self.client.attach(child);
+        self.phase = Phase::Starting;
+        self.generation += 1;
+        self.stopping = false;
+        self.started_at = Some(Instant::now());
+        self.generation
+    }
+
+    /// Start accepting requests; the handshake has been answered.
+    pub fn mark_ready(&mut self) {
+        self.phase = Phase::Ready;
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.phase == Phase::Ready
+    }
+
+    /// Write a request to the sidecar.
+    ///
+    /// Fails with `SidecarNotRunning` until the sidecar is ready, and with
+    /// `Overloaded` once `max_pending` requests are outstanding.
+    pub fn send(
+        &mut self,
+        method: &str,
+        params: serde_json::Value,
+        max_pending: usize,
+    ) -> Result<(u64, oneshot::Receiver<RpcResult>), RpcError> {
+        if !self.is_ready() {
+            return Err(RpcError::SidecarNotRunning);
+        }
+        self.client.send(method, params, max_pending)
+    }
+
+    /// Record that the process of `generation` has exited. Unless it had
+    /// already been replaced, everything still waiting on it fails, and the
+    /// result says whether to start another one: only if the exit wasn't
+    /// asked for.
+    fn exited(&mut self, generation: u64) -> Option<bool> {
+        self.exited_generation = self.exited_generation.max(generation);
+        if self.generation != generation {
+            // A replacement is already running; this was the old process
+            return None;
+        }
+        self.phase = Phase::Stopped;
+        self.client.detach();
+        self.client.fail_all(|| RpcError::SidecarNotRunning);
+        if self.started_at.take().is_some_and(|t| t.elapsed() >= HEALTHY_UPTIME) {
+            self.restart_attempts = 0;
+        }
+        Some(!std::mem::take(&mut self.stopping))
+    }
+
+    /// The stdout of `generation` closed, whether or not a termination was
+    /// reported: nothing will answer what is still in flight, so don't
+    /// leave it to time out.
+    fn stdout_closed(&mut self, generation: u64) {
+        if self.generation == generation {
+            self.phase = Phase::Stopped;
+            self.client.fail_all(|| RpcError::SidecarNotRunning);
+        }
+    }
+}
+
+/// What `read_events` passes on from the sidecar.
+pub enum Output {
+    /// A line of stdout that wasn't a reply somebody was waiting for.
+    Message(Message),
+    /// A line of stderr, as written.
+    Stderr(Vec<u8>),
+    Error(String),
+    /// The current process exited; `restart` unless it was asked to.
+    Terminated { code: Option<i32>, restart: bool },
+}
+
+/// Read the output of sidecar `generation` until its stdout closes.
+///
+/// Replies go straight to the request that is waiting for them; the rest
+/// is handed to `output`, in the order the sidecar wrote it.
+pub async fn read_events<H, F, Fut>(
+    state: Arc<Mutex<H>>,
+    generation: u64,
+    mut rx: Receiver<CommandEvent>,
+    mut output: F,
+) where
+    H: Host,
+    F: FnMut(Output) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut framer = LineFramer::default();
+    while let Some(event) = rx.recv().await {
+        match event {
+            CommandEvent::Stdout(chunk) => {
+                for line in framer.push(&chunk) {
+                    if let Some(message) = route(&state, &line).await {
+                        output(Output::Message(message)).await;
+                    }
+                }
+            }
+            CommandEvent::Stderr(line) => output(Output::Stderr(line)).await,
+            CommandEvent::Error(error) => output(Output::Error(error)).await,
+            CommandEvent::Terminated(payload) => {
+                warn!("Sidecar terminated: {:?}", payload);
+                let restart = {
+                    let mut host = state.lock().await;
+                    let restart = host.sidecar().exited(generation);
+                    if restart.is_some() {
+                        host.exited();
+                    }
+                    restart
+                };
+                if let Some(restart) = restart {
+                    output(Output::Terminated { code: payload.code, restart }).await;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    state.lock().await.sidecar().stdout_closed(generation);
+}
+
+/// Decode one complete line of stdout and deliver it if it's a reply
+/// somebody is waiting for. Returns anything nobody asked for.
+async fn route<H: Host>(state: &Mutex<H>, line: &[u8]) -> Option<Message> {
+    let message = match ipc::decode_line(line) {
+        Ok(message) => message,
+        Err(e) => {
+            warn!("Ignoring unparseable sidecar output ({}): {}", e, String::from_utf8_lossy(line));
+            return None;
+        }
+    };
+    if tracing::enabled!(tracing::Level::DEBUG) {
+        if let Ok(mut payload) = serde_json::from_slice::<serde_json::Value>(line) {
+            ipc::redact_secrets(&mut payload);
+            debug!("<- {}", payload);
+        }
+    }
+    state.lock().await.sidecar().client.route(message)
+}
+
+/// Send a request to the sidecar and wait up to `timeout` for its response.
+///
+/// The lock is only held while writing, never across the await.
+pub async fn call<H: Host>(
+    state: &Mutex<H>,
+    method: &str,
+    params: serde_json::Value,
+    timeout: Duration,
+) -> RpcResult {
+    let (id, rx) = {
+        let mut host = state.lock().await;
+        let max_pending = host.max_pending_requests();
+        host.sidecar().send(method, params, max_pending)?
+    };
+    response(state, id, rx, timeout)
+        .instrument(info_span!("rpc", id, method))
+        .await
+}
+
+/// Wait for the reply to request `id`, forgetting the request if it
+/// doesn't come within `timeout`.
+pub async fn response<H: Host>(
+    state: &Mutex<H>,
+    id: u64,
+    rx: oneshot::Receiver<RpcResult>,
+    timeout: Duration,
+) -> RpcResult {
+    let result = ipc::wait(rx, timeout).await;
+    if let Err(RpcError::Timeout) = result {
+        state.lock().await.sidecar().client.forget(id);
+    }
+    result
+}
+
+/// Stop the sidecar process, if one is running.
+///
+/// A ready sidecar is first asked to `shutdown`, which disconnects the peer
+/// and exits; `announce` runs just before. Failing that, on Unix it gets
+/// SIGTERM so it can still say goodbye; it is only killed if it is around
+/// after the grace period.
+pub async fn stop<H: Host>(state: &Mutex<H>, announce: impl Future<Output = ()>) {
+    let (ready, generation) = {
+        let mut host = state.lock().await;
+        let sidecar = host.sidecar();
+        if !sidecar.client.is_attached() {
+            return;
+        }
+        // Set before asking, so the exit that follows isn't taken for a crash
+        sidecar.stopping = true;
+        (sidecar.is_ready(), sidecar.generation)
+    };
+
+    if ready {
+        announce.await;
+        let deadline = tokio::time::Instant::now() + SHUTDOWN_RPC_TIMEOUT;
+        match call(state, "shutdown", serde_json::json!({}), SHUTDOWN_RPC_TIMEOUT).await {
+            Ok(_) if wait_for_exit(state, generation, deadline).await => return,
+            Ok(_) => warn!("Sidecar acknowledged shutdown but did not exit"),
+            Err(e) => warn!("Sidecar did not shut down cleanly: {}", e),
+        }
+    }
+
+    let Some(child) = state.lock().await.sidecar().client.detach() else {
+        return;
+    };
+
+    #[cfg(unix)]
+    {
+        // The mock has no process to signal
+        if let Some(pid) = child.pid() {
+            // SAFETY: kill(2) with a valid signal has no memory-safety requirements
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGTERM);
+            }
+
+            let deadline = tokio::time::Instant::now() + SHUTDOWN_GRACE;
+            if wait_for_exit(state, generation, deadline).await {
+                return;
+            }
+        }
+    }
+
+    if let Err(e) = child.kill() {
+        error!("Failed to kill sidecar: {}", e);
+    }
+}
+
+/// Wait until the sidecar of `generation` has exited, or `deadline` passes.
+/// Returns whether it exited.
+async fn wait_for_exit<H: Host>(state: &Mutex<H>, generation: u64, deadline: tokio::time::Instant) -> bool {
+    // `read_events` records the exit once the process is reaped
+    while tokio::time::Instant::now() < deadline {
+        if state.lock().await.sidecar().exited_generation >= generation {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[cfg(test)]
+pub mod testing {
+    //! A `Sidecar` on its own, for tests here and in other modules.
+
+    use super::*;
+    use crate::{mock, SidecarChild};
+    use tokio::sync::mpsc;
+
+    pub struct Holder<C>(pub Sidecar<C>);
+
+    impl<C: Child + Send + 'static> Host for Holder<C> {
+        type Child = C;
+
+        fn sidecar(&mut self) -> &mut Sidecar<C> {
+            &mut self.0
+        }
+
+        fn max_pending_requests(&self) -> usize {
+            16
+        }
+    }
+
+    pub type State<C> = Arc<Mutex<Holder<C>>>;
+
+    /// Attach `child` and read its output the way `start_sidecar` does,
+    /// passing on everything `read_events` reports.
+    pub fn attach<C: Child + Send + 'static>(
+        child: C,
+        rx: Receiver<CommandEvent>,
+    ) -> (State<C>, mpsc::UnboundedReceiver<Output>) {
+        let mut sidecar = Sidecar::new();
+        let generation = sidecar.attach(child);
+        sidecar.mark_ready();
+        let state = Arc::new(Mutex::new(Holder(sidecar)));
+        let (tx, outputs) = mpsc::unbounded_channel();
+        tauri::async_runtime::spawn(read_events(state.clone(), generation, rx, move |output| {
+            let _ = tx.send(output);
+            std::future::ready(())
+        }));
+        (state, outputs)
+    }
+
+    /// The mock, ready for requests.
+    pub fn start_mock() -> (State<SidecarChild>, mpsc::UnboundedReceiver<Output>) {
+        let (rx, child) = mock::spawn();
+        attach(SidecarChild::Mock(child), rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::*;
+    use super::*;
+    use crate::{mock, rpc_timeout, SidecarChild};
+    use serde_json::json;
+    use tauri_plugin_shell::ShellExt;
+    use tokio::sync::mpsc;
+
+    /// Generous, for a loaded CI machine; nothing should come close.
+    const PATIENCE: Duration = Duration::from_secs(10);
+
+    /// Takes requests and never answers them.
+    struct Silent;
+
+    impl Transport for Silent {
+        fn write(&mut self, _buf: &[u8]) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl Child for Silent {
+        fn pid(&self) -> Option<u32> {
+            None
+        }
+
+        fn kill(self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Run a shell as the sidecar: `setup`, then a long sleep. Returns once
+    /// `setup` is done, so signals sent after can't beat it.
+    async fn start_process(setup: &str) -> (State<SidecarChild>, mpsc::UnboundedReceiver<Output>, u32) {
+        let app = tauri::test::mock_app();
+        app.handle().plugin(tauri_plugin_shell::init()).unwrap();
+        let script = format!("{} echo started >&2; exec sleep 30", setup);
+        let (rx, child) = app.shell().command("sh").args(["-c", &script]).spawn().unwrap();
+        let pid = child.pid();
+        let (state, mut outputs) = attach(SidecarChild::Process(child), rx);
+        let started = tokio::time::timeout(PATIENCE, outputs.recv()).await.expect("shell never started");
+        assert!(matches!(started, Some(Output::Stderr(_))));
+        (state, outputs, pid)
+    }
+
+    /// Whether process `pid` is gone, not even a zombie waiting to be
+    /// reaped: signalling a zombie still succeeds.
+    #[cfg(unix)]
+    fn reaped(pid: u32) -> bool {
+        // SAFETY: signal 0 only checks that the process exists
+        let alive = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+        !alive && std::io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+    }
+
+    /// The next exit reported, skipping other output.
+    async fn terminated(outputs: &mut mpsc::UnboundedReceiver<Output>) -> (Option<i32>, bool) {
+        tokio::time::timeout(PATIENCE, async {
+            loop {
+                match outputs.recv().await.expect("output ended without an exit") {
+                    Output::Terminated { code, restart } => return (code, restart),
+                    _ => continue,
+                }
+            }
+        })
+        .await
+        .expect("no exit reported")
+    }
+
+    #[test]
+    fn phase_follows_the_process() {
+        let mut sidecar = Sidecar::new();
+        assert_eq!(sidecar.phase, Phase::Stopped);
+        let generation = sidecar.attach(Silent);
+        assert_eq!(sidecar.phase, Phase::Starting);
+        assert!(matches!(sidecar.send("ping", json!({}), 16), Err(RpcError::SidecarNotRunning)));
+        sidecar.mark_ready();
+        assert!(sidecar.is_ready());
+
+        // The exit of a process that was already replaced changes nothing
+        assert_eq!(sidecar.exited(generation - 1), None);
+        assert!(sidecar.is_ready());
+        assert_eq!(sidecar.exited(generation), Some(true));
+        assert_eq!(sidecar.phase, Phase::Stopped);
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_get_their_own_replies() {
+        let (state, _outputs) = start_mock();
+        let ask = |method: &'static str| call(&state, method, json!({}), rpc_timeout(method));
+
+        let (peers, pong, status) = tokio::join!(ask("get_peers"), ask("ping"), ask("get_status"));
+        assert!(peers.unwrap().is_array());
+        assert_eq!(pong.unwrap()["pong"], true);
+        assert_eq!(status.unwrap()["state"], "disconnected");
+        assert_eq!(state.lock().await.0.client.pending_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn remote_errors_reach_the_caller() {
+        let (state, _outputs) = start_mock();
+        let result = call(&state, "no_such_method", json!({}), rpc_timeout("no_such_method")).await;
+        assert!(matches!(result, Err(RpcError::Remote { message, .. }) if message.contains("no_such_method")));
+    }
+
+    #[tokio::test]
+    async fn timed_out_request_is_forgotten_and_its_late_reply_passed_on() {
+        let (state, mut outputs) = start_mock();
+        let connect = json!({ "host": "192.0.2.10", "port": 52525 });
+        let connecting = call(&state, "connect", connect, rpc_timeout("connect")).await.unwrap();
+        assert_eq!(connecting["status"], "connecting");
+
+        // The mock takes a while to "accept" the connection, and answers
+        // nothing else meanwhile
+        let ping = call(&state, "ping", json!({}), Duration::from_millis(100)).await;
+        assert!(matches!(ping, Err(RpcError::Timeout)));
+        let ping_id = state.lock().await.0.client.last_id();
+        assert_eq!(state.lock().await.0.client.pending_count(), 0);
+
+        let late = tokio::time::timeout(PATIENCE, async {
+            loop {
+                if let Some(Output::Message(Message::Reply { id: Some(id), result, .. })) = outputs.recv().await {
+                    if id == ping_id {
+                        return result;
+                    }
+                }
+            }
+        });
+        assert_eq!(late.await.expect("late reply never came").unwrap()["pong"], true);
+    }
+
+    #[tokio::test]
+    async fn requests_wait_for_the_ready_handshake() {
+        let (rx, child) = mock::spawn();
+        let (state, _outputs) = attach(SidecarChild::Mock(child), rx);
+        state.lock().await.0.phase = Phase::Starting;
+
+        let refused = call(&state, "ping", json!({}), rpc_timeout("ping")).await;
+        assert!(matches!(refused, Err(RpcError::SidecarNotRunning)));
+    }
+
+    #[tokio::test]
+    async fn closed_stdout_fails_requests_in_flight_at_once() {
+        let (stdout, rx) = tauri::async_runtime::channel(1);
+        let (state, mut outputs) = attach(Silent, rx);
+        let status = tokio::spawn({
+            let state = state.clone();
+            async move { call(&state, "get_status", json!({}), PATIENCE).await }
+        });
+        while state.lock().await.0.client.pending_count() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let closed = Instant::now();
+        drop(stdout);
+        assert!(matches!(status.await.unwrap(), Err(RpcError::SidecarNotRunning)));
+        assert!(closed.elapsed() < Duration::from_secs(1));
+        // Without a termination there's nothing to restart
+        assert!(outputs.recv().await.is_none());
+        assert_eq!(state.lock().await.0.phase, Phase::Stopped);
+    }
+
+    #[tokio::test]
+    async fn unexpected_exit_fails_requests_in_flight_and_asks_for_a_restart() {
+        let (state, mut outputs) = start_mock();
+        // While the mock is busy "accepting" the connection, queue a ping
+        // behind `shutdown`: it exits after answering that, so the ping is
+        // still in flight when it goes
+        let connect = json!({ "host": "192.0.2.10", "port": 52525 });
+        call(&state, "connect", connect, rpc_timeout("connect")).await.unwrap();
+        let (shutdown, ping) = {
+            let mut host = state.lock().await;
+            (host.0.send("shutdown", json!({}), 16).unwrap(), host.0.send("ping", json!({}), 16).unwrap())
+        };
+        assert_eq!(ipc::wait(shutdown.1, PATIENCE).await.unwrap()["status"], "shutting_down");
+        assert!(matches!(ipc::wait(ping.1, PATIENCE).await, Err(RpcError::SidecarNotRunning)));
+
+        assert_eq!(terminated(&mut outputs).await, (Some(0), true));
+        let mut host = state.lock().await;
+        assert_eq!(host.0.phase, Phase::Stopped);
+        assert!(!host.0.client.is_attached());
+        assert!(matches!(host.0.send("ping", json!({}), 16), Err(RpcError::SidecarNotRunning)));
+    }
+
+    #[tokio::test]
+    async fn stopped_sidecar_shuts_down_without_a_restart() {
+        let (state, mut outputs) = start_mock();
+        stop(&state, async {}).await;
+
+        assert_eq!(terminated(&mut outputs).await, (Some(0), false));
+        let host = state.lock().await;
+        assert_eq!(host.0.exited_generation, host.0.generation);
+        assert!(!host.0.stopping);
+    }
+
+    #[tokio::test]
+    async fn sidecar_that_isnt_ready_is_stopped_without_asking() {
+        let (state, mut outputs) = start_mock();
+        state.lock().await.0.phase = Phase::Starting;
+        let announced = std::cell::Cell::new(false);
+        stop(&state, async { announced.set(true) }).await;
+
+        // Closing its stdin is all it takes to end the mock
+        assert!(!terminated(&mut outputs).await.1);
+        assert!(!announced.get());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sidecar_ignoring_sigterm_is_killed_after_the_grace_period() {
+        let (state, mut outputs, pid) = start_process("trap '' TERM;").await;
+        state.lock().await.0.phase = Phase::Starting;
+
+        let started = Instant::now();
+        stop(&state, async {}).await;
+        assert!(started.elapsed() >= SHUTDOWN_GRACE);
+        let (code, restart) = terminated(&mut outputs).await;
+        // Killed, so no exit code
+        assert_eq!((code, restart), (None, false));
+        assert!(reaped(pid));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sidecar_exiting_on_sigterm_is_not_killed() {
+        let (state, mut outputs, pid) = start_process("").await;
+        state.lock().await.0.phase = Phase::Starting;
+
+        let started = Instant::now();
+        stop(&state, async {}).await;
+        assert!(started.elapsed() < SHUTDOWN_GRACE);
+        assert_eq!(terminated(&mut outputs).await, (None, false));
+        assert!(reaped(pid));
+    }
+}
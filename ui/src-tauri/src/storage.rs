@@ -0,0 +1,190 @@
+//! Checking that the settings and the keychain agree about peers.
+//!
+//! They can drift apart: a pairing secret left behind for a peer nothing
+//! else remembers, a last peer whose secret the keychain has lost, or a
+//! fingerprint recorded with the last peer that isn't the pinned one.
+//! `verify_storage` reports what it finds and `repair_storage` fixes what
+//! can be fixed, backing the settings up first. Both report their way
+//! through the peers as `sidecar:storage_progress`.
+//!
+//! The keychain can't be listed, so only the secrets of peers known to the
+//! settings, connected now or discovered are checked, and a secret is
+//! orphaned when no record in the settings or connection names its peer.
+//! Nothing about a connected peer is changed by a repair. The backup is of
+//! the settings alone: secrets stay out of files, and the ones removed are
+//! those nothing would use.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tracing::info;
+
+use crate::messages::Text;
+use crate::{emit_event, keychain, unix_millis, RpcError, SharedState};
+
+/// Progress is reported every this many peers checked.
+const PROGRESS_INTERVAL: usize = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A pairing secret for a peer with no record at all: no pinned
+    /// fingerprint, no allow or block entry, not the last peer and not
+    /// connected.
+    OrphanedSecret,
+    /// The last peer paired, but there is no secret for it; only pairing
+    /// again restores one.
+    MissingSecret,
+    /// The fingerprint recorded with the last peer isn't the pinned one.
+    StaleFingerprint,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub peer_id: String,
+    /// Whether `repair_storage` can fix it.
+    pub repairable: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StorageReport {
+    pub peers_checked: usize,
+    pub issues: Vec<Issue>,
+    /// Peers whose keychain entry couldn't be read, as `"<id>: <error>"`.
+    pub unreadable: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RepairReport {
+    pub repaired: Vec<Issue>,
+    /// Left as they are: not repairable, or about a connected peer.
+    pub skipped: Vec<Issue>,
+    /// Repairs that failed, as `"<id>: <error>"`.
+    pub failed: Vec<String>,
+    /// Where the settings were backed up, if anything was repaired.
+    pub backup: Option<String>,
+}
+
+async fn progress(app: &AppHandle, checked: usize, total: usize) {
+    emit_event(app, "sidecar:storage_progress", serde_json::json!({
+        "checked": checked,
+        "total": total
+    }))
+    .await;
+}
+
+async fn verify(app: &AppHandle) -> StorageReport {
+    let (known, checked, last_peer, trusted) = {
+        let state = app.state::<SharedState>();
+        let state = state.lock().await;
+        let settings = &state.settings;
+        let known: BTreeSet<String> = settings
+            .trusted_fingerprints
+            .keys()
+            .chain(settings.last_peer.as_ref().and_then(|peer| peer.id.as_ref()))
+            .chain(state.connections.values().filter_map(|connection| connection.peer_id.as_ref()))
+            .chain(&settings.blocked_peers)
+            .chain(&settings.allowed_peers)
+            .cloned()
+            .collect();
+        let checked: BTreeSet<String> = known.iter().chain(state.peers.keys()).cloned().collect();
+        (known, checked, settings.last_peer.clone(), settings.trusted_fingerprints.clone())
+    };
+    let paired_last = last_peer.as_ref().filter(|peer| peer.paired).and_then(|peer| peer.id.clone());
+
+    let mut report = StorageReport { peers_checked: checked.len(), ..Default::default() };
+    let mut issue = |kind, peer_id: &str, repairable| {
+        report.issues.push(Issue { kind, peer_id: peer_id.to_string(), repairable });
+    };
+    let mut unreadable = Vec::new();
+    for (done, id) in checked.iter().enumerate() {
+        match keychain::has_pairing_secret(id) {
+            Ok(true) if !known.contains(id) => issue(IssueKind::OrphanedSecret, id, true),
+            Ok(false) if paired_last.as_ref() == Some(id) => issue(IssueKind::MissingSecret, id, false),
+            Ok(_) => {}
+            Err(e) => unreadable.push(format!("{}: {}", id, e)),
+        }
+        if (done + 1) % PROGRESS_INTERVAL == 0 {
+            progress(app, done + 1, checked.len()).await;
+        }
+    }
+    if let Some(last_peer) = &last_peer {
+        if let (Some(id), Some(fingerprint)) = (&last_peer.id, &last_peer.fingerprint) {
+            if trusted.get(id).is_some_and(|pinned| pinned != fingerprint) {
+                issue(IssueKind::StaleFingerprint, id, true);
+            }
+        }
+    }
+    progress(app, checked.len(), checked.len()).await;
+    report.unreadable = unreadable;
+    report
+}
+
+/// Cross-check the peers in the settings, their pinned fingerprints and
+/// their keychain secrets, changing nothing.
+#[tauri::command]
+pub async fn verify_storage(app: AppHandle) -> Result<StorageReport, RpcError> {
+    let report = verify(&app).await;
+    info!("Storage check: {} peers, {} issues", report.peers_checked, report.issues.len());
+    Ok(report)
+}
+
+/// Fix what `verify_storage` finds that can be fixed: orphaned secrets are
+/// removed, and a stale fingerprint on the last peer is replaced by the
+/// pinned one. The settings are backed up next to them first; if that
+/// fails, nothing is changed.
+#[tauri::command]
+pub async fn repair_storage(app: AppHandle) -> Result<RepairReport, RpcError> {
+    let issues = verify(&app).await.issues;
+    let state = app.state::<SharedState>().inner().clone();
+    let connected: BTreeSet<String> = {
+        let state = state.lock().await;
+        state.connections.values().filter_map(|connection| connection.peer_id.clone()).collect()
+    };
+    let (todo, skipped): (Vec<Issue>, Vec<Issue>) = issues
+        .into_iter()
+        .partition(|issue| issue.repairable && !connected.contains(&issue.peer_id));
+    let mut report = RepairReport { skipped, ..Default::default() };
+    if todo.is_empty() {
+        return Ok(report);
+    }
+
+    {
+        let state = state.lock().await;
+        if let Some(path) = &state.settings_path {
+            let backup = path.with_file_name(format!("settings.{}.bak.json", unix_millis()));
+            state
+                .settings
+                .save(&backup)
+                .map_err(|e| RpcError::Storage(Text::detail("storage.backup_failed", e)))?;
+            report.backup = Some(backup.to_string_lossy().to_string());
+        }
+    }
+
+    for issue in todo {
+        match issue.kind {
+            IssueKind::OrphanedSecret => {
+                if let Err(e) = keychain::clear_pairing_secret(&issue.peer_id) {
+                    report.failed.push(format!("{}: {}", issue.peer_id, e));
+                    continue;
+                }
+            }
+            IssueKind::StaleFingerprint => {
+                let mut state = state.lock().await;
+                let pinned = state.settings.trusted_fingerprints.get(&issue.peer_id).cloned();
+                let last_peer = state.settings.last_peer.as_mut();
+                if let Some(last_peer) = last_peer.filter(|peer| peer.id.as_ref() == Some(&issue.peer_id)) {
+                    last_peer.fingerprint = pinned;
+                }
+                state.save_settings();
+            }
+            // Not repairable, so never among these
+            IssueKind::MissingSecret => continue,
+        }
+        report.repaired.push(issue);
+    }
+    info!("Storage repair: {} repaired, {} failed", report.repaired.len(), report.failed.len());
+    Ok(report)
+}
@@ -0,0 +1,142 @@
+//! The transfers under way in either direction, for a transfer manager
+//! view.
+//!
+//! `transfers` records each transfer here as it starts, moves and ends,
+//! alongside the `sidecar:transfer_*` events it emits. A finished transfer
+//! is kept for `RETENTION` so the view can show how it ended, then dropped,
+//! which keeps the list bounded. Every change is announced as
+//! `sidecar:transfers_changed` carrying the whole list; progress alone
+//! announces it at most every `PROGRESS_INTERVAL`.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::history::Status;
+use crate::{connections, emit_event, idle, unix_millis, RpcError, SharedState};
+
+/// How long a finished transfer stays listed.
+const RETENTION: Duration = Duration::from_secs(10);
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveTransfer {
+    pub id: String,
+    pub filename: String,
+    /// `"outgoing"` or `"incoming"`.
+    pub direction: String,
+    /// Name of the peer it started with, if connected.
+    pub peer: Option<String>,
+    /// Bytes sent or received so far.
+    pub bytes: u64,
+    pub total: u64,
+    pub status: Status,
+    /// Unix time (ms) it started.
+    pub started_at: u64,
+}
+
+#[derive(Default)]
+pub struct TransferList {
+    transfers: HashMap<String, ActiveTransfer>,
+    last_announced: Option<Instant>,
+}
+
+impl TransferList {
+    /// Oldest first.
+    fn snapshot(&self) -> Vec<ActiveTransfer> {
+        let mut transfers: Vec<ActiveTransfer> = self.transfers.values().cloned().collect();
+        transfers.sort_by(|a, b| (a.started_at, &a.id).cmp(&(b.started_at, &b.id)));
+        transfers
+    }
+}
+
+async fn announce(app: &AppHandle, transfers: Vec<ActiveTransfer>) {
+    emit_event(app, "sidecar:transfers_changed", serde_json::json!({ "transfers": transfers })).await;
+}
+
+/// List a transfer that has just started.
+pub async fn start(app: &AppHandle, id: &str, filename: &str, direction: &str, total: u64) {
+    let transfers = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        let peer = connections::for_event(&state, &serde_json::Value::Null).map(|connection| connection.name.clone());
+        idle::touch(&mut state);
+        let list = &mut state.transfer_list;
+        list.transfers.insert(id.to_string(), ActiveTransfer {
+            id: id.to_string(),
+            filename: filename.to_string(),
+            direction: direction.to_string(),
+            peer,
+            bytes: 0,
+            total,
+            status: Status::InProgress,
+            started_at: unix_millis(),
+        });
+        list.last_announced = Some(Instant::now());
+        list.snapshot()
+    };
+    announce(app, transfers).await;
+}
+
+/// Record `bytes` moved so far.
+pub async fn progress(app: &AppHandle, id: &str, bytes: u64) {
+    let transfers = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        idle::touch(&mut state);
+        let list = &mut state.transfer_list;
+        let Some(transfer) = list.transfers.get_mut(id) else {
+            return;
+        };
+        transfer.bytes = bytes;
+        if list.last_announced.is_some_and(|at| at.elapsed() < PROGRESS_INTERVAL) {
+            return;
+        }
+        list.last_announced = Some(Instant::now());
+        list.snapshot()
+    };
+    announce(app, transfers).await;
+}
+
+/// Record how a transfer ended, and drop it once `RETENTION` has passed.
+pub async fn finish(app: &AppHandle, id: &str, status: Status) {
+    let transfers = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        let list = &mut state.transfer_list;
+        let Some(transfer) = list.transfers.get_mut(id) else {
+            return;
+        };
+        transfer.status = status;
+        list.last_announced = Some(Instant::now());
+        list.snapshot()
+    };
+    announce(app, transfers).await;
+
+    let (app, id) = (app.clone(), id.to_string());
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(RETENTION).await;
+        let transfers = {
+            let state = app.state::<SharedState>();
+            let mut state = state.lock().await;
+            let list = &mut state.transfer_list;
+            // Unless the id has since been reused by a transfer still going
+            if list.transfers.get(&id).is_none_or(|transfer| transfer.status == Status::InProgress) {
+                return;
+            }
+            list.transfers.remove(&id);
+            list.snapshot()
+        };
+        announce(&app, transfers).await;
+    });
+}
+
+/// Transfers in either direction, in progress or recently finished,
+/// oldest first.
+#[tauri::command]
+pub async fn list_transfers(state: tauri::State<'_, SharedState>) -> Result<Vec<ActiveTransfer>, RpcError> {
+    Ok(state.lock().await.transfer_list.snapshot())
+}
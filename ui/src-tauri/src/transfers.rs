@@ -0,0 +1,886 @@
+//! File transfers, streamed to and from the peer through the sidecar.
+//!
+//! Outgoing files are read here and sent as a `transfer_start` request,
+//! a series of `transfer_chunk` requests carrying base64 data, and a final
+//! `transfer_finish`; the sidecar holds the first chunk until the peer
+//! accepts, and fails it if the peer refuses. A failed or cancelled
+//! transfer ends with `transfer_cancel` so the peer can discard what it
+//! received. Several files sent together form a batch, streamed a few at a
+//! time. Outgoing chunks are paced to `transfer_rate_limit_kbps`; the
+//! sidecar is told the limit for what it sends us.
+//!
+//! Incoming files mirror this: the sidecar announces a `transfer_request`
+//! event, and once the user accepts it pushes `transfer_chunk` events that
+//! are written here, followed by `transfer_finish` (or `transfer_cancel`).
+
+use std::fs::{File, Metadata, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use tauri::{AppHandle, Manager};
+use tokio::io::AsyncReadExt;
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::history::{self, Status};
+use crate::messages::Text;
+use crate::{call_sidecar, emit_event, ipc, transfer_list, AppState, RpcError, SharedState};
+
+/// File bytes per `transfer_chunk` request, before base64 encoding.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Files of a batch streamed at once; more would only contend for the link.
+const BATCH_CONCURRENCY: usize = 2;
+
+/// A local file checked and ready to send.
+struct OutgoingFile {
+    path: PathBuf,
+    metadata: Metadata,
+    filename: String,
+    /// The session it goes to; `None` while only one peer is connected.
+    session_id: Option<String>,
+}
+
+/// Holds a transfer to the rate limit, which is re-read before every chunk
+/// so a change applies mid-transfer.
+struct Pacer {
+    limit_kbps: Option<u32>,
+    since: Instant,
+    bytes: u64,
+}
+
+impl Pacer {
+    fn new() -> Self {
+        Self { limit_kbps: None, since: Instant::now(), bytes: 0 }
+    }
+
+    /// Wait until `n` more bytes fit under `limit_kbps`.
+    async fn pace(&mut self, limit_kbps: Option<u32>, n: u64) {
+        // Measure a new limit from now, not from when the transfer began
+        if limit_kbps != self.limit_kbps {
+            *self = Self { limit_kbps, ..Self::new() };
+        }
+        let Some(kbps) = limit_kbps else {
+            return;
+        };
+        self.bytes += n;
+        let due = Duration::from_secs_f64(self.bytes as f64 * 8.0 / (kbps as f64 * 1000.0));
+        if let Some(wait) = due.checked_sub(self.since.elapsed()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Aggregate progress of a `send_files` batch.
+struct Batch {
+    id: String,
+    total: usize,
+    completed: AtomicUsize,
+    overall_bytes: AtomicU64,
+}
+
+impl Batch {
+    async fn emit_progress(&self, app: &AppHandle) {
+        emit_event(app, "sidecar:batch_progress", serde_json::json!({
+            "batch_id": self.id,
+            "completed": self.completed.load(Ordering::Relaxed),
+            "total": self.total,
+            "overall_bytes": self.overall_bytes.load(Ordering::Relaxed)
+        }))
+        .await;
+    }
+}
+
+/// A file the peer offered us.
+pub struct IncomingTransfer {
+    /// The session it was offered in.
+    session_id: Option<String>,
+    size: u64,
+    /// Destination, open once the user has accepted the transfer. Written
+    /// outside the state lock, hence a lock of its own.
+    file: Option<(PathBuf, Arc<std::sync::Mutex<File>>)>,
+    bytes_received: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TransferRequest {
+    id: String,
+    #[serde(default)]
+    filename: String,
+    size: u64,
+    session_id: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TransferChunk {
+    id: String,
+    offset: u64,
+    data: String,
+    session_id: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TransferRef {
+    id: String,
+}
+
+/// Send the file at `path` to the peer in session `session_id`, which may
+/// be left out while only one is connected. Returns the transfer id.
+#[tauri::command]
+pub async fn send_file(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    path: String,
+    session_id: Option<String>,
+    trace_id: Option<String>,
+) -> Result<String, RpcError> {
+    ipc::traced(trace_id, async move {
+        ensure_connected(&state, session_id.as_deref()).await?;
+        let file = open_outgoing(PathBuf::from(path), session_id).await?;
+        let id = uuid::Uuid::new_v4().to_string();
+        announce(&state, &id, &file).await?;
+        record_start(&app, &id, &file.filename, "outgoing", file.metadata.len()).await;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        state.lock().await.transfers.insert(id.clone(), cancel.clone());
+
+        let transfer_id = id.clone();
+        tauri::async_runtime::spawn(ipc::traced(ipc::trace_id(), async move {
+            let state = app.state::<SharedState>().inner().clone();
+            let result = stream_or_cancel(&app, &state, &id, &file, &cancel, None).await;
+            report(&app, &state, &id, result).await;
+        }));
+
+        Ok(transfer_id)
+    })
+    .await
+}
+
+/// Send several files to the peer in session `session_id`, as `send_file`,
+/// `BATCH_CONCURRENCY` at a time, returning their transfer ids in the
+/// order given.
+///
+/// Each file reports its own progress and outcome as `send_file` would,
+/// and one failing doesn't stop the rest. The batch as a whole reports
+/// `sidecar:batch_progress` — whose `batch_id` can be passed to
+/// `cancel_transfer` to cancel every file in it — and finally
+/// `sidecar:batch_complete` with each file's error, if any.
+#[tauri::command]
+pub async fn send_files(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    paths: Vec<String>,
+    session_id: Option<String>,
+    trace_id: Option<String>,
+) -> Result<Vec<String>, RpcError> {
+    ipc::traced(trace_id, async move {
+        if paths.is_empty() {
+            return Err(RpcError::InvalidInput(Text::new("input.no_files")));
+        }
+        ensure_connected(&state, session_id.as_deref()).await?;
+
+        let batch = Arc::new(Batch {
+            id: uuid::Uuid::new_v4().to_string(),
+            total: paths.len(),
+            completed: AtomicUsize::new(0),
+            overall_bytes: AtomicU64::new(0),
+        });
+        let queued: Vec<(String, String, Arc<AtomicBool>)> = paths
+            .into_iter()
+            .map(|path| (uuid::Uuid::new_v4().to_string(), path, Arc::new(AtomicBool::new(false))))
+            .collect();
+        let ids: Vec<String> = queued.iter().map(|(id, _, _)| id.clone()).collect();
+        {
+            // Registered up front so queued files can be cancelled too
+            let mut state = state.lock().await;
+            for (id, _, cancel) in &queued {
+                state.transfers.insert(id.clone(), cancel.clone());
+            }
+            state.batches.insert(batch.id.clone(), ids.clone());
+        }
+        batch.emit_progress(&app).await;
+
+        let slots = Arc::new(Semaphore::new(BATCH_CONCURRENCY));
+        let jobs: Vec<_> = queued
+            .into_iter()
+            .map(|(id, path, cancel)| {
+                let (app, batch, slots, session_id) = (app.clone(), batch.clone(), slots.clone(), session_id.clone());
+                tauri::async_runtime::spawn(ipc::traced(ipc::trace_id(), async move {
+                    let _slot = slots.acquire_owned().await;
+                    let state = app.state::<SharedState>().inner().clone();
+                    let result = if cancel.load(Ordering::Relaxed) {
+                        Err(Text::new("transfer.cancelled"))
+                    } else {
+                        send_queued(&app, &state, &id, &path, session_id, &cancel, &batch).await
+                    };
+                    let error = result.as_ref().err().cloned();
+                    report(&app, &state, &id, result).await;
+
+                    batch.completed.fetch_add(1, Ordering::Relaxed);
+                    batch.emit_progress(&app).await;
+                    serde_json::json!({
+                        "id": id,
+                        "path": path,
+                        "error": error.as_ref().map(Text::english),
+                        "message_code": error.as_ref().map(|error| error.code),
+                        "params": error.map(|error| error.params)
+                    })
+                }))
+            })
+            .collect();
+
+        tauri::async_runtime::spawn(ipc::traced(ipc::trace_id(), async move {
+            let mut results = Vec::with_capacity(jobs.len());
+            for job in jobs {
+                match job.await {
+                    Ok(result) => results.push(result),
+                    Err(e) => warn!("Batch {} lost a transfer: {}", batch.id, e),
+                }
+            }
+            app.state::<SharedState>().lock().await.batches.remove(&batch.id);
+            emit_event(&app, "sidecar:batch_complete", serde_json::json!({
+                "batch_id": batch.id,
+                "results": results
+            }))
+            .await;
+        }));
+
+        Ok(ids)
+    })
+    .await
+}
+
+/// Check, announce and stream one file of a batch.
+async fn send_queued(
+    app: &AppHandle,
+    state: &SharedState,
+    id: &str,
+    path: &str,
+    session_id: Option<String>,
+    cancel: &AtomicBool,
+    batch: &Batch,
+) -> Result<(), Text> {
+    let file = open_outgoing(PathBuf::from(path), session_id).await.map_err(|e| e.text())?;
+    announce(state, id, &file).await.map_err(|e| e.text())?;
+    record_start(app, id, &file.filename, "outgoing", file.metadata.len()).await;
+    stream_or_cancel(app, state, id, &file, cancel, Some(batch)).await
+}
+
+/// Fail unless session `session_id` is connected, or with `None` any at all.
+async fn ensure_connected(state: &SharedState, session_id: Option<&str>) -> Result<(), RpcError> {
+    let state = state.lock().await;
+    let connected = match session_id {
+        Some(session_id) => state.connections.contains_key(session_id),
+        None => !state.connections.is_empty(),
+    };
+    if connected {
+        Ok(())
+    } else {
+        Err(RpcError::NotConnected)
+    }
+}
+
+async fn open_outgoing(path: PathBuf, session_id: Option<String>) -> Result<OutgoingFile, RpcError> {
+    let metadata = tokio::fs::metadata(&path)
+        .await
+        .map_err(|e| {
+            RpcError::InvalidInput(Text::detail("input.file_unreadable", e).with("path", path.display().to_string()))
+        })?;
+    if !metadata.is_file() {
+        return Err(RpcError::InvalidInput(Text::new("input.not_a_file").with("path", path.display().to_string())));
+    }
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(OutgoingFile { path, metadata, filename, session_id })
+}
+
+/// Tell the peer a transfer is coming.
+async fn announce(state: &SharedState, id: &str, file: &OutgoingFile) -> Result<(), RpcError> {
+    call_sidecar(state, "transfer_start", serde_json::json!({
+        "id": id,
+        "filename": file.filename,
+        "size": file.metadata.len(),
+        "session_id": file.session_id
+    }))
+    .await?;
+    Ok(())
+}
+
+/// Stream an announced transfer, telling the peer to discard it on failure.
+async fn stream_or_cancel(
+    app: &AppHandle,
+    state: &SharedState,
+    id: &str,
+    file: &OutgoingFile,
+    cancel: &AtomicBool,
+    batch: Option<&Batch>,
+) -> Result<(), Text> {
+    let result = stream_file(app, state, id, file, cancel, batch).await;
+    if result.is_err() {
+        let cancel = serde_json::json!({ "id": id, "session_id": file.session_id });
+        let _ = call_sidecar(state, "transfer_cancel", cancel).await;
+    }
+    result
+}
+
+/// Forget a finished outgoing transfer and report how it went.
+async fn report(app: &AppHandle, state: &SharedState, id: &str, result: Result<(), Text>) {
+    state.lock().await.transfers.remove(id);
+    let status = match &result {
+        Ok(()) => Status::Completed,
+        Err(error) => Status::from_error(error),
+    };
+    record_end(app, id, status).await;
+
+    match result {
+        Ok(()) => {
+            emit_event(app, "sidecar:transfer_complete", serde_json::json!({
+                "id": id,
+                "direction": "outgoing"
+            }))
+            .await;
+        }
+        Err(error) => {
+            warn!("Transfer {} failed: {}", id, error);
+            emit_error(app, id, "outgoing", &error).await;
+        }
+    }
+}
+
+/// Cap transfer speed in both directions at `kbps` kilobits per second, or
+/// lift the cap with `None`. Transfers already running slow down or speed
+/// up from their next chunk.
+#[tauri::command]
+pub async fn set_transfer_rate_limit(
+    state: tauri::State<'_, SharedState>,
+    kbps: Option<u32>,
+) -> Result<(), RpcError> {
+    if kbps == Some(0) {
+        return Err(RpcError::InvalidInput(Text::new("input.rate_limit_too_low")));
+    }
+    {
+        let mut state = state.lock().await;
+        state.settings.transfer_rate_limit_kbps = kbps;
+        state.save_settings();
+    }
+    // Only paces what the sidecar sends us; our own sending is paced here
+    if let Err(e) = call_sidecar(&state, "set_transfer_rate_limit", serde_json::json!({ "kbps": kbps })).await {
+        warn!("Sidecar did not apply the transfer rate limit: {}", e);
+    }
+    Ok(())
+}
+
+/// Cancel an outgoing transfer, or every file of a `send_files` batch.
+#[tauri::command]
+pub async fn cancel_transfer(state: tauri::State<'_, SharedState>, id: String) -> Result<(), RpcError> {
+    let state = state.lock().await;
+    if let Some(ids) = state.batches.get(&id) {
+        for cancel in ids.iter().filter_map(|id| state.transfers.get(id)) {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        return Ok(());
+    }
+    match state.transfers.get(&id) {
+        // The streaming task notices before its next chunk and cleans up
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        None => Err(RpcError::InvalidInput(Text::new("input.no_active_transfer").with("id", id))),
+    }
+}
+
+/// Send the file in chunks, emitting progress after each one.
+async fn stream_file(
+    app: &AppHandle,
+    state: &SharedState,
+    id: &str,
+    outgoing: &OutgoingFile,
+    cancel: &AtomicBool,
+    batch: Option<&Batch>,
+) -> Result<(), Text> {
+    let (path, expected) = (&outgoing.path, &outgoing.metadata);
+    let total = expected.len();
+    let mut file = tokio::fs::File::open(path).await.map_err(|e| Text::detail("transfer.failed", e))?;
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut bytes_sent = 0;
+    let mut pacer = Pacer::new();
+    let started = Instant::now();
+
+    while bytes_sent < total {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(Text::new("transfer.cancelled"));
+        }
+        ensure_unchanged(path, expected).await?;
+
+        let n = file.read(&mut buf).await.map_err(|e| Text::detail("transfer.failed", e))?;
+        if n == 0 {
+            return Err(Text::new("transfer.truncated"));
+        }
+
+        let limit_kbps = state.lock().await.settings.transfer_rate_limit_kbps;
+        pacer.pace(limit_kbps, n as u64).await;
+
+        let data = base64::engine::general_purpose::STANDARD.encode(&buf[..n]);
+        call_sidecar(state, "transfer_chunk", serde_json::json!({
+            "id": id,
+            "offset": bytes_sent,
+            "data": data,
+            "session_id": outgoing.session_id
+        }))
+        .await
+        .map_err(|e| e.text())?;
+
+        bytes_sent += n as u64;
+        transfer_list::progress(app, id, bytes_sent).await;
+        // Bits per millisecond is kilobits per second
+        let elapsed_ms = started.elapsed().as_millis().max(1) as u64;
+        emit_event(app, "sidecar:transfer_progress", serde_json::json!({
+            "id": id,
+            "direction": "outgoing",
+            "bytes_sent": bytes_sent,
+            "total": total,
+            "throughput_kbps": bytes_sent * 8 / elapsed_ms,
+            "rate_limit_kbps": limit_kbps
+        }))
+        .await;
+        if let Some(batch) = batch {
+            batch.overall_bytes.fetch_add(n as u64, Ordering::Relaxed);
+            batch.emit_progress(app).await;
+        }
+    }
+
+    ensure_unchanged(path, expected).await?;
+    call_sidecar(state, "transfer_finish", serde_json::json!({ "id": id, "session_id": outgoing.session_id }))
+        .await
+        .map_err(|e| e.text())?;
+    Ok(())
+}
+
+/// Fail if the file was deleted or modified since the transfer started.
+async fn ensure_unchanged(path: &Path, expected: &Metadata) -> Result<(), Text> {
+    let current = tokio::fs::metadata(path)
+        .await
+        .map_err(|_| Text::new("transfer.deleted"))?;
+
+    let modified = current.modified().ok() != expected.modified().ok();
+    if current.len() != expected.len() || modified {
+        return Err(Text::new("transfer.modified"));
+    }
+    Ok(())
+}
+
+/// Resolve `save_path`, refusing anything outside the downloads directory.
+fn validate_save_path(app: &AppHandle, save_path: &str) -> Result<PathBuf, RpcError> {
+    let downloads = app
+        .path()
+        .download_dir()
+        .map_err(|e| RpcError::InvalidInput(Text::detail("input.save_path_no_downloads", e)))?;
+    resolve_save_path(&downloads, save_path)
+}
+
+/// Resolve `save_path`, refusing anything outside `downloads`, symlinks
+/// included.
+fn resolve_save_path(downloads: &Path, save_path: &str) -> Result<PathBuf, RpcError> {
+    let invalid = |code: &'static str| RpcError::InvalidInput(Text::new(code));
+
+    let path = Path::new(save_path);
+    if path.components().any(|c| matches!(c, Component::ParentDir)) {
+        return Err(invalid("input.save_path_parent_dir"));
+    }
+    let file_name = path.file_name().ok_or_else(|| invalid("input.save_path_no_file_name"))?;
+
+    let allowed = downloads
+        .canonicalize()
+        .map_err(|e| RpcError::InvalidInput(Text::detail("input.save_path_no_downloads", e)))?;
+    let parent = path
+        .parent()
+        .and_then(|parent| parent.canonicalize().ok())
+        .ok_or_else(|| invalid("input.save_path_no_directory"))?;
+    if !parent.starts_with(&allowed) {
+        let dir = allowed.display().to_string();
+        return Err(RpcError::InvalidInput(Text::new("input.save_path_outside_downloads").with("dir", dir)));
+    }
+
+    Ok(parent.join(file_name))
+}
+
+#[tauri::command]
+pub async fn accept_transfer(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    id: String,
+    save_path: String,
+    trace_id: Option<String>,
+) -> Result<(), RpcError> {
+    ipc::traced(trace_id, async move {
+        let path = validate_save_path(&app, &save_path)?;
+
+        let session_id = {
+            let mut state = state.lock().await;
+            let transfer = state
+                .incoming_transfers
+                .get_mut(&id)
+                .filter(|transfer| transfer.file.is_none())
+                .ok_or_else(|| RpcError::InvalidInput(Text::new("input.no_pending_transfer").with("id", id.as_str())))?;
+
+            let file = create_destination(&path)?;
+            transfer.file = Some((path, Arc::new(std::sync::Mutex::new(file))));
+            transfer.session_id.clone()
+        };
+
+        let params = serde_json::json!({ "id": id, "session_id": session_id });
+        if let Err(e) = call_sidecar(&state, "accept_transfer", params).await {
+            discard_incoming(&state, &id).await;
+            record_end(&app, &id, Status::Failed).await;
+            return Err(e);
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Create the file an accepted transfer is written to. Never overwrites an
+/// existing one.
+fn create_destination(path: &Path) -> Result<File, RpcError> {
+    OpenOptions::new().write(true).create_new(true).open(path).map_err(|e| {
+        let text = Text::detail("input.file_uncreatable", e).with("path", path.display().to_string());
+        RpcError::InvalidInput(text)
+    })
+}
+
+#[tauri::command]
+pub async fn reject_transfer(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    id: String,
+    trace_id: Option<String>,
+) -> Result<(), RpcError> {
+    ipc::traced(trace_id, async move {
+        let session_id = discard_incoming(&state, &id).await;
+        record_end(&app, &id, Status::Rejected).await;
+        call_sidecar(&state, "reject_transfer", serde_json::json!({ "id": id, "session_id": session_id })).await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Forget an incoming transfer, deleting whatever was written so far.
+/// Returns the session it was offered in.
+async fn discard_incoming(state: &SharedState, id: &str) -> Option<String> {
+    let transfer = state.lock().await.incoming_transfers.remove(id)?;
+    if let Some((path, file)) = transfer.file {
+        drop(file);
+        let _ = std::fs::remove_file(path);
+    }
+    transfer.session_id
+}
+
+/// Transfers moving data: outgoing ones, and incoming ones the user has
+/// accepted.
+pub fn in_flight(state: &AppState) -> usize {
+    let receiving = state.incoming_transfers.values().filter(|transfer| transfer.file.is_some()).count();
+    state.transfers.len() + receiving
+}
+
+async fn emit_error(app: &AppHandle, id: &str, direction: &str, error: &Text) {
+    emit_event(app, "sidecar:transfer_error", serde_json::json!({
+        "id": id,
+        "direction": direction,
+        "error": error.english(),
+        "message_code": error.code,
+        "params": error.params
+    }))
+    .await;
+}
+
+/// Record a transfer starting, in the history and the transfer list.
+async fn record_start(app: &AppHandle, id: &str, filename: &str, direction: &str, size: u64) {
+    history::start(app, id, filename, direction, size).await;
+    transfer_list::start(app, id, filename, direction, size).await;
+}
+
+async fn record_end(app: &AppHandle, id: &str, status: Status) {
+    history::finish(app, id, status);
+    transfer_list::finish(app, id, status).await;
+}
+
+/// Cancel every transfer in either direction, returning how many there were.
+///
+/// Outgoing transfers stop at their next chunk, as with `cancel_transfer`;
+/// incoming ones, pending or being received, are discarded and the peer
+/// told to stop sending.
+#[tauri::command]
+pub async fn cancel_all_transfers(app: AppHandle, state: tauri::State<'_, SharedState>) -> Result<usize, RpcError> {
+    Ok(abort_all(&app, state.inner(), "transfer.cancelled", true).await)
+}
+
+/// Abort every transfer in either direction because the peer is gone.
+///
+/// Outgoing transfers stop at their next chunk and report their own error;
+/// incoming ones are discarded here.
+pub async fn cancel_all(app: &AppHandle, state: &SharedState) {
+    abort_all(app, state, "transfer.disconnected", false).await;
+}
+
+/// Abort every transfer with the error `code`, telling the peer if
+/// `tell_peer`; returns how many.
+pub async fn abort_all(app: &AppHandle, state: &SharedState, code: &'static str, tell_peer: bool) -> usize {
+    let error = Text::new(code);
+    let (outgoing, incoming) = {
+        let state = state.lock().await;
+        for cancel in state.transfers.values() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        let incoming: Vec<String> = state.incoming_transfers.keys().cloned().collect();
+        (state.transfers.len(), incoming)
+    };
+
+    for id in &incoming {
+        let session_id = discard_incoming(state, id).await;
+        if tell_peer {
+            let cancel = serde_json::json!({ "id": id, "session_id": session_id });
+            let _ = call_sidecar(state, "transfer_cancel", cancel).await;
+        }
+        record_end(app, id, Status::from_error(&error)).await;
+        emit_error(app, id, "incoming", &error).await;
+    }
+    outgoing + incoming.len()
+}
+
+/// Discard the incoming transfers offered in session `session_id`, whose
+/// peer is gone. Its outgoing ones fail at their next chunk.
+pub async fn cancel_session(app: &AppHandle, state: &SharedState, session_id: &str) {
+    let error = Text::new("transfer.disconnected");
+    let ids: Vec<String> = state
+        .lock()
+        .await
+        .incoming_transfers
+        .iter()
+        .filter(|(_, transfer)| transfer.session_id.as_deref() == Some(session_id))
+        .map(|(id, _)| id.clone())
+        .collect();
+    for id in &ids {
+        discard_incoming(state, id).await;
+        record_end(app, id, Status::from_error(&error)).await;
+        emit_error(app, id, "incoming", &error).await;
+    }
+}
+
+// The handlers below for the sidecar's incoming-transfer events return
+// whether the raw event should still be forwarded to the frontend; chunks
+// are replaced by `sidecar:transfer_progress`, and the end of a transfer by
+// `sidecar:transfer_complete` or `sidecar:transfer_error`.
+
+/// A peer offering us a file, forwarded so the user can accept it.
+///
+/// An offer reusing the id of one we already have is dropped: replacing
+/// it would orphan a file being written, and the chunks that follow
+/// couldn't tell the two apart.
+pub async fn handle_request(app: &AppHandle, request: TransferRequest) -> bool {
+    {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        if state.incoming_transfers.contains_key(&request.id) {
+            warn!("Ignoring a second offer of transfer {}", request.id);
+            return false;
+        }
+        state.incoming_transfers.insert(
+            request.id.clone(),
+            IncomingTransfer { session_id: request.session_id, size: request.size, file: None, bytes_received: 0 },
+        );
+    }
+    record_start(app, &request.id, &request.filename, "incoming", request.size).await;
+    true
+}
+
+pub async fn handle_chunk(app: &AppHandle, chunk: TransferChunk) -> bool {
+    let state = app.state::<SharedState>().inner().clone();
+    match write_chunk(&state, &chunk).await {
+        Ok((bytes_received, total)) => {
+            transfer_list::progress(app, &chunk.id, bytes_received).await;
+            let limit_kbps = state.lock().await.settings.transfer_rate_limit_kbps;
+            emit_event(app, "sidecar:transfer_progress", serde_json::json!({
+                "id": chunk.id,
+                "direction": "incoming",
+                "bytes_received": bytes_received,
+                "total": total,
+                "rate_limit_kbps": limit_kbps
+            }))
+            .await;
+        }
+        Err(error) => {
+            discard_incoming(&state, &chunk.id).await;
+            record_end(app, &chunk.id, Status::Failed).await;
+            // The stdout reader that delivered this can't wait on the response
+            let cancel = serde_json::json!({ "id": chunk.id, "session_id": chunk.session_id });
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = call_sidecar(&state, "transfer_cancel", cancel).await {
+                    warn!("Could not cancel failed transfer: {}", e);
+                }
+            });
+            emit_error(app, &chunk.id, "incoming", &error).await;
+        }
+    }
+    false
+}
+
+/// `transfer_finish` when `finished`, otherwise `transfer_cancel`.
+pub async fn handle_end(app: &AppHandle, TransferRef { id }: TransferRef, finished: bool) -> bool {
+    let state = app.state::<SharedState>().inner().clone();
+    let transfer = state.lock().await.incoming_transfers.remove(&id);
+    let complete = finished
+        && transfer.as_ref().is_some_and(|t| t.file.is_some() && t.bytes_received == t.size);
+    if complete {
+        record_end(app, &id, Status::Completed).await;
+        emit_event(app, "sidecar:transfer_complete", serde_json::json!({
+            "id": id,
+            "direction": "incoming"
+        }))
+        .await;
+    } else {
+        if let Some((path, file)) = transfer.and_then(|t| t.file) {
+            drop(file);
+            let _ = std::fs::remove_file(path);
+        }
+        let error = Text::new(if finished { "transfer.incomplete" } else { "transfer.cancelled_by_peer" });
+        record_end(app, &id, Status::from_error(&error)).await;
+        emit_error(app, &id, "incoming", &error).await;
+    }
+    false
+}
+
+/// Append a chunk to its accepted transfer; returns (bytes received, total).
+///
+/// The chunk is counted under the state lock but written after it is
+/// released, on a blocking thread, so a slow disk holds up only this
+/// transfer. Chunks still land in order: the stdout reader hands them over
+/// one at a time.
+async fn write_chunk(state: &SharedState, chunk: &TransferChunk) -> Result<(u64, u64), Text> {
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(&chunk.data)
+        .map_err(|e| Text::detail("transfer.invalid_chunk", e))?;
+
+    let (file, received) = {
+        let mut state = state.lock().await;
+        let transfer = state
+            .incoming_transfers
+            .get_mut(&chunk.id)
+            .ok_or_else(|| Text::new("transfer.unknown"))?;
+        let Some((_, file)) = &transfer.file else {
+            return Err(Text::new("transfer.not_accepted"));
+        };
+        let file = file.clone();
+
+        if chunk.offset != transfer.bytes_received {
+            return Err(Text::new("transfer.out_of_order"));
+        }
+        if transfer.bytes_received + data.len() as u64 > transfer.size {
+            return Err(Text::new("transfer.oversized"));
+        }
+        // A failed write discards the transfer, so this can be counted now
+        transfer.bytes_received += data.len() as u64;
+        (file, (transfer.bytes_received, transfer.size))
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        file.lock().unwrap_or_else(|e| e.into_inner()).write_all(&data)
+    })
+    .await
+    .map_err(|e| Text::detail("transfer.failed", e))?
+    .map_err(|e| Text::detail("transfer.failed", e))?;
+    Ok(received)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory standing in for the downloads directory, with a
+    /// sibling outside it.
+    struct Dirs {
+        root: PathBuf,
+        downloads: PathBuf,
+        outside: PathBuf,
+    }
+
+    impl Dirs {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("deck-link-test-{}", uuid::Uuid::new_v4()));
+            let downloads = root.join("Downloads");
+            let outside = root.join("elsewhere");
+            std::fs::create_dir_all(&downloads).unwrap();
+            std::fs::create_dir_all(&outside).unwrap();
+            Dirs { root, downloads, outside }
+        }
+
+        fn in_downloads(&self, name: &str) -> String {
+            self.downloads.join(name).to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for Dirs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn refusal(result: Result<PathBuf, RpcError>) -> &'static str {
+        match result {
+            Err(RpcError::InvalidInput(text)) => text.code,
+            other => panic!("accepted: {:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+
+    #[test]
+    fn save_path_in_downloads_is_accepted() {
+        let dirs = Dirs::new();
+        let path = resolve_save_path(&dirs.downloads, &dirs.in_downloads("photo.jpg")).unwrap();
+        assert_eq!(path, dirs.downloads.canonicalize().unwrap().join("photo.jpg"));
+    }
+
+    #[test]
+    fn save_path_with_parent_components_is_refused() {
+        let dirs = Dirs::new();
+        let escape = dirs.in_downloads("../elsewhere/photo.jpg");
+        assert_eq!(refusal(resolve_save_path(&dirs.downloads, &escape)), "input.save_path_parent_dir");
+        // Even one that would end up back inside
+        let detour = dirs.in_downloads("../Downloads/photo.jpg");
+        assert_eq!(refusal(resolve_save_path(&dirs.downloads, &detour)), "input.save_path_parent_dir");
+    }
+
+    #[test]
+    fn absolute_save_path_outside_downloads_is_refused() {
+        let dirs = Dirs::new();
+        let outside = dirs.outside.join("photo.jpg").to_string_lossy().to_string();
+        assert_eq!(refusal(resolve_save_path(&dirs.downloads, &outside)), "input.save_path_outside_downloads");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_path_through_a_symlink_out_of_downloads_is_refused() {
+        let dirs = Dirs::new();
+        std::os::unix::fs::symlink(&dirs.outside, dirs.downloads.join("link")).unwrap();
+        let linked = dirs.in_downloads("link/photo.jpg");
+        assert_eq!(refusal(resolve_save_path(&dirs.downloads, &linked)), "input.save_path_outside_downloads");
+    }
+
+    #[test]
+    fn existing_file_is_never_overwritten() {
+        let dirs = Dirs::new();
+        let path = resolve_save_path(&dirs.downloads, &dirs.in_downloads("photo.jpg")).unwrap();
+        std::fs::write(&path, b"already here").unwrap();
+
+        let Err(RpcError::InvalidInput(text)) = create_destination(&path) else {
+            panic!("existing file reopened");
+        };
+        assert_eq!(text.code, "input.file_uncreatable");
+        assert_eq!(std::fs::read(&path).unwrap(), b"already here");
+    }
+}
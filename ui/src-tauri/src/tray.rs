@@ -0,0 +1,131 @@
+//! System tray icon mirroring the cached connection state.
+
+use tauri::image::Image;
+use tauri::menu::{Menu, MenuEvent, MenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager, Wry};
+
+use crate::{disconnect, quit, set_clipboard_sync, show_main_window, ConnectionState, SharedState};
+
+const TRAY_ID: &str = "main";
+
+/// Menu items whose text follows app state.
+struct TrayItems {
+    clipboard_sync: MenuItem<Wry>,
+}
+
+/// Create the tray icon. Call once from setup.
+pub fn create(app: &AppHandle) -> tauri::Result<()> {
+    let clipboard_sync_enabled = {
+        let state = app.state::<SharedState>();
+        let state = tauri::async_runtime::block_on(state.lock());
+        state.settings.clipboard_sync_enabled
+    };
+
+    let show = MenuItem::with_id(app, "show", "Show Window", true, None::<&str>)?;
+    let clipboard_sync = MenuItem::with_id(
+        app,
+        "clipboard_sync",
+        clipboard_sync_label(clipboard_sync_enabled),
+        true,
+        None::<&str>,
+    )?;
+    let disconnect = MenuItem::with_id(app, "disconnect", "Disconnect", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&show, &clipboard_sync, &disconnect, &quit])?;
+    app.manage(TrayItems { clipboard_sync });
+
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .menu(&menu)
+        .tooltip(tooltip(ConnectionState::Disconnected, None))
+        .show_menu_on_left_click(false)
+        .on_menu_event(on_menu_event)
+        .on_tray_icon_event(|tray, event| {
+            // Left click brings back a window hidden by run-in-background
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_main_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = icon(app, ConnectionState::Disconnected) {
+        tray = tray.icon(icon);
+    }
+    tray.build(app)?;
+    Ok(())
+}
+
+/// Refresh the icon and tooltip after a connection state change.
+pub fn update(app: &AppHandle, connection_state: ConnectionState, peer_name: Option<&str>) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let _ = tray.set_icon(icon(app, connection_state));
+    let _ = tray.set_tooltip(Some(tooltip(connection_state, peer_name)));
+}
+
+/// Show whether clipboard sync is on.
+pub fn show_clipboard_sync(app: &AppHandle, enabled: bool) {
+    if let Some(items) = app.try_state::<TrayItems>() {
+        let _ = items.clipboard_sync.set_text(clipboard_sync_label(enabled));
+    }
+}
+
+fn clipboard_sync_label(enabled: bool) -> &'static str {
+    if enabled {
+        "Clipboard sync: on"
+    } else {
+        "Clipboard sync: off"
+    }
+}
+
+fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+    match event.id.as_ref() {
+        "show" => show_main_window(app),
+        "clipboard_sync" => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move { set_clipboard_sync(&app, None).await });
+        }
+        "disconnect" => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move { disconnect(&app, None, false).await });
+        }
+        "quit" => quit::request(app),
+        _ => {}
+    }
+}
+
+fn tooltip(connection_state: ConnectionState, peer_name: Option<&str>) -> String {
+    match (connection_state, peer_name) {
+        (ConnectionState::Connected, Some(peer_name)) => format!("Deck Link: connected to {}", peer_name),
+        (ConnectionState::Connected, None) => "Deck Link: connected".to_string(),
+        (ConnectionState::Connecting | ConnectionState::Pairing, _) => "Deck Link: connecting…".to_string(),
+        (ConnectionState::Disconnected | ConnectionState::Discovering, _) => {
+            "Deck Link: not connected".to_string()
+        }
+    }
+}
+
+/// The app icon, recoloured for the state: full colour when connected,
+/// amber while connecting, greyed out otherwise.
+fn icon(app: &AppHandle, connection_state: ConnectionState) -> Option<Image<'static>> {
+    let base = app.default_window_icon()?;
+    let mut rgba = base.rgba().to_vec();
+
+    for pixel in rgba.chunks_exact_mut(4) {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(u32::from);
+        let luma = (r * 299 + g * 587 + b * 114) / 1000;
+        let [r, g, b] = match connection_state {
+            ConnectionState::Connected => [r, g, b],
+            ConnectionState::Connecting | ConnectionState::Pairing => {
+                [(luma + 255) / 2, (luma + 176) / 2, luma / 2]
+            }
+            ConnectionState::Disconnected | ConnectionState::Discovering => [luma, luma, luma],
+        };
+        pixel[..3].copy_from_slice(&[r as u8, g as u8, b as u8]);
+    }
+    Some(Image::new_owned(rgba, base.width(), base.height()))
+}
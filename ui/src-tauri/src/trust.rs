@@ -0,0 +1,79 @@
+//! Pinning peers' identity fingerprints.
+//!
+//! The sidecar reports the fingerprint of the key a peer proved it holds
+//! (`peer_fingerprint` on `connected`). The first one seen for a peer id
+//! is trusted; a different one later raises `sidecar:fingerprint_changed`
+//! until the user accepts it with `trust_fingerprint`.
+
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+
+use crate::messages::Text;
+use crate::{emit_event, RpcError, SharedState};
+
+/// SHA-256, in hex.
+const FINGERPRINT_LEN: usize = 64;
+
+/// Hex digits per group in `display`.
+const GROUP_LEN: usize = 4;
+
+/// A fingerprint as the user compares it: uppercase, in groups of four.
+pub fn display(fingerprint: &str) -> String {
+    fingerprint
+        .to_ascii_uppercase()
+        .as_bytes()
+        .chunks(GROUP_LEN)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn validate(fingerprint: &str) -> Result<String, RpcError> {
+    let fingerprint: String = fingerprint.chars().filter(|c| !c.is_whitespace()).collect();
+    if fingerprint.len() != FINGERPRINT_LEN || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(RpcError::InvalidInput(Text::new("input.not_a_fingerprint")));
+    }
+    Ok(fingerprint.to_ascii_lowercase())
+}
+
+/// Compare a connected peer's fingerprint with the one on record.
+pub async fn check(app: &AppHandle, peer_id: &str, fingerprint: &str) {
+    let previous = {
+        let state = app.state::<SharedState>();
+        let mut state = state.lock().await;
+        let trusted = &mut state.settings.trusted_fingerprints;
+        match trusted.get(peer_id) {
+            Some(known) if known == fingerprint => return,
+            Some(known) => known.clone(),
+            None => {
+                trusted.insert(peer_id.to_string(), fingerprint.to_string());
+                state.save_settings();
+                return;
+            }
+        }
+    };
+
+    warn!("Fingerprint of {} changed; possible impersonation", peer_id);
+    emit_event(app, "sidecar:fingerprint_changed", serde_json::json!({
+        "peer_id": peer_id,
+        "previous": previous,
+        "previous_display": display(&previous),
+        "fingerprint": fingerprint,
+        "display": display(fingerprint)
+    }))
+    .await;
+}
+
+/// Accept `fingerprint` as `peer_id`'s identity from now on.
+#[tauri::command]
+pub async fn trust_fingerprint(
+    state: tauri::State<'_, SharedState>,
+    peer_id: String,
+    fingerprint: String,
+) -> Result<(), RpcError> {
+    let fingerprint = validate(&fingerprint)?;
+    let mut state = state.lock().await;
+    state.settings.trusted_fingerprints.insert(peer_id, fingerprint);
+    state.save_settings();
+    Ok(())
+}
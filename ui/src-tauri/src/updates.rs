@@ -0,0 +1,124 @@
+//! In-app updates through `tauri-plugin-updater`.
+//!
+//! Updates are looked for on startup and every
+//! `updates.check_interval_hours`, and announced as `app:update_available`;
+//! nothing is installed until the user confirms with `install_update`.
+
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager};
+use tauri_plugin_updater::{Update, UpdaterExt};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::messages::Text;
+use crate::{emit_event, stop_sidecar, RpcError, SharedState};
+
+/// How long after startup the first check runs, leaving the sidecar and
+/// the network time to settle.
+const STARTUP_DELAY: Duration = Duration::from_secs(10);
+
+/// What `check_for_update` found.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UpdateInfo {
+    pub version: String,
+    pub current_version: String,
+    /// Release notes, if the release has any.
+    pub notes: Option<String>,
+}
+
+/// Start the periodic check. Call once from setup.
+pub fn spawn_checker(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(STARTUP_DELAY).await;
+        loop {
+            let (enabled, interval) = {
+                let state = app.state::<SharedState>();
+                let state = state.lock().await;
+                let updates = &state.settings.updates;
+                (updates.enabled, updates.check_interval())
+            };
+            if enabled {
+                if let Err(e) = check(&app).await {
+                    warn!("Update check failed: {}", e);
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+/// Look for an update now, regardless of `updates.enabled`.
+#[tauri::command]
+pub async fn check_for_update(app: AppHandle) -> Result<Option<UpdateInfo>, RpcError> {
+    check(&app).await
+}
+
+/// Download the available update, stop the sidecar, install and relaunch.
+///
+/// The sidecar goes down before the installer runs, since on some
+/// platforms installing exits this process straight away and would
+/// leave the old sidecar running.
+#[tauri::command]
+pub async fn install_update(app: AppHandle) -> Result<(), RpcError> {
+    let update = find(&app)
+        .await?
+        .ok_or_else(|| RpcError::InvalidInput(Text::new("input.no_update")))?;
+
+    info!("Downloading update {}", update.version);
+    let mut downloaded = 0;
+    let progress = app.clone();
+    let bytes = update
+        .download(
+            move |chunk, total| {
+                downloaded += chunk as u64;
+                let app = progress.clone();
+                tauri::async_runtime::spawn(async move {
+                    emit_event(&app, "app:update_progress", serde_json::json!({
+                        "downloaded": downloaded,
+                        "total": total
+                    }))
+                    .await;
+                });
+            },
+            || {},
+        )
+        .await
+        .map_err(|e| RpcError::Transport(format!("update download failed: {}", e)))?;
+
+    stop_sidecar(&app).await;
+    info!("Installing update {}", update.version);
+    update
+        .install(bytes)
+        .map_err(|e| RpcError::Transport(format!("update install failed: {}", e)))?;
+    app.restart();
+}
+
+async fn check(app: &AppHandle) -> Result<Option<UpdateInfo>, RpcError> {
+    let Some(update) = find(app).await? else {
+        return Ok(None);
+    };
+    let info = UpdateInfo {
+        version: update.version.clone(),
+        current_version: update.current_version.clone(),
+        notes: update.body.clone(),
+    };
+    info!("Update {} available", info.version);
+    emit_event(app, "app:update_available", serde_json::to_value(&info)?).await;
+    Ok(Some(info))
+}
+
+/// Ask the endpoint from settings, or the one in `tauri.conf.json`.
+async fn find(app: &AppHandle) -> Result<Option<Update>, RpcError> {
+    let endpoint = app.state::<SharedState>().lock().await.settings.updates.endpoint.clone();
+    let failed = |e: tauri_plugin_updater::Error| RpcError::Transport(format!("update check failed: {}", e));
+
+    let mut builder = app.updater_builder();
+    if let Some(endpoint) = endpoint {
+        let url = Url::parse(&endpoint)
+            .map_err(|e| RpcError::InvalidInput(Text::detail("input.invalid_update_endpoint", e)))?;
+        builder = builder.endpoints(vec![url]).map_err(failed)?;
+    }
+    builder.build().map_err(failed)?.check().await.map_err(failed)
+}
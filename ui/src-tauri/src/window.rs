@@ -0,0 +1,125 @@
+//! Remembering the main window's size and position between runs.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{
+    AppHandle, LogicalSize, Manager, PhysicalPosition, PhysicalSize, WebviewWindow, Window,
+    WindowEvent,
+};
+
+use crate::{RpcError, SharedState};
+
+/// Moves and resizes arrive in bursts; persist once they settle.
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// Matches the window size in `tauri.conf.json`.
+const DEFAULT_SIZE: LogicalSize<f64> = LogicalSize::new(480.0, 640.0);
+
+/// Outer position and inner size, in physical pixels.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Apply the saved geometry to the main window. Call once from setup.
+///
+/// The saved rectangle is clamped to the monitor it overlaps most; if it
+/// overlaps none (say, an external display that's gone), the window keeps
+/// its default centred placement.
+pub fn restore(app: &AppHandle) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    let saved = {
+        let state = app.state::<SharedState>();
+        let state = tauri::async_runtime::block_on(state.lock());
+        state.settings.window
+    };
+    let Some(saved) = saved else {
+        return;
+    };
+
+    let monitors = window.available_monitors().unwrap_or_default();
+    let best = monitors
+        .iter()
+        .map(|monitor| {
+            let (pos, size) = (monitor.position(), monitor.size());
+            let overlap = overlap(saved.x, saved.width, pos.x, size.width)
+                * overlap(saved.y, saved.height, pos.y, size.height);
+            (monitor, overlap)
+        })
+        .filter(|(_, overlap)| *overlap > 0)
+        .max_by_key(|(_, overlap)| *overlap);
+    let Some((monitor, _)) = best else {
+        return;
+    };
+
+    let (pos, size) = (monitor.position(), monitor.size());
+    let width = saved.width.min(size.width);
+    let height = saved.height.min(size.height);
+    let x = saved.x.clamp(pos.x, pos.x + (size.width - width) as i32);
+    let y = saved.y.clamp(pos.y, pos.y + (size.height - height) as i32);
+    let _ = window.set_size(PhysicalSize::new(width, height));
+    let _ = window.set_position(PhysicalPosition::new(x, y));
+}
+
+/// Length of the intersection of two spans along one axis.
+fn overlap(a: i32, a_len: u32, b: i32, b_len: u32) -> i64 {
+    let end = (a as i64 + a_len as i64).min(b as i64 + b_len as i64);
+    (end - (a as i64).max(b as i64)).max(0)
+}
+
+/// Record the window's geometry after a move or resize.
+pub fn track(window: &Window, event: &WindowEvent) {
+    if !matches!(event, WindowEvent::Moved(_) | WindowEvent::Resized(_)) {
+        return;
+    }
+    // Minimized windows report bogus positions (-32000 on Windows)
+    if window.is_minimized().unwrap_or(false) || window.is_maximized().unwrap_or(false) {
+        return;
+    }
+    let (Ok(position), Ok(size)) = (window.outer_position(), window.inner_size()) else {
+        return;
+    };
+
+    let state = window.state::<SharedState>().inner().clone();
+    tauri::async_runtime::spawn(async move {
+        {
+            let mut state = state.lock().await;
+            state.settings.window = Some(WindowGeometry {
+                x: position.x,
+                y: position.y,
+                width: size.width,
+                height: size.height,
+            });
+            if std::mem::replace(&mut state.window_save_scheduled, true) {
+                return;
+            }
+        }
+        tokio::time::sleep(SAVE_DELAY).await;
+        let mut state = state.lock().await;
+        state.window_save_scheduled = false;
+        state.save_settings();
+    });
+}
+
+/// Forget the saved geometry and put the window back at its default
+/// size, centred — for when it has ended up somewhere unreachable.
+#[tauri::command]
+pub async fn reset_window_position(
+    window: WebviewWindow,
+    state: tauri::State<'_, SharedState>,
+) -> Result<(), RpcError> {
+    {
+        let mut state = state.lock().await;
+        state.settings.window = None;
+        state.save_settings();
+    }
+    let _ = window.set_size(DEFAULT_SIZE);
+    let _ = window.center();
+    Ok(())
+}
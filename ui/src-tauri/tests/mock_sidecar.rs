@@ -0,0 +1,182 @@
+//! The sidecar protocol end to end, against the mock run as a process of
+//! its own (`PATCH_MOCK=stdio`): requests written to its stdin, replies and
+//! events read back from its stdout, one JSON object per line.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+/// Long enough for any canned answer, even on a loaded CI machine.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The mock, as the app would spawn the sidecar.
+struct Sidecar {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    /// Every line it writes, parsed.
+    lines: mpsc::Receiver<Value>,
+    last_id: u64,
+}
+
+impl Sidecar {
+    fn spawn() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_deck-link"))
+            .env("PATCH_MOCK", "stdio")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("cannot spawn the mock sidecar");
+        let stdout = child.stdout.take().unwrap();
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let message = serde_json::from_str(&line).unwrap_or_else(|e| panic!("bad line {:?}: {}", line, e));
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Sidecar { stdin: child.stdin.take(), child, lines, last_id: 0 }
+    }
+
+    /// Write a request, returning its id.
+    fn send(&mut self, method: &str, params: Value) -> u64 {
+        self.last_id += 1;
+        let mut line = json!({ "id": self.last_id, "method": method, "params": params }).to_string();
+        line.push('\n');
+        let stdin = self.stdin.as_mut().expect("stdin closed");
+        stdin.write_all(line.as_bytes()).unwrap();
+        stdin.flush().unwrap();
+        self.last_id
+    }
+
+    /// The next line matching `wanted` within `timeout`, skipping others.
+    fn next(&self, timeout: Duration, wanted: impl Fn(&Value) -> bool) -> Option<Value> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.checked_duration_since(Instant::now())?;
+            match self.lines.recv_timeout(left) {
+                Ok(message) if wanted(&message) => return Some(message),
+                Ok(_) => {}
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// The reply to request `id`, if it comes within `timeout`.
+    fn reply(&self, id: u64, timeout: Duration) -> Option<Value> {
+        self.next(timeout, |message| message["id"] == id && message.get("event").is_none())
+    }
+
+    /// Send a request and wait for its reply.
+    fn call(&mut self, method: &str, params: Value) -> Value {
+        let id = self.send(method, params);
+        self.reply(id, REPLY_TIMEOUT).unwrap_or_else(|| panic!("no reply to {}", method))
+    }
+
+    /// Wait for the process to exit.
+    fn exit(&mut self, timeout: Duration) -> Option<ExitStatus> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return Some(status);
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        None
+    }
+}
+
+impl Drop for Sidecar {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn requests_get_their_replies() {
+    let mut sidecar = Sidecar::spawn();
+
+    let pong = sidecar.call("ping", json!({}));
+    assert_eq!(pong["result"]["pong"], true);
+    assert!(pong["result"]["protocol_version"].is_string());
+
+    let status = sidecar.call("get_status", json!({}));
+    assert_eq!(status["result"]["state"], "disconnected");
+
+    let unknown = sidecar.call("no_such_method", json!({}));
+    assert_eq!(unknown["error"], "Unknown method: no_such_method");
+    assert!(unknown.get("result").is_none());
+
+    let refused = sidecar.call("set_clipboard", json!({ "text": "hi" }));
+    assert_eq!(refused["error"], "Not connected");
+}
+
+#[test]
+fn replies_keep_ids_of_pipelined_requests() {
+    let mut sidecar = Sidecar::spawn();
+    let ids: Vec<u64> = (0..5).map(|_| sidecar.send("get_peers", json!({}))).collect();
+    for id in ids {
+        let reply = sidecar.reply(id, REPLY_TIMEOUT).unwrap_or_else(|| panic!("no reply to {}", id));
+        assert!(reply["result"].is_array());
+    }
+}
+
+#[test]
+fn slow_reply_times_out_and_still_arrives() {
+    let mut sidecar = Sidecar::spawn();
+    let connect = sidecar.call("connect", json!({ "host": "192.0.2.10", "port": 52525 }));
+    assert_eq!(connect["result"]["status"], "connecting");
+
+    // The mock takes a while to "accept" the connection, and answers
+    // nothing else meanwhile
+    let ping = sidecar.send("ping", json!({}));
+    assert!(sidecar.reply(ping, Duration::from_millis(100)).is_none());
+    let late = sidecar.reply(ping, REPLY_TIMEOUT).expect("late reply never came");
+    assert_eq!(late["result"]["pong"], true);
+
+    let status = sidecar.call("get_status", json!({}));
+    assert_eq!(status["result"]["state"], "connected");
+    assert_eq!(status["result"]["peer"]["name"], "Steam Deck (mock)");
+}
+
+#[test]
+fn events_follow_the_reply_that_caused_them() {
+    let mut sidecar = Sidecar::spawn();
+    sidecar.call("connect", json!({ "host": "192.0.2.10", "port": 52525 }));
+    let connected = sidecar.next(REPLY_TIMEOUT, |message| message["event"] == "connected").unwrap();
+    assert_eq!(connected["data"]["session_id"], "mock-session");
+
+    sidecar.call("disconnect", json!({}));
+    let disconnected = sidecar.next(REPLY_TIMEOUT, |message| message["event"] == "disconnected").unwrap();
+    assert_eq!(disconnected["data"]["session_id"], "mock-session");
+}
+
+#[test]
+fn shutdown_exits_and_a_restart_starts_afresh() {
+    let mut sidecar = Sidecar::spawn();
+    sidecar.call("connect", json!({ "host": "192.0.2.10", "port": 52525 }));
+    assert_eq!(sidecar.call("shutdown", json!({}))["result"]["status"], "shutting_down");
+    let status = sidecar.exit(REPLY_TIMEOUT).expect("mock did not exit");
+    assert!(status.success());
+
+    let mut restarted = Sidecar::spawn();
+    assert_eq!(restarted.call("ping", json!({}))["result"]["pong"], true);
+    assert_eq!(restarted.call("get_status", json!({}))["result"]["state"], "disconnected");
+}
+
+#[test]
+fn closing_stdin_ends_the_process() {
+    let mut sidecar = Sidecar::spawn();
+    sidecar.call("ping", json!({}));
+    sidecar.stdin = None;
+    let status = sidecar.exit(REPLY_TIMEOUT).expect("mock outlived its stdin");
+    assert!(status.success());
+}
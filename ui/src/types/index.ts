@@ -13,11 +13,35 @@ export interface Peer {
   device_type: 'laptop' | 'deck';
 }
 
+export interface LocalAddress {
+  ip: string;
+  family: 4 | 6;
+  primary: boolean;
+  link_local: boolean;
+  interface?: string | null;
+}
+
+/** An entry of `list_interfaces`. */
+export interface NetworkInterface {
+  name: string;
+  addresses: LocalAddress[];
+  active: boolean;
+}
+
 export interface LocalInfo {
   name: string;
   type: string;
   ip: string;
+  addresses?: LocalAddress[];
+  port: number;
+}
+
+export interface Connection {
+  session_id: string;
+  name: string;
+  ip: string;
   port: number;
+  fingerprint: string | null;
 }
 
 export interface Status {
@@ -25,6 +49,8 @@ export interface Status {
   device_name: string;
   device_type: string;
   port: number;
+  connections: Connection[];
+  // The most recent connection
   peer: Peer | null;
   session_id: string | null;
   local_info?: LocalInfo;
@@ -40,3 +66,43 @@ export interface PassphraseRequiredData {
   session_id: string;
   peer_name: string;
 }
+
+/** `code` of a `remote` command error from a failed connection. */
+export enum ConnectErrorCode {
+  Unknown = 0,
+  PeerOffline = 1001,
+  Refused = 1002,
+  Timeout = 1003,
+  WrongPassphrase = 1004,
+  IncompatibleVersion = 1005,
+  TlsError = 1006,
+}
+
+/**
+ * User-facing text as a code to look up in the frontend's translations and
+ * the params to fill into it. Alongside any English `message`, `error` or
+ * `reason` field, which stays for logs.
+ */
+export interface LocalizedText {
+  message_code: string;
+  params: Record<string, string | number>;
+}
+
+export interface CommandError extends LocalizedText {
+  kind: string;
+  /** English, for logs. */
+  message: string;
+  code?: number;
+}
+
+/** An entry of `list_transfers` and `sidecar:transfers_changed`. */
+export interface ActiveTransfer {
+  id: string;
+  filename: string;
+  direction: 'outgoing' | 'incoming';
+  peer: string | null;
+  bytes: number;
+  total: number;
+  status: 'in_progress' | 'completed' | 'failed' | 'cancelled' | 'rejected';
+  started_at: number;
+}